@@ -0,0 +1,81 @@
+use crate::{
+    ray::Ray,
+    units::{point::Point, vec3::Vec3},
+};
+
+/// Axis aligned bounding box.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Aabb {
+    minimum: Point,
+    maximum: Point,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point, maximum: Point) -> Self {
+        Self { minimum, maximum }
+    }
+
+    pub fn min(&self) -> Point {
+        self.minimum
+    }
+
+    pub fn max(&self) -> Point {
+        self.maximum
+    }
+
+    /// Smallest box holding both boxes.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut minimum = self.minimum;
+        let mut maximum = self.maximum;
+        for a in 0..3 {
+            minimum[a] = minimum[a].min(other.minimum[a]);
+            maximum[a] = maximum[a].max(other.maximum[a]);
+        }
+        Aabb::new(minimum, maximum)
+    }
+
+    /// The box grown by `margin` on every side, so flat boxes can be hit.
+    pub fn padded(&self, margin: f64) -> Aabb {
+        let margin = Vec3::new(margin, margin, margin);
+        Aabb::new(self.minimum - margin, self.maximum + margin)
+    }
+
+    pub fn centroid(&self) -> Point {
+        (self.minimum + self.maximum) * 0.5
+    }
+
+    pub fn diagonal(&self) -> Vec3 {
+        self.maximum - self.minimum
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.diagonal();
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    /// Returns the parametric interval where the ray is inside the box,
+    /// clipped to `[t_min, t_max]`.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for a in 0..3 {
+            let inv_d = 1.0 / ray.direction()[a];
+            let mut t0 = (self.minimum[a] - ray.origin()[a]) * inv_d;
+            let mut t1 = (self.maximum[a] - ray.origin()[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN happens for rays parallel to a slab with the origin on its plane.
+            if !t0.is_nan() {
+                t_min = t_min.max(t0);
+            }
+            if !t1.is_nan() {
+                t_max = t_max.min(t1);
+            }
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
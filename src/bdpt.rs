@@ -0,0 +1,825 @@
+use crate::{
+    camera::Camera,
+    film::Film,
+    light::LightSample,
+    ray::{HitRecord, Ray},
+    sampling::power_heuristic,
+    scene::Scene,
+    units::{
+        color::Color,
+        point::Point,
+        vec3::{dot_product, random_f64, unit_vector, Vec3},
+    },
+};
+
+/// Bidirectional path tracer: every camera sample traces a path from the
+/// camera and one from a light, and connects each vertex of one to each
+/// vertex of the other, weighting the strategies with the balance heuristic.
+/// Connections from light paths to the camera land anywhere on the image and
+/// are splatted onto a `Film`, they make caustics converge.
+///
+/// Lights at infinity are only sampled from the camera path and found by
+/// escaping rays, like `Integrator::Mis` does. Media only absorb along the
+/// path, they don't scatter, and point lights shine past their range.
+#[derive(Copy, Clone, Debug)]
+pub struct Bdpt {
+    max_depth: usize,
+}
+
+impl Bdpt {
+    /// Paths bounce at most `max_depth` times between the camera and the
+    /// light.
+    pub fn new(max_depth: usize) -> Self {
+        Self { max_depth }
+    }
+
+    /// Radiance arriving through the image point `(s, t)`, in the
+    /// coordinates `Camera::get_ray` takes. Light reaching other pixels is
+    /// splatted onto `film`.
+    pub fn sample(&self, scene: &Scene, camera: &Camera, s: f64, t: f64, film: &Film) -> Color {
+        let context = Context { scene, camera };
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        let ray = camera.get_ray(s, t);
+        let mut camera_path = vec![Vertex {
+            kind: Kind::Camera,
+            record: HitRecord::new(ray.origin(), camera.forward(), 0.0),
+            ray,
+            beta: white,
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        }];
+        let escape = context.walk(
+            ray,
+            white,
+            camera.pdf(&ray).1,
+            &mut camera_path,
+            self.max_depth + 2,
+        );
+        let mut radiance = self.infinite_lights(&context, &camera_path, escape);
+
+        let mut light_path = vec![];
+        if let Some((index, pmf, emission)) = scene.lights.sample_emission(
+            random_f64(),
+            (random_f64(), random_f64()),
+            (random_f64(), random_f64()),
+        ) {
+            let pdf_position = emission.pdf_position * pmf;
+            let light = Vertex {
+                kind: Kind::Light {
+                    index,
+                    delta_position: emission.delta_position,
+                },
+                record: emission.record.clone(),
+                ray: Ray::new(emission.record.point, emission.direction),
+                beta: white / pdf_position,
+                pdf_fwd: pdf_position,
+                pdf_rev: 0.0,
+                delta: false,
+            };
+            let ray = light.ray;
+            let beta =
+                light.f(&context, &emission.direction) / (pdf_position * emission.pdf_direction);
+            light_path.push(light);
+            if emission.pdf_direction > 0.0 {
+                context.walk(
+                    ray,
+                    beta,
+                    emission.pdf_direction,
+                    &mut light_path,
+                    self.max_depth + 1,
+                );
+            }
+        }
+
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = (s + t) as isize - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.max_depth as isize {
+                    continue;
+                }
+                if t == 1 {
+                    if let Some((raster, splat)) = context.splat(&light_path, s) {
+                        film.add_splat(raster, splat);
+                    }
+                } else {
+                    radiance += context.connect(&light_path, &camera_path, s, t);
+                }
+            }
+        }
+        radiance
+    }
+
+    /// Light from lights at infinity and the background, sampled at every
+    /// vertex of the camera path and found by the ray escaping at its end.
+    fn infinite_lights(
+        &self,
+        context: &Context,
+        camera_path: &[Vertex],
+        escape: Option<Escape>,
+    ) -> Color {
+        let scene = context.scene;
+        let mut radiance = Color::default();
+        for vertex in camera_path.iter().take(self.max_depth + 1).skip(1) {
+            if vertex.connectible() {
+                radiance += vertex.beta * context.sample_infinite(vertex);
+            }
+        }
+        if let Some(escape) = escape {
+            let r = &escape.ray;
+            radiance += escape.beta
+                * match (camera_path.len(), &scene.backplate, escape.pdf) {
+                    (1, Some(backplate), _) => backplate.radiance(&r.direction()),
+                    (_, _, None) => scene.background.radiance(r) + scene.lights.escaped(r),
+                    (_, _, Some(bsdf_pdf)) => {
+                        let light_pdf = scene.lights.pdf_infinite(&r.origin(), &r.direction());
+                        scene.background.radiance(r)
+                            + scene.lights.escaped(r) * power_heuristic(bsdf_pdf, light_pdf)
+                    }
+                };
+        }
+        radiance
+    }
+}
+
+/// Where a path vertex lies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Camera,
+    /// Start of a light path, on the light with the given index.
+    Light {
+        index: usize,
+        delta_position: bool,
+    },
+    Surface,
+}
+
+/// Vertex of a camera or light path. Densities are per unit area of the
+/// vertex: `pdf_fwd` of the path that built it reaching it, `pdf_rev` of a
+/// path from the other end doing so.
+#[derive(Clone)]
+struct Vertex {
+    kind: Kind,
+    record: HitRecord,
+    /// The ray the path arrived along, or the first one leaving the camera
+    /// or the light.
+    ray: Ray,
+    /// Throughput of the path up to the vertex, over its density.
+    beta: Color,
+    pdf_fwd: f64,
+    pdf_rev: f64,
+    /// The path left the vertex through a specular lobe.
+    delta: bool,
+}
+
+impl Vertex {
+    fn on_surface(&self) -> bool {
+        match self.kind {
+            Kind::Camera => false,
+            Kind::Light { delta_position, .. } => !delta_position,
+            Kind::Surface => true,
+        }
+    }
+
+    /// Whether other vertices can connect to this one. Purely specular
+    /// surfaces only scatter into directions they sample.
+    fn connectible(&self) -> bool {
+        match self.kind {
+            Kind::Surface => self.record.material.lobes().has_non_specular(),
+            _ => true,
+        }
+    }
+
+    fn is_delta_light(&self) -> bool {
+        matches!(
+            self.kind,
+            Kind::Light {
+                delta_position: true,
+                ..
+            }
+        )
+    }
+
+    /// Turns the solid angle density `pdf` of leaving this vertex towards
+    /// `next` into a density per unit area at `next`.
+    fn convert_density(&self, pdf: f64, next: &Vertex) -> f64 {
+        let w = next.record.point - self.record.point;
+        let distance_squared = w.length_squared();
+        if distance_squared == 0.0 {
+            return 0.0;
+        }
+        let mut pdf = pdf / distance_squared;
+        if next.on_surface() {
+            pdf *= dot_product(&next.record.normal, &w).abs() / distance_squared.sqrt();
+        }
+        pdf
+    }
+
+    /// Light scattered or emitted towards `direction`, times the cosine at
+    /// the vertex.
+    fn f(&self, context: &Context, direction: &Vec3) -> Color {
+        let direction = unit_vector(*direction);
+        match self.kind {
+            Kind::Surface => self
+                .record
+                .material
+                .eval(&self.ray, &self.record, &direction),
+            Kind::Light { index, .. } => {
+                let emission = context
+                    .scene
+                    .lights
+                    .light(index)
+                    .emission(&self.record, &direction);
+                if self.on_surface() {
+                    emission * dot_product(&self.record.normal, &direction).abs()
+                } else {
+                    emission
+                }
+            }
+            Kind::Camera => Color::default(),
+        }
+    }
+}
+
+/// Ray that left the scene at the end of the camera path.
+struct Escape {
+    ray: Ray,
+    beta: Color,
+    /// Solid angle density of the last bounce, `None` for the camera ray
+    /// and specular bounces which lights can't be sampled for.
+    pdf: Option<f64>,
+}
+
+struct Context<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+}
+
+impl Context<'_> {
+    /// Extends `path` by following sampled BSDFs from `ray`, until it holds
+    /// `max_vertices` or the path ends. The ray leaves the last vertex with
+    /// throughput `beta` and solid angle density `pdf`.
+    fn walk(
+        &self,
+        mut ray: Ray,
+        mut beta: Color,
+        mut pdf: f64,
+        path: &mut Vec<Vertex>,
+        max_vertices: usize,
+    ) -> Option<Escape> {
+        let mut specular = path.len() == 1 && path[0].kind == Kind::Camera;
+        while path.len() < max_vertices {
+            let record = match self.scene.world.hit(&ray, 0.001, f64::INFINITY) {
+                Some(record) => record,
+                None => {
+                    return Some(Escape {
+                        ray,
+                        beta,
+                        pdf: (!specular).then_some(pdf),
+                    })
+                }
+            };
+            let distance = record.t * ray.direction().length();
+            beta = beta * ray.interior().current().transmittance(distance);
+            let mut vertex = Vertex {
+                kind: Kind::Surface,
+                record,
+                ray,
+                beta,
+                pdf_fwd: 0.0,
+                pdf_rev: 0.0,
+                delta: false,
+            };
+            let n = path.len();
+            vertex.pdf_fwd = path[n - 1].convert_density(pdf, &vertex);
+            path.push(vertex);
+            if path.len() == max_vertices {
+                break;
+            }
+
+            let vertex = &path[n];
+            let (record, material) = (&vertex.record, &vertex.record.material);
+            let sample = material.sample(&ray, record, random_f64(), (random_f64(), random_f64()));
+            let sample = match sample {
+                Some(sample) => sample,
+                None => break,
+            };
+            let scattered = sample.scattered;
+            specular = sample.lobe.is_specular();
+            // Density of the reverse path, arriving along the scattered ray
+            // and leaving back towards the previous vertex.
+            let pdf_rev = if specular {
+                pdf = 0.0;
+                0.0
+            } else {
+                pdf = sample.pdf;
+                let reverse =
+                    scattered.spawn(record.point + scattered.direction(), -scattered.direction());
+                material.pdf(
+                    &reverse,
+                    &record.facing(&reverse),
+                    &unit_vector(-ray.direction()),
+                )
+            };
+            beta = beta * sample.weight;
+            path[n].delta = specular;
+            path[n - 1].pdf_rev = path[n].convert_density(pdf_rev, &path[n - 1]);
+            if beta == Color::default() {
+                break;
+            }
+            ray = scattered;
+        }
+        None
+    }
+
+    /// Area density with which `vertex`, reached from `prev`, scatters or
+    /// emits the path towards `next`.
+    fn pdf(&self, vertex: &Vertex, prev: Option<&Vertex>, next: &Vertex) -> f64 {
+        let direction = next.record.point - vertex.record.point;
+        let pdf = match vertex.kind {
+            Kind::Camera => self.camera.pdf(&Ray::new(vertex.record.point, direction)).1,
+            Kind::Light { index, .. } => {
+                self.scene
+                    .lights
+                    .light(index)
+                    .emission_pdf(&vertex.record, &direction)
+                    .1
+            }
+            Kind::Surface => {
+                let prev = match prev {
+                    Some(prev) => prev,
+                    None => return 0.0,
+                };
+                let r_in = vertex
+                    .ray
+                    .spawn(prev.record.point, vertex.record.point - prev.record.point);
+                let record = vertex.record.facing(&r_in);
+                record.material.pdf(&r_in, &record, &unit_vector(direction))
+            }
+        };
+        vertex.convert_density(pdf, next)
+    }
+
+    /// Light paths reaching the camera vertex `camera_path[t - 1]`, which
+    /// lies on an emitter, ending there.
+    fn emitted(&self, camera_path: &[Vertex], t: usize) -> Color {
+        let pt = &camera_path[t - 1];
+        let emitted = pt.record.material.emitted(&pt.ray, &pt.record);
+        if emitted == Color::default() {
+            return emitted;
+        }
+        pt.beta * emitted * self.mis_weight(&[], camera_path, pt, 0, t)
+    }
+
+    /// Light path `light_path[..s]` connected to the camera, returned with
+    /// where it lands on the image.
+    fn splat(&self, light_path: &[Vertex], s: usize) -> Option<((f64, f64), Color)> {
+        let qs = &light_path[s - 1];
+        if !qs.connectible() {
+            return None;
+        }
+        let lens = self
+            .camera
+            .sample_lens(&qs.record.point, (random_f64(), random_f64()))?;
+        if lens.importance <= 0.0 || lens.pdf <= 0.0 {
+            return None;
+        }
+        let to_lens = lens.point - qs.record.point;
+        let f = qs.f(self, &to_lens);
+        if f == Color::default() {
+            return None;
+        }
+        let transmittance = self.visibility(qs, lens.point)?;
+        let camera = Vertex {
+            kind: Kind::Camera,
+            record: HitRecord::new(lens.point, self.camera.forward(), 0.0),
+            ray: Ray::new(lens.point, -to_lens),
+            beta: Color::new(1.0, 1.0, 1.0) * (lens.importance / lens.pdf),
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        };
+        let weight = self.mis_weight(light_path, &[], &camera, s, 1);
+        Some((
+            lens.raster,
+            qs.beta * f * camera.beta * transmittance * weight,
+        ))
+    }
+
+    /// Light path `light_path[..s]` joined to camera path
+    /// `camera_path[..t]`, with `t` of at least two.
+    fn connect(&self, light_path: &[Vertex], camera_path: &[Vertex], s: usize, t: usize) -> Color {
+        let black = Color::default();
+        if s == 0 {
+            return self.emitted(camera_path, t);
+        }
+        let (qs, pt) = (&light_path[s - 1], &camera_path[t - 1]);
+        if !qs.connectible() || !pt.connectible() {
+            return black;
+        }
+        let d = qs.record.point - pt.record.point;
+        let f = qs.f(self, &-d) * pt.f(self, &d);
+        if f == black {
+            return black;
+        }
+        let transmittance = match self.visibility(pt, qs.record.point) {
+            Some(transmittance) => transmittance,
+            None => return black,
+        };
+        let weight = self.mis_weight(light_path, camera_path, pt, s, t);
+        qs.beta * f * pt.beta * transmittance * (weight / d.length_squared())
+    }
+
+    /// Transmittance from `vertex` to `point`, `None` when something blocks
+    /// the way.
+    fn visibility(&self, vertex: &Vertex, point: Point) -> Option<Color> {
+        let d = point - vertex.record.point;
+        let distance = d.length();
+        let shadow = vertex.ray.spawn(vertex.record.point, d / distance);
+        if self
+            .scene
+            .world
+            .hit(&shadow, 0.001, distance - 0.001)
+            .is_some()
+        {
+            return None;
+        }
+        Some(shadow.interior().current().transmittance(distance))
+    }
+
+    /// Balance heuristic weight of the strategy joining `s` light vertices
+    /// to `t` camera vertices, among every other way of building the same
+    /// path. `pt` is the camera end of the connection: `camera_path[t - 1]`,
+    /// or the sampled camera vertex when `t` is one.
+    fn mis_weight(
+        &self,
+        light_path: &[Vertex],
+        camera_path: &[Vertex],
+        pt: &Vertex,
+        s: usize,
+        t: usize,
+    ) -> f64 {
+        if s + t == 2 {
+            return 1.0;
+        }
+        let lights = &self.scene.lights;
+        // Forward and reverse densities and delta flags of the vertices, with
+        // the ones around the connection as this strategy would sample them.
+        let densities = |path: &[Vertex], n: usize| -> Vec<(f64, f64, bool)> {
+            path[..n]
+                .iter()
+                .map(|v| (v.pdf_fwd, v.pdf_rev, v.delta))
+                .collect()
+        };
+        let mut light = densities(light_path, s);
+        let mut camera = densities(camera_path, t - 1);
+        let qs = (s > 0).then(|| &light_path[s - 1]);
+        let qs_minus = (s > 1).then(|| &light_path[s - 2]);
+        let pt_minus = (t > 1).then(|| &camera_path[t - 2]);
+
+        camera.push((pt.pdf_fwd, 0.0, false));
+        camera[t - 1].1 = match (qs, pt_minus) {
+            (Some(qs), _) => self.pdf(qs, qs_minus, pt),
+            (None, Some(pt_minus)) => {
+                let direction = pt_minus.record.point - pt.record.point;
+                let pdf_position = lights.emission_pdf(&pt.record, &direction).0;
+                if pdf_position <= 0.0 {
+                    // An emitter that isn't among the lights, only found by
+                    // camera paths.
+                    return 1.0;
+                }
+                pdf_position
+            }
+            (None, None) => 0.0,
+        };
+        if let Some(pt_minus) = pt_minus {
+            camera[t - 2].1 = match qs {
+                Some(qs) => self.pdf(pt, Some(qs), pt_minus),
+                None => {
+                    let direction = pt_minus.record.point - pt.record.point;
+                    let pdf = lights.emission_pdf(&pt.record, &direction).1;
+                    pt.convert_density(pdf, pt_minus)
+                }
+            };
+        }
+        if let Some(qs) = qs {
+            light[s - 1].2 = false;
+            light[s - 1].1 = self.pdf(pt, pt_minus, qs);
+        }
+        if let (Some(qs), Some(qs_minus)) = (qs, qs_minus) {
+            light[s - 2].1 = self.pdf(qs, Some(pt), qs_minus);
+        }
+
+        // Ratios of the densities of the other strategies to this one's,
+        // moving the connection along the path. Zero densities come from
+        // specular neighbors, whose strategies the delta flags rule out.
+        let remap = |pdf: f64| if pdf != 0.0 { pdf } else { 1.0 };
+        let mut sum = 0.0;
+        let mut ratio = 1.0;
+        for i in (1..t).rev() {
+            ratio *= remap(camera[i].1) / remap(camera[i].0);
+            if !camera[i].2 && !camera[i - 1].2 {
+                sum += ratio;
+            }
+        }
+        ratio = 1.0;
+        for i in (0..s).rev() {
+            ratio *= remap(light[i].1) / remap(light[i].0);
+            let delta_before = if i > 0 {
+                light[i - 1].2
+            } else {
+                light_path[0].is_delta_light()
+            };
+            if !light[i].2 && !delta_before {
+                sum += ratio;
+            }
+        }
+        1.0 / (1.0 + sum)
+    }
+
+    /// Light from the lights at infinity reflected at `vertex` towards the
+    /// previous one, weighted against finding them with the BSDF.
+    fn sample_infinite(&self, vertex: &Vertex) -> Color {
+        let black = Color::default();
+        let (lights, world) = (&self.scene.lights, &self.scene.world);
+        let (r, record) = (&vertex.ray, &vertex.record);
+        let u = (random_f64(), random_f64());
+        match lights.sample_infinite(&record.point, random_f64(), u) {
+            Some(LightSample::Delta {
+                direction,
+                irradiance,
+                ..
+            }) => {
+                let shadow = r.spawn(record.point, direction);
+                if world.hit(&shadow, 0.001, f64::INFINITY).is_some() {
+                    return black;
+                }
+                irradiance * record.material.eval(r, record, &direction)
+            }
+            Some(LightSample::Direction(direction)) => {
+                let light_pdf = lights.pdf_infinite(&record.point, &direction);
+                if light_pdf <= 0.0 {
+                    return black;
+                }
+                let bsdf = record.material.eval(r, record, &direction);
+                if bsdf == black {
+                    return black;
+                }
+                let shadow = r.spawn(record.point, direction);
+                if world.hit(&shadow, 0.001, f64::INFINITY).is_some() {
+                    return black;
+                }
+                let bsdf_pdf = record.material.pdf(r, record, &direction);
+                lights.escaped(&shadow) * bsdf * (power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+            }
+            None => black,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        integrator::Integrator,
+        light::{DirectionalLight, Lights, PointLight},
+        material::{Conductor, Dielectric, DiffuseLight, Lambertian, Material},
+        microfacet::Distribution,
+        quad::Quad,
+        ray::Hittables,
+        scene::Background,
+        sphere::Sphere,
+    };
+
+    /// A diffuse floor, a glossy wall and a glass ball lit by a quad facing
+    /// down, a small sphere, a point light and a soft sun.
+    fn scene() -> Scene {
+        let floor: Material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let wall: Material = Arc::new(Conductor::gold(Distribution::Ggx, 0.3));
+        let light: Material = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let sphere = || Sphere::new(Point::new(1.0, 0.5, 0.0), 0.3, light.clone());
+        let quad = || {
+            Quad::new(
+                Point::new(-1.0, 3.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                light.clone(),
+            )
+        };
+
+        let mut world = Hittables::new();
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -5.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            floor,
+        )));
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -2.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 5.0, 0.0),
+            wall,
+        )));
+        world.add(Box::new(Sphere::new(
+            Point::new(-0.5, 0.6, 0.5),
+            0.6,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        world.add(Box::new(sphere()));
+        world.add(Box::new(quad()));
+        let mut lights = Lights::new();
+        lights.add_area(Box::new(sphere()));
+        lights.add_area(Box::new(quad()));
+        lights.add(Box::new(PointLight::new(
+            Point::new(-1.0, 1.0, 1.0),
+            Color::new(2.0, 2.0, 2.0),
+        )));
+        lights.add(Box::new(
+            DirectionalLight::new(Vec3::new(-0.3, -1.0, -0.5), Color::new(1.0, 1.0, 1.0))
+                .with_angular_diameter(30.0),
+        ));
+        Scene::new(world, lights).with_background(Background::Solid(Color::default()))
+    }
+
+    #[test]
+    fn strategy_weights_sum_to_one() {
+        // A path from the camera to the floor, the gold wall and the quad
+        // light, and every way of sampling it.
+        let floor: Material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let wall: Material = Arc::new(Conductor::gold(Distribution::Ggx, 0.3));
+        let light: Material = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let quad = || {
+            Quad::new(
+                Point::new(-1.0, 3.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                light.clone(),
+            )
+        };
+        let mut world = Hittables::new();
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -5.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            floor,
+        )));
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -2.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 5.0, 0.0),
+            wall,
+        )));
+        world.add(Box::new(quad()));
+        let mut lights = Lights::new();
+        lights.add_area(Box::new(quad()));
+        let scene = Scene::new(world, lights);
+        let camera = Camera::new(
+            Point::new(0.0, 2.0, 6.0),
+            Point::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            50.0,
+            1.5,
+            0.0,
+            6.0,
+        );
+        let context = Context {
+            scene: &scene,
+            camera: &camera,
+        };
+
+        let vertex = |kind: Kind, record: HitRecord, ray: Ray| Vertex {
+            kind,
+            record,
+            ray,
+            beta: Color::new(1.0, 1.0, 1.0),
+            pdf_fwd: 0.0,
+            pdf_rev: 0.0,
+            delta: false,
+        };
+        let hit = |from: Point, to: Point| {
+            let ray = Ray::new(from, to - from);
+            (scene.world.hit(&ray, 0.001, f64::INFINITY).unwrap(), ray)
+        };
+        let ray = camera.get_ray(0.4, 0.2);
+        let lens = ray.origin();
+        let (floor_hit, _) = hit(lens, ray.at(1.0));
+        assert!(floor_hit.point.y().abs() < 1e-9);
+        let points = [
+            lens,
+            floor_hit.point,
+            Point::new(0.3, 1.5, -2.0),
+            Point::new(0.2, 3.0, -0.5),
+        ];
+
+        let mut camera_path = vec![vertex(
+            Kind::Camera,
+            HitRecord::new(lens, camera.forward(), 0.0),
+            ray,
+        )];
+        for pair in points.windows(2) {
+            let (record, ray) = hit(pair[0], pair[1]);
+            assert!((record.point - pair[1]).length() < 1e-9);
+            camera_path.push(vertex(Kind::Surface, record, ray));
+        }
+        let light = &camera_path[3];
+        let mut light_path = vec![vertex(
+            Kind::Light {
+                index: 0,
+                delta_position: false,
+            },
+            light.record.clone(),
+            Ray::new(points[3], points[2] - points[3]),
+        )];
+        for pair in [[points[3], points[2]], [points[2], points[1]]] {
+            let (record, ray) = hit(pair[0], pair[1]);
+            light_path.push(vertex(Kind::Surface, record, ray));
+        }
+
+        // Densities as `walk` leaves them.
+        for i in 1..4 {
+            let prev = (i > 1).then(|| camera_path[i - 2].clone());
+            camera_path[i].pdf_fwd =
+                context.pdf(&camera_path[i - 1], prev.as_ref(), &camera_path[i]);
+        }
+        // The vertex before the light gets its reverse density in
+        // `mis_weight`, which knows how the light would emit towards it.
+        let next = camera_path[3].clone();
+        camera_path[1].pdf_rev = context.pdf(&camera_path[2], Some(&next), &camera_path[1]);
+        light_path[0].pdf_fwd = scene
+            .lights
+            .emission_pdf(&light_path[0].record, &(points[2] - points[3]))
+            .0;
+        for i in 1..3 {
+            let prev = (i > 1).then(|| light_path[i - 2].clone());
+            light_path[i].pdf_fwd = context.pdf(&light_path[i - 1], prev.as_ref(), &light_path[i]);
+        }
+        let next = light_path[2].clone();
+        light_path[0].pdf_rev = context.pdf(&light_path[1], Some(&next), &light_path[0]);
+
+        let sampled = vertex(
+            Kind::Camera,
+            HitRecord::new(lens, camera.forward(), 0.0),
+            Ray::new(lens, points[0] - points[1]),
+        );
+        let weights = [
+            context.mis_weight(&[], &camera_path, &camera_path[3], 0, 4),
+            context.mis_weight(&light_path, &camera_path, &camera_path[2], 1, 3),
+            context.mis_weight(&light_path, &camera_path, &camera_path[1], 2, 2),
+            context.mis_weight(&light_path, &[], &sampled, 3, 1),
+        ];
+        assert!(weights.iter().all(|&w| w > 0.0 && w < 1.0), "{weights:?}");
+        let sum: f64 = weights.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-9, "{weights:?}");
+    }
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        let scene = scene();
+        let camera = Camera::new(
+            Point::new(0.0, 2.0, 6.0),
+            Point::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            50.0,
+            1.5,
+            0.0,
+            6.0,
+        );
+        let (width, height, samples) = (6, 4, 4000);
+        let film = Film::new(width, height);
+        let bdpt = Bdpt::new(4);
+        let mut reference = vec![Color::default(); width * height];
+        let mut estimate = vec![Color::default(); width * height];
+        for j in 0..height {
+            for i in 0..width {
+                for _ in 0..samples {
+                    let s = (i as f64 + random_f64()) / (width - 1) as f64;
+                    let t = (j as f64 + random_f64()) / (height - 1) as f64;
+                    reference[j * width + i] +=
+                        Integrator::default().ray_color(&camera.get_ray(s, t), &scene, 4);
+                    estimate[j * width + i] += bdpt.sample(&scene, &camera, s, t, &film);
+                }
+            }
+        }
+        // The path tracer misses the caustic of the point light through the
+        // glass, a small part of the image.
+        let mut total = (Color::default(), Color::default());
+        for j in 0..height {
+            for i in 0..width {
+                let reference = reference[j * width + i] / samples as f64;
+                let estimate = (estimate[j * width + i] + film.splat(i, j)) / samples as f64;
+                assert!(
+                    (estimate - reference).length() < 0.2 * reference.length() + 0.03,
+                    "pixel ({i}, {j}): {estimate:?} against {reference:?}"
+                );
+                total = (total.0 + reference, total.1 + estimate);
+            }
+        }
+        assert!(
+            (total.1 - total.0).length() < 0.03 * total.0.length(),
+            "{total:?}"
+        );
+    }
+}
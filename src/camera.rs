@@ -1,7 +1,9 @@
 use crate::degress_to_radies;
 use crate::ray::Ray;
-use crate::units::vec3::{cross_product, random_in_unit_disk, unit_vector};
+use crate::sampling::sample_uniform_disk;
+use crate::units::vec3::{cross_product, dot_product, random_in_unit_disk, unit_vector};
 use crate::units::{point::Point, vec3::Vec3};
+use crate::PI;
 
 #[derive(Debug, Default)]
 pub struct Camera {
@@ -13,6 +15,22 @@ pub struct Camera {
     v: Vec3,
     w: Vec3,
     lens_radius: f64,
+    focus_dist: f64,
+    /// Area of the image on a plane at unit distance from the lens.
+    image_area: f64,
+}
+
+/// Point on the lens seen from a point of the scene, drawn by
+/// `Camera::sample_lens`.
+#[derive(Copy, Clone, Debug)]
+pub struct LensSample {
+    pub point: Point,
+    /// Importance of the ray from the lens point towards the scene point.
+    pub importance: f64,
+    /// Solid angle density of the lens point seen from the scene point.
+    pub pdf: f64,
+    /// Where the ray lands on the image, in the coordinates `get_ray` takes.
+    pub raster: (f64, f64),
 }
 
 impl Camera {
@@ -48,6 +66,8 @@ impl Camera {
             v,
             w,
             lens_radius,
+            focus_dist,
+            image_area: viewport_width * viewport_height,
         }
     }
 
@@ -59,4 +79,131 @@ impl Camera {
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
         )
     }
+
+    /// Direction the camera looks along.
+    pub fn forward(&self) -> Vec3 {
+        -self.w
+    }
+
+    /// Area of the lens, one for a pinhole so its point counts as a delta.
+    fn lens_area(&self) -> f64 {
+        if self.lens_radius > 0.0 {
+            PI * self.lens_radius * self.lens_radius
+        } else {
+            1.0
+        }
+    }
+
+    /// Where a ray leaving the lens lands on the image, in the coordinates
+    /// `get_ray` takes, or `None` when it points away from the image plane.
+    /// The coordinates may fall outside of the image.
+    pub fn raster(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let direction = unit_vector(ray.direction());
+        let cos_theta = dot_product(&direction, &self.forward());
+        if cos_theta <= 0.0 {
+            return None;
+        }
+        let focus =
+            ray.origin() + direction * (self.focus_dist / cos_theta) - self.lower_left_corner;
+        Some((
+            dot_product(&focus, &self.horizontal) / self.horizontal.length_squared(),
+            dot_product(&focus, &self.vertical) / self.vertical.length_squared(),
+        ))
+    }
+
+    /// Importance emitted along a ray leaving the lens, normalized so that
+    /// rays spread uniformly over `get_ray`'s unit square of image
+    /// coordinates carry one in total.
+    pub fn importance(&self, ray: &Ray) -> f64 {
+        let cos_theta = dot_product(&unit_vector(ray.direction()), &self.forward());
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        1.0 / (self.image_area * self.lens_area() * cos_theta.powi(4))
+    }
+
+    /// Densities with which `get_ray` picks the ray's origin, per unit area
+    /// of the lens, and its direction, per unit solid angle.
+    pub fn pdf(&self, ray: &Ray) -> (f64, f64) {
+        let cos_theta = dot_product(&unit_vector(ray.direction()), &self.forward());
+        if cos_theta <= 0.0 {
+            return (0.0, 0.0);
+        }
+        (
+            1.0 / self.lens_area(),
+            1.0 / (self.image_area * cos_theta.powi(3)),
+        )
+    }
+
+    /// Picks a point on the lens with two uniform numbers to connect
+    /// `point` to the camera. `None` when the point is behind the camera.
+    pub fn sample_lens(&self, point: &Point, u: (f64, f64)) -> Option<LensSample> {
+        let rd = self.lens_radius * sample_uniform_disk(u);
+        let lens = self.origin + self.u * rd.x() + self.v * rd.y();
+        let to_point = *point - lens;
+        let distance = to_point.length();
+        let ray = Ray::new(lens, to_point / distance);
+        let raster = self.raster(&ray)?;
+        let cos_theta = dot_product(&ray.direction(), &self.forward());
+        Some(LensSample {
+            point: lens,
+            importance: self.importance(&ray),
+            pdf: distance * distance / (cos_theta * self.lens_area()),
+            raster,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(aperture: f64) -> Camera {
+        Camera::new(
+            Point::new(1.0, 2.0, 3.0),
+            Point::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0,
+            1.5,
+            aperture,
+            4.0,
+        )
+    }
+
+    #[test]
+    fn raster_inverts_get_ray() {
+        for aperture in [0.0, 0.5] {
+            let camera = camera(aperture);
+            for (s, t) in [(0.5, 0.5), (0.1, 0.9), (0.75, 0.2)] {
+                let (rs, rt) = camera.raster(&camera.get_ray(s, t)).unwrap();
+                assert!((rs - s).abs() < 1e-9 && (rt - t).abs() < 1e-9);
+            }
+            let behind = Ray::new(Point::default(), camera.w);
+            assert!(camera.raster(&behind).is_none());
+        }
+    }
+
+    #[test]
+    fn importance_integrates_to_one_over_the_image() {
+        // Summed over the solid angle of the image from the center of the
+        // lens, importance times cosine gives one over the lens area.
+        let camera = camera(0.0);
+        let n = 200;
+        let mut sum = 0.0;
+        for i in 0..n * n {
+            let (s, t) = (
+                ((i % n) as f64 + 0.5) / n as f64,
+                ((i / n) as f64 + 0.5) / n as f64,
+            );
+            let ray = camera.get_ray(s, t);
+            let cos_theta = dot_product(&unit_vector(ray.direction()), &camera.forward());
+            // Solid angle of the element of the image on the focus plane.
+            let area = camera.horizontal.length() * camera.vertical.length() / (n * n) as f64;
+            let distance = camera.focus_dist / cos_theta;
+            let solid_angle = area * cos_theta / (distance * distance);
+            assert!((camera.pdf(&ray).1 * solid_angle * (n * n) as f64 - 1.0).abs() < 1e-9);
+            sum += camera.importance(&ray) * cos_theta * solid_angle;
+        }
+        assert!((sum - 1.0).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,125 @@
+use crate::{
+    aabb::Aabb,
+    material::Material,
+    ray::{HitRecord, Hittable, Ray},
+    units::vec3::{random_f64, Vec3},
+    INFINITY,
+};
+
+/// Volume of constant density bounded by any closed `Hittable`. Rays
+/// travelling through it scatter after an exponentially distributed free
+/// flight, using `phase_function` as the material at the scattering point.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    density: f64,
+    phase_function: Material,
+}
+
+/// Distance to the next collision in a medium of constant `density`, from
+/// `u` uniform in `[0, 1)`. Free flights are exponentially distributed with
+/// mean `1 / density`, and go on forever through an empty medium.
+pub fn sample_free_flight(density: f64, u: f64) -> f64 {
+    if density > 0.0 {
+        -(1.0 - u).ln() / density
+    } else {
+        f64::INFINITY
+    }
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Box<dyn Hittable>, density: f64, phase_function: Material) -> Self {
+        Self {
+            boundary,
+            density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // Find where the ray enters and leaves the boundary, looking in both
+        // directions so rays starting inside the volume work as well.
+        let rec1 = self.boundary.hit(ray, -INFINITY, INFINITY)?;
+        let rec2 = self.boundary.hit(ray, rec1.t + 0.0001, INFINITY)?;
+
+        let t_enter = rec1.t.max(t_min).max(0.0);
+        let t_exit = rec2.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = ray.direction().length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = sample_free_flight(self.density, random_f64());
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        // Normal and face are arbitrary inside a volume.
+        let mut record = HitRecord::new(ray.at(t), Vec3::new(1.0, 0.0, 0.0), t);
+        record.front_face = true;
+        record.material = self.phase_function.clone();
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Isotropic;
+    use crate::sphere::Sphere;
+    use crate::units::{color::Color, point::Point};
+    use std::sync::Arc;
+
+    fn fog(radius: f64, density: f64) -> ConstantMedium {
+        let phase: Material = Arc::new(Isotropic::new(Color::new(1.0, 1.0, 1.0)));
+        let boundary = Sphere::new(Point::new(0.0, 0.0, 0.0), radius, phase.clone());
+        ConstantMedium::new(Box::new(boundary), density, phase)
+    }
+
+    #[test]
+    fn free_flights_invert_the_transmittance() {
+        let u = 1.0 - (-1.0_f64).exp();
+        assert!((sample_free_flight(2.0, u) - 0.5).abs() < 1e-12);
+        assert_eq!(sample_free_flight(0.0, 0.5), f64::INFINITY);
+    }
+
+    #[test]
+    fn mean_free_path_is_inverse_density() {
+        // The boundary is far enough that no flight is cut short by it.
+        let medium = fog(1000.0, 2.0);
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0));
+        let n = 20_000;
+        let mean = (0..n)
+            .map(|_| {
+                let rec = medium.hit(&ray, 0.0, INFINITY).unwrap();
+                (rec.point - ray.origin()).length()
+            })
+            .sum::<f64>()
+            / n as f64;
+        assert!((mean - 0.5).abs() < 0.02, "{mean}");
+    }
+
+    #[test]
+    fn escaping_rays_follow_beer_lambert() {
+        let medium = fog(1.0, 0.5);
+        let ray = Ray::new(Point::new(-2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let n = 20_000;
+        let escaped = (0..n)
+            .filter(|_| medium.hit(&ray, 0.0, INFINITY).is_none())
+            .count();
+        let estimate = escaped as f64 / n as f64;
+        // The ray crosses the whole diameter of the unit sphere.
+        let expected = (-1.0_f64).exp();
+        assert!(
+            (estimate - expected).abs() < 0.02,
+            "{estimate} vs {expected}"
+        );
+    }
+}
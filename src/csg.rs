@@ -0,0 +1,208 @@
+use crate::{
+    aabb::Aabb,
+    ray::{HitRecord, Hittable, Interval, Ray},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Constructive solid geometry node combining two closed solids. Both
+/// operands must report their `intervals`. Surfaces keep the material of
+/// the operand they come from, so the walls carved by a difference show
+/// the material of the subtracted solid.
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<dyn Hittable>,
+    right: Box<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self {
+            operation,
+            left,
+            right,
+        }
+    }
+
+    pub fn union(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self::new(CsgOperation::Union, left, right)
+    }
+
+    pub fn intersection(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self::new(CsgOperation::Intersection, left, right)
+    }
+
+    pub fn difference(left: Box<dyn Hittable>, right: Box<dyn Hittable>) -> Self {
+        Self::new(CsgOperation::Difference, left, right)
+    }
+}
+
+/// Boundary crossing of one operand while sweeping along the ray.
+struct Event {
+    record: HitRecord,
+    from_left: bool,
+}
+
+impl Hittable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.intervals(ray)
+            .iter()
+            .find_map(|interval| interval.first_hit(ray, t_min, t_max))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let mut events = vec![];
+        for (object, from_left) in [(&self.left, true), (&self.right, false)] {
+            for interval in object.intervals(ray) {
+                events.push(Event {
+                    record: interval.enter,
+                    from_left,
+                });
+                events.push(Event {
+                    record: interval.exit,
+                    from_left,
+                });
+            }
+        }
+        events.sort_by(|a, b| a.record.t.total_cmp(&b.record.t));
+
+        // Sweep the boundaries in order, toggling which operands we are in
+        // and keeping the ones where the combined solid changes state.
+        let mut in_left = false;
+        let mut in_right = false;
+        let mut inside = false;
+        let mut enter = None;
+        let mut intervals = vec![];
+        for event in events {
+            if event.from_left {
+                in_left = !in_left;
+            } else {
+                in_right = !in_right;
+            }
+            let now_inside = self.operation.inside(in_left, in_right);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+
+            let mut record = event.record;
+            if self.operation == CsgOperation::Difference && !event.from_left {
+                // The subtracted solid is turned inside out.
+                record.normal = -record.normal;
+            }
+            if inside {
+                enter = Some(record);
+            } else if let Some(enter) = enter.take() {
+                intervals.push(Interval {
+                    enter,
+                    exit: record,
+                });
+            }
+        }
+        intervals
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // Intersections and differences lie within the left operand.
+        let left = self.left.bounding_box()?;
+        match self.operation {
+            CsgOperation::Union => Some(left.union(&self.right.bounding_box()?)),
+            CsgOperation::Intersection | CsgOperation::Difference => Some(left),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        cuboid::Cuboid,
+        material::{Lambertian, Material, Metal},
+        sphere::Sphere,
+        units::{color::Color, point::Point, vec3::Vec3},
+    };
+
+    fn drilled_box() -> (Csg, Material, Material) {
+        let wood: Material = Arc::new(Lambertian::new(Color::new(0.6, 0.4, 0.2)));
+        let steel: Material = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.0));
+        let csg = Csg::difference(
+            Box::new(Cuboid::new(
+                Point::new(-1.0, -1.0, -1.0),
+                Point::new(1.0, 1.0, 1.0),
+                wood.clone(),
+            )),
+            Box::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 0.5, steel.clone())),
+        );
+        (csg, wood, steel)
+    }
+
+    #[test]
+    fn difference_hits_outer_surface_first() {
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let (csg, wood, _) = drilled_box();
+        let record = csg.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-9);
+        assert_eq!(record.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert!(Arc::ptr_eq(&record.material, &wood));
+    }
+
+    #[test]
+    fn difference_carved_surface_faces_into_hole() {
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let (csg, _, steel) = drilled_box();
+        let record = csg.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((record.t - 0.5).abs() < 1e-9);
+        assert_eq!(record.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert!(record.front_face);
+        assert!(Arc::ptr_eq(&record.material, &steel));
+    }
+
+    #[test]
+    fn intersection_of_spheres_is_a_lens() {
+        let glass: Material = Arc::new(Lambertian::default());
+        let lens = Csg::intersection(
+            Box::new(Sphere::new(Point::new(-0.8, 0.0, 0.0), 1.0, glass.clone())),
+            Box::new(Sphere::new(Point::new(0.8, 0.0, 0.0), 1.0, glass)),
+        );
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let intervals = lens.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.t - 4.8).abs() < 1e-9);
+        assert!((intervals[0].exit.t - 5.2).abs() < 1e-9);
+
+        let miss = Ray::new(Point::new(-5.0, 0.7, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(lens.hit(&miss, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn bounds_follow_the_operation() {
+        let (drilled, _, steel) = drilled_box();
+        let bounds = drilled.bounding_box().unwrap();
+        assert_eq!(bounds.min(), Point::new(-1.0, -1.0, -1.0));
+        assert_eq!(bounds.max(), Point::new(1.0, 1.0, 1.0));
+
+        let ball = Sphere::new(Point::new(3.0, 0.0, 0.0), 0.5, steel);
+        let union = Csg::union(Box::new(drilled_box().0), Box::new(ball));
+        assert_eq!(
+            union.bounding_box().unwrap().max(),
+            Point::new(3.5, 1.0, 1.0)
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    aabb::Aabb,
+    material::Material,
+    ray::{HitRecord, Hittable, Interval, Ray},
+    units::{point::Point, vec3::Vec3},
+};
+
+/// Axis aligned box spanned by two opposite corners.
+pub struct Cuboid {
+    minimum: Point,
+    maximum: Point,
+    material: Material,
+}
+
+impl Cuboid {
+    pub fn new(p0: Point, p1: Point, material: Material) -> Self {
+        let minimum = Point::new(p0.x().min(p1.x()), p0.y().min(p1.y()), p0.z().min(p1.z()));
+        let maximum = Point::new(p0.x().max(p1.x()), p0.y().max(p1.y()), p0.z().max(p1.z()));
+        Self {
+            minimum,
+            maximum,
+            material,
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.intervals(ray)
+            .first()
+            .and_then(|interval| interval.first_hit(ray, t_min, t_max))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        // Slab test keeping track of which axis bounds the interval on each side.
+        let mut t_enter = f64::NEG_INFINITY;
+        let mut t_exit = f64::INFINITY;
+        let mut enter_normal = Vec3::default();
+        let mut exit_normal = Vec3::default();
+        for a in 0..3 {
+            let d = ray.direction()[a];
+            let o = ray.origin()[a];
+            if d == 0.0 {
+                if o < self.minimum[a] || o > self.maximum[a] {
+                    return vec![];
+                }
+                continue;
+            }
+            let mut t0 = (self.minimum[a] - o) / d;
+            let mut t1 = (self.maximum[a] - o) / d;
+            let mut normal = Vec3::default();
+            normal[a] = -1.0;
+            if d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+                normal = -normal;
+            }
+            if t0 > t_enter {
+                t_enter = t0;
+                enter_normal = normal;
+            }
+            if t1 < t_exit {
+                t_exit = t1;
+                exit_normal = -normal;
+            }
+        }
+        if t_exit <= t_enter {
+            return vec![];
+        }
+
+        let boundary = |t: f64, outward_normal: Vec3| {
+            let mut record = HitRecord::new(ray.at(t), outward_normal, t);
+            record.material = self.material.clone();
+            record
+        };
+        vec![Interval {
+            enter: boundary(t_enter, enter_normal),
+            exit: boundary(t_exit, exit_normal),
+        }]
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.minimum, self.maximum))
+    }
+}
@@ -0,0 +1,217 @@
+use std::{io, path::Path};
+
+use crate::{
+    aabb::Aabb,
+    light::{EmissionSample, Light, LightSample, SceneDisk},
+    ray::{HitRecord, Ray},
+    sampling::Distribution2D,
+    sphere::Sphere,
+    texture::{ImageTexture, Texture},
+    units::{
+        color::Color,
+        point::Point,
+        vec3::{unit_vector, Vec3},
+    },
+    PI,
+};
+
+/// Light arriving from every direction, read from an equirectangular image
+/// with up at the top. Directions are importance sampled following the
+/// brightness of the image.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    image: ImageTexture,
+    intensity: f64,
+    /// Rotation around the vertical axis, as cosine and sine.
+    rotation: (f64, f64),
+    distribution: Distribution2D,
+    disk: SceneDisk,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: ImageTexture) -> Self {
+        let (width, height) = (image.width(), image.height());
+        // Rows near the poles cover less solid angle than those around the
+        // horizon.
+        let mut func = Vec::with_capacity(width * height);
+        for j in 0..height {
+            let sin_theta = (PI * (j as f64 + 0.5) / height as f64).sin();
+            for i in 0..width {
+                let c = image.pixel(i, j);
+                func.push((0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()) * sin_theta);
+            }
+        }
+        Self {
+            distribution: Distribution2D::new(&func, width, height),
+            image,
+            intensity: 1.0,
+            rotation: (1.0, 0.0),
+            disk: SceneDisk::default(),
+        }
+    }
+
+    /// Loads a `.hdr` or `.pfm` image, picked by the file extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let image = match extension.as_deref() {
+            Some("hdr") => ImageTexture::load_hdr(path)?,
+            Some("pfm") => ImageTexture::load_pfm(path)?,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "environment maps must be .hdr or .pfm images",
+                ))
+            }
+        };
+        Ok(Self::new(image))
+    }
+
+    /// Scales the radiance of the whole map.
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Turns the map around the vertical axis by `degrees`.
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        let angle = degrees.to_radians();
+        self.rotation = (angle.cos(), angle.sin());
+        self
+    }
+
+    /// Radiance arriving from `direction`, travelling against it.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = Sphere::uv(&self.map_direction(&unit_vector(*direction)));
+        self.image.value(u, v, &Point::default()) * self.intensity
+    }
+
+    /// Luminance of the radiance integrated over the sphere of directions.
+    pub fn radiance_integral(&self) -> f64 {
+        // The sampling distribution holds luminance times sin θ over the
+        // image, which spans 2π by π.
+        self.distribution.integral() * 2.0 * PI * PI * self.intensity
+    }
+
+    /// World space direction turned into the frame of the image.
+    fn map_direction(&self, d: &Vec3) -> Vec3 {
+        let (cos, sin) = self.rotation;
+        Vec3::new(cos * d.x() + sin * d.z(), d.y(), -sin * d.x() + cos * d.z())
+    }
+
+    /// Inverse of `map_direction`.
+    fn world_direction(&self, d: &Vec3) -> Vec3 {
+        let (cos, sin) = self.rotation;
+        Vec3::new(cos * d.x() - sin * d.z(), d.y(), sin * d.x() + cos * d.z())
+    }
+}
+
+impl Light for EnvironmentMap {
+    fn sample(&self, _point: &Point, u: (f64, f64)) -> Option<LightSample> {
+        // Image coordinates run from the top left, texture coordinates from
+        // the bottom, as in `Sphere::uv`.
+        let ((s, t), pdf) = self.distribution.sample_continuous(u);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let theta = (1.0 - t) * PI;
+        let phi = s * 2.0 * PI;
+        let d = Vec3::new(
+            -theta.sin() * phi.cos(),
+            -theta.cos(),
+            theta.sin() * phi.sin(),
+        );
+        Some(LightSample::Direction(self.world_direction(&d)))
+    }
+
+    fn pdf(&self, _point: &Point, direction: &Vec3) -> f64 {
+        let d = self.map_direction(&unit_vector(*direction));
+        let (u, v) = Sphere::uv(&d);
+        let sin_theta = (1.0 - d.y() * d.y()).max(0.0).sqrt();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, 1.0 - v) / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn escaped(&self, r: &Ray) -> Color {
+        self.radiance(&r.direction())
+    }
+
+    fn phi(&self) -> f64 {
+        self.disk.phi(self.radiance_integral())
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        self.disk = SceneDisk::new(scene_bounds);
+    }
+
+    fn sample_emission(&self, u: (f64, f64), v: (f64, f64)) -> Option<EmissionSample> {
+        self.disk.sample_emission(self, u, v)
+    }
+
+    fn emission(&self, _record: &HitRecord, direction: &Vec3) -> Color {
+        self.radiance(&-*direction)
+    }
+
+    fn emission_pdf(&self, record: &HitRecord, direction: &Vec3) -> (f64, f64) {
+        self.disk.emission_pdf(self, record, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dim map with one bright texel.
+    fn map() -> EnvironmentMap {
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Color::new(0.1, 0.1, 0.1); width * height];
+        pixels[2 * width + 5] = Color::new(50.0, 40.0, 30.0);
+        EnvironmentMap::new(ImageTexture::new(width, height, pixels)).with_rotation(30.0)
+    }
+
+    #[test]
+    fn sampled_directions_match_pdf_and_radiance() {
+        let map = map();
+        let origin = Point::default();
+        let mut bright = 0;
+        for i in 0..100 {
+            let u = ((i as f64 * 0.618034) % 1.0, (i as f64 + 0.5) / 100.0);
+            let direction = match map.sample(&origin, u) {
+                Some(LightSample::Direction(direction)) => direction,
+                _ => panic!("environment maps always sample a direction"),
+            };
+            assert!(map.pdf(&origin, &direction) > 0.0);
+            if map.radiance(&direction).x() > 1.0 {
+                bright += 1;
+            }
+        }
+        // The bright texel holds most of the power.
+        assert!(bright > 50, "{}", bright);
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let map = map();
+        let (nu, nv) = (400, 200);
+        let mut integral = 0.0;
+        for j in 0..nv {
+            let theta = PI * (j as f64 + 0.5) / nv as f64;
+            for i in 0..nu {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / nu as f64;
+                let d = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let solid_angle = theta.sin() * (PI / nv as f64) * (2.0 * PI / nu as f64);
+                integral += map.pdf(&Point::default(), &d) * solid_angle;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+    }
+}
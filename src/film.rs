@@ -0,0 +1,83 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::units::color::Color;
+
+/// Light splatted onto the image at arbitrary positions, by paths traced
+/// from the lights that connect to the camera. Many threads may splat at
+/// once: channels are atomics holding the bits of an `f64`, so splats
+/// never wait on a lock.
+#[derive(Debug)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    splats: Vec<[AtomicU64; 3]>,
+}
+
+/// Adds `value` to the `f64` whose bits `cell` holds.
+fn atomic_add(cell: &AtomicU64, value: f64) {
+    let mut current = cell.load(Ordering::Relaxed);
+    loop {
+        let sum = (f64::from_bits(current) + value).to_bits();
+        match cell.compare_exchange_weak(current, sum, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return,
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            splats: (0..width * height)
+                .map(|_| std::array::from_fn(|_| AtomicU64::new(0.0_f64.to_bits())))
+                .collect(),
+        }
+    }
+
+    /// Adds `color` to the pixel under the image coordinates `(s, t)` that
+    /// `Camera::get_ray` takes, with pixel `(i, j)` spanning
+    /// `[i, i + 1) / (width - 1)` like the renderer samples them. Splats
+    /// outside the image are dropped.
+    pub fn add_splat(&self, (s, t): (f64, f64), color: Color) {
+        let i = (s * (self.width - 1) as f64).floor();
+        let j = (t * (self.height - 1) as f64).floor();
+        if i < 0.0 || j < 0.0 || i >= self.width as f64 || j >= self.height as f64 {
+            return;
+        }
+        let pixel = &self.splats[j as usize * self.width + i as usize];
+        for (channel, cell) in pixel.iter().enumerate() {
+            atomic_add(cell, color[channel]);
+        }
+    }
+
+    /// Splatted light of pixel `(i, j)`, with `j` counted from the bottom,
+    /// to add to the pixel's sum of camera samples. Each camera sample
+    /// traces one light path, whose splats estimate the whole image.
+    pub fn splat(&self, i: usize, j: usize) -> Color {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let [r, g, b] = &self.splats[j * self.width + i];
+        let channel = |cell: &AtomicU64| f64::from_bits(cell.load(Ordering::Relaxed));
+        Color::new(channel(r), channel(g), channel(b)) * ((w - 1.0) * (h - 1.0) / (w * h))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn concurrent_splats_add_up() {
+        let film = Film::new(3, 3);
+        (0..10_000).into_par_iter().for_each(|k| {
+            film.add_splat((0.75, 0.25), Color::new(1.0, 0.5, k as f64 % 2.0));
+        });
+        // Pixels cover half of the image coordinates on a 3 x 3 film, and
+        // splats are scaled by 4 / 9.
+        let expected = Color::new(10_000.0, 5_000.0, 5_000.0) * (4.0 / 9.0);
+        assert!((film.splat(1, 0) - expected).length() < 1e-6);
+        assert_eq!(film.splat(0, 0), Color::default());
+    }
+}
@@ -0,0 +1,76 @@
+use crate::units::color::Color;
+
+/// Fresnel reflectance of a conductor with complex index of refraction
+/// `eta + i k` (relative to the outside medium), for unpolarized light
+/// arriving with `cos_theta_i` to the normal. Evaluated per color channel.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: Color, k: Color) -> Color {
+    let mut reflectance = Color::default();
+    for c in 0..3 {
+        reflectance[c] = fresnel_conductor_channel(cos_theta_i, eta[c], k[c]);
+    }
+    reflectance
+}
+
+fn fresnel_conductor_channel(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta_i.clamp(-1.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_theta_i.abs() * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+/// Exact Fresnel reflectance of a dielectric interface for unpolarized
+/// light. `eta` is the index on the side opposite to the normal divided by
+/// the index on the normal's side; a negative `cos_theta_i` means the light
+/// arrives from the opposite side. Returns one under total internal reflection.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_i = cos_i.min(1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dielectric_normal_incidence() {
+        // ((1.5 - 1) / (1.5 + 1))² from either side.
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(-1.0, 1.0 / 1.5) - 0.04).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dielectric_total_internal_reflection() {
+        assert_eq!(fresnel_dielectric(0.2, 1.0 / 1.5), 1.0);
+    }
+
+    #[test]
+    fn conductor_without_absorption_matches_dielectric() {
+        let reflectance = fresnel_conductor(0.7, Color::new(1.5, 1.5, 1.5), Color::default());
+        assert!((reflectance.x() - fresnel_dielectric(0.7, 1.5)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use crate::units::point::Point;
+
+/// Dense 3D grid of scalar samples, stored with `x` varying fastest.
+/// Samples sit at voxel centers and are trilinearly interpolated.
+#[derive(Debug, Clone)]
+pub struct DensityGrid {
+    nx: usize,
+    ny: usize,
+    nz: usize,
+    data: Vec<f32>,
+    max_value: f64,
+}
+
+/// Densities must be finite and non negative: ratio tracking divides them
+/// by the largest one and expects factors within `[0, 1]`.
+fn valid_density(d: f32) -> bool {
+    d.is_finite() && d >= 0.0
+}
+
+impl DensityGrid {
+    /// Grid of `nx * ny * nz` samples. Panics when a dimension is zero, the
+    /// data doesn't fill the grid or holds negative or non finite values,
+    /// which `from_reader` reports as errors instead.
+    pub fn new(nx: usize, ny: usize, nz: usize, data: Vec<f32>) -> Self {
+        assert!(
+            nx > 0 && ny > 0 && nz > 0,
+            "grid dimensions must be non-zero"
+        );
+        assert_eq!(
+            data.len(),
+            nx * ny * nz,
+            "grid data does not match dimensions"
+        );
+        assert!(
+            data.iter().all(|&d| valid_density(d)),
+            "grid densities must be finite and non negative"
+        );
+        let max_value = data.iter().fold(0.0_f32, |acc, &d| acc.max(d)) as f64;
+        Self {
+            nx,
+            ny,
+            nz,
+            data,
+            max_value,
+        }
+    }
+
+    /// Builds a grid by evaluating `f` at every voxel center, given in `[0, 1]^3`.
+    pub fn from_fn(nx: usize, ny: usize, nz: usize, f: impl Fn(Point) -> f64) -> Self {
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let p = Point::new(
+                        (x as f64 + 0.5) / nx as f64,
+                        (y as f64 + 0.5) / ny as f64,
+                        (z as f64 + 0.5) / nz as f64,
+                    );
+                    data.push(f(p) as f32);
+                }
+            }
+        }
+        Self::new(nx, ny, nz, data)
+    }
+
+    /// Loads a raw grid: three little endian `u32` dimensions (`nx`, `ny`,
+    /// `nz`) followed by `nx * ny * nz` little endian `f32` values.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        let dim = |i: usize| {
+            u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]) as usize
+        };
+        let (nx, ny, nz) = (dim(0), dim(4), dim(8));
+        if nx == 0 || ny == 0 || nz == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "grid has a zero dimension",
+            ));
+        }
+
+        let size = nx
+            .checked_mul(ny)
+            .and_then(|n| n.checked_mul(nz))
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "grid is too large"))?;
+        // The header isn't trusted with an allocation, the data read grows
+        // the buffer as it arrives.
+        let mut bytes = vec![];
+        reader.take(size as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "grid data is truncated",
+            ));
+        }
+        let data: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        if !data.iter().all(|&d| valid_density(d)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "grid densities must be finite and non negative",
+            ));
+        }
+        Ok(Self::new(nx, ny, nz, data))
+    }
+
+    pub fn dimensions(&self) -> (usize, usize, usize) {
+        (self.nx, self.ny, self.nz)
+    }
+
+    pub fn max_value(&self) -> f64 {
+        self.max_value
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.ny + y) * self.nx + x] as f64
+    }
+
+    /// Trilinear lookup at `p` in grid space `[0, 1]^3`. Points outside the
+    /// grid have zero density.
+    pub fn lookup(&self, p: Point) -> f64 {
+        if (0..3).any(|a| p[a] < 0.0 || p[a] > 1.0) {
+            return 0.0;
+        }
+        let axis = |v: f64, n: usize| {
+            let s = (v * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            let i = (s.floor() as usize).min(n.saturating_sub(2));
+            (i, (i + 1).min(n - 1), s - i as f64)
+        };
+        let (x0, x1, fx) = axis(p.x(), self.nx);
+        let (y0, y1, fy) = axis(p.y(), self.ny);
+        let (z0, z1, fz) = axis(p.z(), self.nz);
+
+        let lerp = |a: f64, b: f64, t: f64| a * (1.0 - t) + b * t;
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), fx);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), fx);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), fx);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), fx);
+        lerp(lerp(c00, c10, fy), lerp(c01, c11, fy), fz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_reproduces_linear_field() {
+        let grid = DensityGrid::from_fn(8, 4, 4, |p| 2.0 * p.x() + p.y());
+        let p = Point::new(0.4, 0.55, 0.3);
+        assert!((grid.lookup(p) - (2.0 * 0.4 + 0.55)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn lookup_outside_is_empty() {
+        let grid = DensityGrid::from_fn(2, 2, 2, |_| 1.0);
+        assert_eq!(grid.lookup(Point::new(1.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn reads_raw_grid() {
+        let mut bytes = vec![];
+        for d in [2u32, 1, 1] {
+            bytes.extend_from_slice(&d.to_le_bytes());
+        }
+        for v in [0.25f32, 0.75] {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        let grid = DensityGrid::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(grid.dimensions(), (2, 1, 1));
+        assert_eq!(grid.max_value(), 0.75);
+        assert!((grid.lookup(Point::new(0.5, 0.5, 0.5)) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_truncated_grid() {
+        let mut bytes = vec![];
+        for d in [2u32, 2, 2] {
+            bytes.extend_from_slice(&d.to_le_bytes());
+        }
+        bytes.extend_from_slice(&1.0f32.to_le_bytes());
+        assert!(DensityGrid::from_reader(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_dimensions() {
+        for dims in [[0u32, 1, 1], [u32::MAX, u32::MAX, u32::MAX]] {
+            let mut bytes = vec![];
+            for d in dims {
+                bytes.extend_from_slice(&d.to_le_bytes());
+            }
+            let error = DensityGrid::from_reader(bytes.as_slice()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_negative_and_non_finite_densities() {
+        for bad in [-0.5f32, f32::NAN, f32::INFINITY] {
+            let mut bytes = vec![];
+            for d in [2u32, 1, 1] {
+                bytes.extend_from_slice(&d.to_le_bytes());
+            }
+            for v in [0.25f32, bad] {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            let error = DensityGrid::from_reader(bytes.as_slice()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "finite and non negative")]
+    fn new_panics_on_negative_densities() {
+        DensityGrid::new(2, 1, 1, vec![0.5, -1.0]);
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    aabb::Aabb,
+    material::Material,
+    ray::{HitRecord, Hittable, Ray},
+    units::{
+        point::Point,
+        vec3::{cross_product, dot_product, unit_vector, Vec3},
+    },
+};
+
+/// Cells per side of the coarse blocks used to skip empty space.
+const BLOCK_SIZE: usize = 8;
+
+/// Terrain given by a regular grid of heights over the xz plane. Each cell
+/// is split in two triangles with normals interpolated from the vertices.
+/// Rays walk the grid with a DDA over coarse blocks and then cells, skipping
+/// any block or cell whose height range the ray passes above or below.
+pub struct Heightfield {
+    nx: usize,
+    nz: usize,
+    corner: Point,
+    size: Vec3,
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    cell_ranges: Vec<(f64, f64)>,
+    block_ranges: Vec<(f64, f64)>,
+    bounds: Aabb,
+    material: Material,
+}
+
+impl Heightfield {
+    /// `heights` holds `nx * nz` samples in `[0, 1]`, with x varying fastest.
+    /// The field covers `size.x()` by `size.z()` starting at `corner`, and a
+    /// height of one is raised `size.y()` above it.
+    pub fn new(
+        heights: &[f64],
+        nx: usize,
+        nz: usize,
+        corner: Point,
+        size: Vec3,
+        material: Material,
+    ) -> Self {
+        assert!(nx >= 2 && nz >= 2, "heightfield needs at least 2x2 samples");
+        assert_eq!(heights.len(), nx * nz, "heights do not match dimensions");
+        let heights: Vec<f64> = heights.iter().map(|h| corner.y() + h * size.y()).collect();
+
+        let dx = size.x() / (nx - 1) as f64;
+        let dz = size.z() / (nz - 1) as f64;
+        let height = |i: usize, j: usize| heights[j * nx + i];
+        let mut normals = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(nx - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(nz - 1));
+                let slope_x = (height(i1, j) - height(i0, j)) / ((i1 - i0) as f64 * dx);
+                let slope_z = (height(i, j1) - height(i, j0)) / ((j1 - j0) as f64 * dz);
+                normals.push(unit_vector(Vec3::new(-slope_x, 1.0, -slope_z)));
+            }
+        }
+
+        let (cx, cz) = (nx - 1, nz - 1);
+        let mut cell_ranges = Vec::with_capacity(cx * cz);
+        for j in 0..cz {
+            for i in 0..cx {
+                let corners = [
+                    height(i, j),
+                    height(i + 1, j),
+                    height(i, j + 1),
+                    height(i + 1, j + 1),
+                ];
+                let min = corners.iter().cloned().fold(f64::INFINITY, f64::min);
+                let max = corners.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                cell_ranges.push((min, max));
+            }
+        }
+
+        let (bx, bz) = (cx.div_ceil(BLOCK_SIZE), cz.div_ceil(BLOCK_SIZE));
+        let mut block_ranges = vec![(f64::INFINITY, f64::NEG_INFINITY); bx * bz];
+        for j in 0..cz {
+            for i in 0..cx {
+                let (min, max) = cell_ranges[j * cx + i];
+                let block = &mut block_ranges[(j / BLOCK_SIZE) * bx + i / BLOCK_SIZE];
+                block.0 = block.0.min(min);
+                block.1 = block.1.max(max);
+            }
+        }
+
+        let min_height = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_height = heights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        // Padded so flat fields, whose box has no height, can still be hit.
+        let bounds = Aabb::new(
+            Point::new(corner.x(), min_height, corner.z()),
+            Point::new(corner.x() + size.x(), max_height, corner.z() + size.z()),
+        )
+        .padded(1e-4);
+
+        Self {
+            nx,
+            nz,
+            corner,
+            size,
+            heights,
+            normals,
+            cell_ranges,
+            block_ranges,
+            bounds,
+            material,
+        }
+    }
+
+    /// Builds a heightfield from a binary or ASCII grayscale PGM image, the
+    /// image columns running along x and its rows along z.
+    pub fn load_pgm<P: AsRef<Path>>(
+        path: P,
+        corner: Point,
+        size: Vec3,
+        material: Material,
+    ) -> io::Result<Self> {
+        let (width, height, heights) = read_pgm(BufReader::new(File::open(path)?))?;
+        if width < 2 || height < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "heightfield image must be at least 2x2",
+            ));
+        }
+        Ok(Self::new(&heights, width, height, corner, size, material))
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point {
+        Point::new(
+            self.corner.x() + self.size.x() * i as f64 / (self.nx - 1) as f64,
+            self.heights[j * self.nx + i],
+            self.corner.z() + self.size.z() * j as f64 / (self.nz - 1) as f64,
+        )
+    }
+
+    /// Intersects the two triangles of cell `(i, j)`.
+    fn hit_cell(&self, ray: &Ray, i: usize, j: usize, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let corners = [(i, j), (i + 1, j), (i + 1, j + 1), (i, j + 1)];
+        let mut closest: Option<HitRecord> = None;
+        for triangle in [[0, 1, 2], [0, 2, 3]] {
+            let [a, b, c] = triangle.map(|k| corners[k]);
+            let t_limit = closest.as_ref().map_or(t_max, |record| record.t);
+            let Some((t, w1, w2)) = intersect_triangle(
+                ray,
+                self.vertex(a.0, a.1),
+                self.vertex(b.0, b.1),
+                self.vertex(c.0, c.1),
+            ) else {
+                continue;
+            };
+            if t < t_min || t > t_limit {
+                continue;
+            }
+            let w0 = 1.0 - w1 - w2;
+            let normal = |(i, j): (usize, usize)| self.normals[j * self.nx + i];
+            let outward_normal = unit_vector(w0 * normal(a) + w1 * normal(b) + w2 * normal(c));
+            let point = ray.at(t);
+            let mut record = HitRecord::new(point, outward_normal, t);
+            record.set_face_normal(ray, &outward_normal);
+            record.u = (point.x() - self.corner.x()) / self.size.x();
+            record.v = (point.z() - self.corner.z()) / self.size.z();
+            record.material = self.material.clone();
+            closest = Some(record);
+        }
+        closest
+    }
+}
+
+/// Walks the cells of a 2D grid over the xz plane crossed by `ray` between
+/// `t_start` and `t_end`, front to back, until `visit` returns a hit. Cells
+/// are `cell_x` by `cell_z` wide and indices are clamped to `cells`.
+#[allow(clippy::too_many_arguments)]
+fn dda(
+    ray: &Ray,
+    origin: Point,
+    cell_x: f64,
+    cell_z: f64,
+    cells: ((usize, usize), (usize, usize)),
+    t_start: f64,
+    t_end: f64,
+    mut visit: impl FnMut(usize, usize, f64, f64) -> Option<HitRecord>,
+) -> Option<HitRecord> {
+    let ((x_begin, x_end), (z_begin, z_end)) = cells;
+    let start = ray.at(t_start);
+    let cell_index = |p: f64, o: f64, w: f64, begin: usize, end: usize| {
+        (((p - o) / w).floor().max(0.0) as usize).clamp(begin, end - 1) as i64
+    };
+    let mut ix = cell_index(start.x(), origin.x(), cell_x, x_begin, x_end);
+    let mut iz = cell_index(start.z(), origin.z(), cell_z, z_begin, z_end);
+
+    let axis = |d: f64, o: f64, index: i64, w: f64, grid_origin: f64| {
+        if d > 0.0 {
+            (1, (grid_origin + (index + 1) as f64 * w - o) / d, w / d)
+        } else if d < 0.0 {
+            (-1, (grid_origin + index as f64 * w - o) / d, -w / d)
+        } else {
+            (0, f64::INFINITY, f64::INFINITY)
+        }
+    };
+    let direction = ray.direction();
+    let (step_x, mut next_x, delta_x) =
+        axis(direction.x(), ray.origin().x(), ix, cell_x, origin.x());
+    let (step_z, mut next_z, delta_z) =
+        axis(direction.z(), ray.origin().z(), iz, cell_z, origin.z());
+
+    let mut t = t_start;
+    while t < t_end {
+        let t_exit = next_x.min(next_z).min(t_end);
+        if let Some(record) = visit(ix as usize, iz as usize, t, t_exit) {
+            return Some(record);
+        }
+        t = t_exit;
+        if next_x < next_z {
+            ix += step_x;
+            next_x += delta_x;
+        } else {
+            iz += step_z;
+            next_z += delta_z;
+        }
+        if ix < x_begin as i64 || ix >= x_end as i64 || iz < z_begin as i64 || iz >= z_end as i64 {
+            break;
+        }
+    }
+    None
+}
+
+/// Whether the ray's height over `[t0, t1]` overlaps `range`.
+fn overlaps(ray: &Ray, t0: f64, t1: f64, range: (f64, f64)) -> bool {
+    let y0 = ray.at(t0).y();
+    let y1 = ray.at(t1).y();
+    y0.max(y1) >= range.0 && y0.min(y1) <= range.1
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t0, t1) = self.bounds.hit(ray, t_min, t_max)?;
+        let (cx, cz) = (self.nx - 1, self.nz - 1);
+        let (bx, bz) = (cx.div_ceil(BLOCK_SIZE), cz.div_ceil(BLOCK_SIZE));
+        let cell_x = self.size.x() / cx as f64;
+        let cell_z = self.size.z() / cz as f64;
+        // Pad the cell segments a little so triangles on a cell border are
+        // not missed through rounding.
+        let slack = 1e-9 * (t1 - t0).max(1.0);
+
+        dda(
+            ray,
+            self.corner,
+            cell_x * BLOCK_SIZE as f64,
+            cell_z * BLOCK_SIZE as f64,
+            ((0, bx), (0, bz)),
+            t0,
+            t1,
+            |block_x, block_z, block_t0, block_t1| {
+                let range = self.block_ranges[block_z * bx + block_x];
+                if !overlaps(ray, block_t0, block_t1, range) {
+                    return None;
+                }
+                let x_cells = (block_x * BLOCK_SIZE, ((block_x + 1) * BLOCK_SIZE).min(cx));
+                let z_cells = (block_z * BLOCK_SIZE, ((block_z + 1) * BLOCK_SIZE).min(cz));
+                dda(
+                    ray,
+                    self.corner,
+                    cell_x,
+                    cell_z,
+                    (x_cells, z_cells),
+                    block_t0,
+                    block_t1,
+                    |i, j, cell_t0, cell_t1| {
+                        if !overlaps(ray, cell_t0, cell_t1, self.cell_ranges[j * cx + i]) {
+                            return None;
+                        }
+                        self.hit_cell(
+                            ray,
+                            i,
+                            j,
+                            (cell_t0 - slack).max(t_min),
+                            (cell_t1 + slack).min(t_max),
+                        )
+                    },
+                )
+            },
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+/// Möller–Trumbore ray/triangle test, returning `t` and the barycentric
+/// weights of `b` and `c`.
+fn intersect_triangle(ray: &Ray, a: Point, b: Point, c: Point) -> Option<(f64, f64, f64)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = cross_product(&ray.direction(), &edge2);
+    let det = dot_product(&edge1, &p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin() - a;
+    let w1 = dot_product(&s, &p) * inv_det;
+    if !(0.0..=1.0).contains(&w1) {
+        return None;
+    }
+    let q = cross_product(&s, &edge1);
+    let w2 = dot_product(&ray.direction(), &q) * inv_det;
+    if w2 < 0.0 || w1 + w2 > 1.0 {
+        return None;
+    }
+    Some((dot_product(&edge2, &q) * inv_det, w1, w2))
+}
+
+/// Reads a grayscale PGM image (`P2` or `P5`, 8 or 16 bit), returning its
+/// width, height and samples normalised to `[0, 1]`.
+pub fn read_pgm<R: BufRead>(mut reader: R) -> io::Result<(usize, usize, Vec<f64>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    // The header is four whitespace separated tokens, with `#` comments.
+    let mut tokens = vec![];
+    while tokens.len() < 4 {
+        let mut line = vec![];
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Err(invalid("truncated PGM header"));
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.split('#').next().unwrap_or("");
+        tokens.extend(line.split_whitespace().map(str::to_string));
+    }
+    if tokens.len() > 4 && tokens[0] == "P5" {
+        return Err(invalid("unexpected data after PGM header"));
+    }
+    let number = |token: &str| {
+        token
+            .parse::<usize>()
+            .map_err(|_| invalid("invalid number in PGM header"))
+    };
+    let width = number(&tokens[1])?;
+    let height = number(&tokens[2])?;
+    let max_value = number(&tokens[3])?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("invalid PGM maximum value"));
+    }
+    let count = width
+        .checked_mul(height)
+        .ok_or_else(|| invalid("PGM image is too large"))?;
+    let scale = 1.0 / max_value as f64;
+
+    let samples: Vec<f64> = match tokens[0].as_str() {
+        "P2" => {
+            let mut rest = String::new();
+            reader.read_to_string(&mut rest)?;
+            let inline = tokens[4..].iter().map(String::as_str);
+            inline
+                .chain(rest.split_whitespace())
+                .take(count)
+                .map(|token| number(token).map(|v| v as f64 * scale))
+                .collect::<io::Result<_>>()?
+        }
+        "P5" => {
+            let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+            let size = count
+                .checked_mul(bytes_per_sample)
+                .ok_or_else(|| invalid("PGM image is too large"))?;
+            let mut bytes = vec![];
+            reader.take(size as u64).read_to_end(&mut bytes)?;
+            bytes
+                .chunks_exact(bytes_per_sample)
+                .map(|b| match b {
+                    [v] => *v as f64 * scale,
+                    [hi, lo] => u16::from_be_bytes([*hi, *lo]) as f64 * scale,
+                    _ => unreachable!(),
+                })
+                .collect()
+        }
+        _ => return Err(invalid("not a grayscale PGM image")),
+    };
+    if samples.len() != count {
+        return Err(invalid("truncated PGM data"));
+    }
+    Ok((width, height, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use std::sync::Arc;
+
+    fn ramp(n: usize) -> Heightfield {
+        // Heights rise linearly with x, from 0 to 1 over a 10 x 10 field.
+        let heights: Vec<f64> = (0..n * n)
+            .map(|k| (k % n) as f64 / (n - 1) as f64)
+            .collect();
+        Heightfield::new(
+            &heights,
+            n,
+            n,
+            Point::new(0.0, 0.0, 0.0),
+            Vec3::new(10.0, 1.0, 10.0),
+            Arc::new(Lambertian::default()),
+        )
+    }
+
+    #[test]
+    fn hits_sloped_terrain_from_above() {
+        let field = ramp(50);
+        let ray = Ray::new(Point::new(7.3, 5.0, 4.1), Vec3::new(0.0, -1.0, 0.0));
+        let record = field.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((record.point.y() - 0.73).abs() < 1e-9);
+        assert!((record.u - 0.73).abs() < 1e-9);
+        assert!((record.v - 0.41).abs() < 1e-9);
+        let expected_normal = unit_vector(Vec3::new(-0.1, 1.0, 0.0));
+        assert!((record.normal - expected_normal).length() < 1e-9);
+    }
+
+    #[test]
+    fn grazing_ray_hits_where_terrain_rises() {
+        let field = ramp(50);
+        // Travels along x at height 0.5, hitting the ramp at x = 5.
+        let ray = Ray::new(Point::new(-1.0, 0.5, 3.3), Vec3::new(1.0, 0.0, 0.0));
+        let record = field.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((record.point.x() - 5.0).abs() < 1e-9);
+
+        let above = Ray::new(Point::new(-1.0, 1.5, 3.3), Vec3::new(1.0, 0.0, 0.0));
+        assert!(field.hit(&above, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn reads_ascii_pgm() {
+        let pgm = b"P2\n# comment\n3 2\n255\n0 51 102\n153 204 255\n";
+        let (width, height, samples) = read_pgm(&pgm[..]).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(samples[1], 0.2);
+        assert_eq!(samples[5], 1.0);
+    }
+
+    #[test]
+    fn reads_binary_pgm() {
+        let mut pgm = b"P5 2 1 255\n".to_vec();
+        pgm.extend_from_slice(&[0, 255]);
+        let (_, _, samples) = read_pgm(&pgm[..]).unwrap();
+        assert_eq!(samples, vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn hits_flat_terrain() {
+        for level in [0.0, 0.5] {
+            let field = Heightfield::new(
+                &[level; 16],
+                4,
+                4,
+                Point::new(0.0, 0.0, 0.0),
+                Vec3::new(10.0, 1.0, 10.0),
+                Arc::new(Lambertian::default()),
+            );
+            let ray = Ray::new(Point::new(2.0, 5.0, 2.0), Vec3::new(1.0, -2.0, 1.0));
+            let record = field.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert!((record.point.y() - level).abs() < 1e-9);
+            assert!((record.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_pgm() {
+        let pgm = format!("P5 {} {} 65535\n", usize::MAX, 2);
+        let error = read_pgm(pgm.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // A header promising more than the data holds is truncated, not
+        // allocated up front.
+        let error = read_pgm(&b"P5 100000 100000 255\n\0"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
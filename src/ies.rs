@@ -0,0 +1,295 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::PI;
+
+/// Radiometric intensity of one candela at 555 nm, in W/sr.
+const WATTS_PER_CANDELA: f64 = 1.0 / 683.0;
+
+/// Goniometric intensity distribution of a luminaire, read from an IES
+/// LM-63 file. Only type C photometry is supported: vertical angles go from
+/// the nadir at 0° to straight up at 180°, horizontal angles turn around the
+/// vertical axis.
+#[derive(Clone, Debug)]
+pub struct IesProfile {
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    /// Candelas for each horizontal angle, one per vertical angle.
+    candela: Vec<f64>,
+    scale: f64,
+}
+
+impl IesProfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        // Keywords up to the TILT line, then numbers separated by blanks or
+        // commas. Some files aren't valid UTF-8 in their keywords.
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents)?;
+        let contents = String::from_utf8_lossy(&contents);
+        let mut lines = contents.lines();
+        let tilt = loop {
+            match lines.next() {
+                Some(line) if line.trim_start().starts_with("TILT=") => {
+                    break line.trim_start()["TILT=".len()..].trim().to_string();
+                }
+                Some(_) => {}
+                None => return Err(invalid("IES file without a TILT line")),
+            }
+        };
+        let mut numbers = Numbers {
+            tokens: lines
+                .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+                .filter(|token| !token.is_empty())
+                .collect(),
+            position: 0,
+        };
+
+        if tilt == "INCLUDE" {
+            // Lamp tilt factors, which only matter for tilted lamps.
+            numbers.next()?;
+            let count = numbers.count(2)?;
+            for _ in 0..2 * count {
+                numbers.next()?;
+            }
+        }
+
+        let _lamps = numbers.next()?;
+        let _lumens_per_lamp = numbers.next()?;
+        let multiplier = numbers.next()?;
+        let vertical_count = numbers.count(1)?;
+        let horizontal_count = numbers.count(1)?;
+        let photometric_type = numbers.next()?;
+        // Units and luminous opening dimensions.
+        for _ in 0..4 {
+            numbers.next()?;
+        }
+        let ballast_factor = numbers.next()?;
+        let ballast_lamp_factor = numbers.next()?;
+        let _input_watts = numbers.next()?;
+        if photometric_type != 1.0 {
+            return Err(invalid("only type C IES photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid("IES file without angles"));
+        }
+        let candela_count = vertical_count
+            .checked_mul(horizontal_count)
+            .filter(|&n| n <= numbers.left())
+            .ok_or_else(|| invalid("truncated IES file"))?;
+
+        let vertical = (0..vertical_count)
+            .map(|_| numbers.next())
+            .collect::<io::Result<Vec<f64>>>()?;
+        let horizontal = (0..horizontal_count)
+            .map(|_| numbers.next())
+            .collect::<io::Result<Vec<f64>>>()?;
+        let factor = multiplier * ballast_factor * ballast_lamp_factor;
+        let candela = (0..candela_count)
+            .map(|_| numbers.next().map(|c| c * factor))
+            .collect::<io::Result<Vec<f64>>>()?;
+        let increasing = |angles: &[f64]| angles.windows(2).all(|w| w[0] < w[1]);
+        if !increasing(&vertical) || !increasing(&horizontal) {
+            return Err(invalid("IES angles must increase"));
+        }
+        Ok(Self {
+            vertical,
+            horizontal,
+            candela,
+            scale: WATTS_PER_CANDELA,
+        })
+    }
+
+    /// Sets the intensity in scene units one candela stands for, 1/683 W/sr
+    /// by default.
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Intensity in scene units towards `vertical` degrees from the nadir
+    /// and `horizontal` degrees around it, interpolated between the
+    /// measured angles.
+    pub fn intensity(&self, vertical: f64, horizontal: f64) -> f64 {
+        let (first, last) = (self.vertical[0], self.vertical[self.vertical.len() - 1]);
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+        let (v0, v1, tv) = bracket(&self.vertical, vertical);
+        let horizontal = self.fold_horizontal(horizontal);
+        let (h0, h1, th) = bracket(&self.horizontal, horizontal);
+        let n = self.vertical.len();
+        let at = |h: usize, v: usize| self.candela[h * n + v];
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let candela = lerp(
+            lerp(at(h0, v0), at(h0, v1), tv),
+            lerp(at(h1, v0), at(h1, v1), tv),
+            th,
+        );
+        candela.max(0.0) * self.scale
+    }
+
+    /// Intensity averaged over every direction, in scene units.
+    pub fn average_intensity(&self) -> f64 {
+        let (nv, nh) = (90, 72);
+        let mut sum = 0.0;
+        for j in 0..nv {
+            let theta = 180.0 * (j as f64 + 0.5) / nv as f64;
+            let weight = theta.to_radians().sin() * (PI / nv as f64) * (2.0 * PI / nh as f64);
+            for i in 0..nh {
+                let phi = 360.0 * (i as f64 + 0.5) / nh as f64;
+                sum += self.intensity(theta, phi) * weight;
+            }
+        }
+        sum / (4.0 * PI)
+    }
+
+    /// Horizontal angle brought into the measured range, using the symmetry
+    /// the last measured angle implies.
+    fn fold_horizontal(&self, angle: f64) -> f64 {
+        let angle = angle.rem_euclid(360.0);
+        let first = self.horizontal[0];
+        let last = self.horizontal[self.horizontal.len() - 1];
+        if self.horizontal.len() == 1 {
+            // Same in every direction around the axis.
+            first
+        } else if first == 0.0 && last == 90.0 {
+            // Quadrants mirror each other.
+            let angle = if angle > 180.0 { 360.0 - angle } else { angle };
+            if angle > 90.0 {
+                180.0 - angle
+            } else {
+                angle
+            }
+        } else if first == 0.0 && last == 180.0 {
+            // Mirrored about the 0–180 plane.
+            if angle > 180.0 {
+                360.0 - angle
+            } else {
+                angle
+            }
+        } else if first == 90.0 && last == 270.0 {
+            // Mirrored about the 90–270 plane.
+            if angle < 90.0 {
+                180.0 - angle
+            } else if angle > 270.0 {
+                540.0 - angle
+            } else {
+                angle
+            }
+        } else {
+            angle.clamp(first, last)
+        }
+    }
+}
+
+/// Indices of the measured angles around `angle` and how far it is between
+/// them.
+fn bracket(angles: &[f64], angle: f64) -> (usize, usize, f64) {
+    if angles.len() == 1 {
+        return (0, 0, 0.0);
+    }
+    let i = angles
+        .partition_point(|&a| a <= angle)
+        .clamp(1, angles.len() - 1)
+        - 1;
+    let t = ((angle - angles[i]) / (angles[i + 1] - angles[i])).clamp(0.0, 1.0);
+    (i, i + 1, t)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Numbers following the TILT line, read in order.
+struct Numbers<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+}
+
+impl Numbers<'_> {
+    fn next(&mut self) -> io::Result<f64> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| invalid("truncated IES file"))?;
+        self.position += 1;
+        token.parse().map_err(|_| invalid("bad number in IES file"))
+    }
+
+    /// Numbers not read yet.
+    fn left(&self) -> usize {
+        self.tokens.len() - self.position
+    }
+
+    /// Reads how many entries of `size` numbers follow, which the numbers
+    /// left must be able to hold.
+    fn count(&mut self, size: usize) -> io::Result<usize> {
+        let count = self.next()?;
+        if count < 0.0 || count.fract() != 0.0 || count > (self.left() / size) as f64 {
+            return Err(invalid("bad count in IES file"));
+        }
+        Ok(count as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bilaterally symmetric downlight, brighter towards 0° than 180°.
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] test
+[MANUFAC] nobody
+TILT=NONE
+1 1000 2.0 3 3 1 1 0.1 0.1 0.0
+1.0 1.0 60
+0 45 90
+0 90 180
+100 50 0
+100, 40, 0
+100 30 0
+";
+
+    #[test]
+    fn parses_and_interpolates() {
+        let profile = IesProfile::read(DOWNLIGHT.as_bytes())
+            .unwrap()
+            .with_scale(1.0);
+        // The multiplier doubles every value.
+        assert!((profile.intensity(0.0, 0.0) - 200.0).abs() < 1e-9);
+        assert!((profile.intensity(45.0, 0.0) - 100.0).abs() < 1e-9);
+        assert!((profile.intensity(22.5, 0.0) - 150.0).abs() < 1e-9);
+        assert!((profile.intensity(45.0, 135.0) - 70.0).abs() < 1e-9);
+        // Mirrored about the 0–180 plane, dark above the horizon.
+        assert!((profile.intensity(45.0, 270.0) - 80.0).abs() < 1e-9);
+        assert_eq!(profile.intensity(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn rejects_missing_tilt_and_other_photometry() {
+        assert!(IesProfile::read("IESNA:LM-63-2002\n1 2 3\n".as_bytes()).is_err());
+        let type_b = DOWNLIGHT.replace("3 3 1 1", "3 3 2 1");
+        assert!(IesProfile::read(type_b.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn rejects_counts_beyond_the_data() {
+        for (from, to) in [
+            ("3 3 1 1", "1e300 3 1 1"),
+            ("3 3 1 1", "4294967296 4294967296 1 1"),
+            ("3 3 1 1", "-1 3 1 1"),
+            ("TILT=NONE", "TILT=INCLUDE\n1 1e19"),
+        ] {
+            let corrupt = DOWNLIGHT.replace(from, to);
+            let error = IesProfile::read(corrupt.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
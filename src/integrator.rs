@@ -0,0 +1,323 @@
+use crate::{
+    light::{LightSample, Lights},
+    phase::sample_henyey_greenstein,
+    ray::{HitRecord, Hittables, Ray},
+    sampling::{balance_heuristic, power_heuristic},
+    scene::Scene,
+    units::{
+        color::Color,
+        vec3::{random_f64, unit_vector},
+    },
+};
+
+/// How the two estimates of light arriving at a surface, from sampling the
+/// BSDF and from sampling the lights, are combined.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Heuristic {
+    Balance,
+    Power,
+}
+
+impl Heuristic {
+    fn weight(self, f_pdf: f64, g_pdf: f64) -> f64 {
+        match self {
+            Heuristic::Balance => balance_heuristic(f_pdf, g_pdf),
+            Heuristic::Power => power_heuristic(f_pdf, g_pdf),
+        }
+    }
+}
+
+/// Path tracing strategy. All of them converge to the same image, they
+/// differ in noise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Integrator {
+    /// Paths only follow sampled BSDFs and find lights by chance, so delta
+    /// lights don't show at all.
+    Bsdf,
+    /// Every non specular bounce also samples one light with a shadow ray,
+    /// and ignores emission the scattered ray finds where a light could
+    /// have been sampled.
+    NextEvent,
+    /// Light and BSDF sampling combined with multiple importance sampling,
+    /// good both for small bright lights and large ones on glossy surfaces.
+    Mis(Heuristic),
+}
+
+impl Default for Integrator {
+    fn default() -> Self {
+        Integrator::Mis(Heuristic::Power)
+    }
+}
+
+impl Integrator {
+    /// Radiance arriving along the camera ray `r` from the `scene`.
+    pub fn ray_color(&self, r: &Ray, scene: &Scene, depth: i32) -> Color {
+        self.radiance(r, scene, depth, Origin::Camera)
+    }
+
+    fn radiance(&self, r: &Ray, scene: &Scene, depth: i32, origin: Origin) -> Color {
+        if depth < 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let (world, lights) = (&scene.world, &scene.lights);
+        let rec = match world.hit(r, 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => {
+                if let (Origin::Camera, Some(backplate)) = (origin, &scene.backplate) {
+                    return backplate.radiance(&r.direction());
+                }
+                return scene.background.radiance(r)
+                    + lights.escaped(r) * self.emission_weight(r, lights, origin);
+            }
+        };
+
+        // Absorption of the medium the ray travelled through to get here, or
+        // a step of a random walk when the medium scatters before the hit.
+        let medium = r.interior().current();
+        let length = r.direction().length();
+        let flight = medium.sample_distance(rec.t * length, random_f64(), random_f64());
+        if let (Some(distance), Some(scattering)) = (flight.distance, medium.scattering) {
+            let u = (random_f64(), random_f64());
+            let direction = sample_henyey_greenstein(&unit_vector(r.direction()), scattering.g, u);
+            let scattered = r.spawn(r.at(distance / length), direction);
+            return self.radiance(&scattered, scene, depth - 1, Origin::Unsampled) * flight.weight;
+        }
+        let transmittance = flight.weight;
+
+        let emitted = rec.material.emitted(r, &rec) * self.emission_weight(r, lights, origin);
+        let sample = rec
+            .material
+            .sample(r, &rec, random_f64(), (random_f64(), random_f64()));
+        let sample = match sample {
+            Some(sample) => sample,
+            None => {
+                // Sampling may fail where the BSDF is still lit, like rough
+                // metals reflecting a sampled micro normal below the surface.
+                if rec.material.lobes().has_non_specular() {
+                    let direct = self.sample_lights(r, &rec, world, lights);
+                    return (emitted + direct) * transmittance;
+                }
+                return emitted * transmittance;
+            }
+        };
+        let scattered = sample.scattered;
+        // Delta lobes can't be lit by sampled lights, they only find emission
+        // by scattering into it.
+        let (direct, next) = if sample.lobe.is_specular() {
+            (Color::new(0.0, 0.0, 0.0), Origin::Unsampled)
+        } else {
+            let direct = self.sample_lights(r, &rec, world, lights);
+            (direct, Origin::Bsdf(sample.pdf))
+        };
+        let indirect = self.radiance(&scattered, scene, depth - 1, next) * sample.weight;
+        (emitted + direct + indirect) * transmittance
+    }
+
+    /// Share of the emission found along `r` that BSDF sampling accounts for.
+    fn emission_weight(&self, r: &Ray, lights: &Lights, origin: Origin) -> f64 {
+        let bsdf_pdf = match origin {
+            Origin::Bsdf(bsdf_pdf) => bsdf_pdf,
+            _ => return 1.0,
+        };
+        match self {
+            Integrator::Bsdf => 1.0,
+            Integrator::NextEvent => {
+                if lights.pdf(&r.origin(), &r.direction()) > 0.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            Integrator::Mis(heuristic) => {
+                let light_pdf = lights.pdf(&r.origin(), &r.direction());
+                heuristic.weight(bsdf_pdf, light_pdf)
+            }
+        }
+    }
+
+    /// Next event estimation: light from one of the lights reflected by the
+    /// surface at `rec` towards the origin of `r`, if nothing blocks the way.
+    pub(crate) fn sample_lights(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &Hittables,
+        lights: &Lights,
+    ) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if *self == Integrator::Bsdf {
+            return black;
+        }
+        let u = (random_f64(), random_f64());
+        match lights.sample(&rec.point, random_f64(), u) {
+            Some(LightSample::Delta {
+                direction,
+                distance,
+                irradiance,
+            }) => {
+                let bsdf = rec.material.eval(r, rec, &direction);
+                if bsdf == black {
+                    return black;
+                }
+                let shadow = r.spawn(rec.point, direction);
+                if world.hit(&shadow, 0.001, distance).is_some() {
+                    return black;
+                }
+                if distance.is_infinite() {
+                    return irradiance * bsdf;
+                }
+                let transmittance = shadow.interior().current().transmittance(distance);
+                irradiance * bsdf * transmittance
+            }
+            Some(LightSample::Direction(direction)) => {
+                let light_pdf = lights.pdf(&rec.point, &direction);
+                if light_pdf <= 0.0 {
+                    return black;
+                }
+                let bsdf = rec.material.eval(r, rec, &direction);
+                if bsdf == black {
+                    return black;
+                }
+                let weight = match self {
+                    Integrator::Mis(heuristic) => {
+                        heuristic.weight(light_pdf, rec.material.pdf(r, rec, &direction))
+                    }
+                    _ => 1.0,
+                };
+                // The shadow ray reports whatever light it sees first, which
+                // the density of sampling any of the lights accounts for.
+                let shadow = r.spawn(rec.point, direction);
+                let radiance = match world.hit(&shadow, 0.001, f64::INFINITY) {
+                    Some(light) => {
+                        let transmittance = shadow
+                            .interior()
+                            .current()
+                            .transmittance(light.t * direction.length());
+                        light.material.emitted(&shadow, &light) * transmittance
+                    }
+                    None => lights.escaped(&shadow),
+                };
+                radiance * bsdf * (weight / light_pdf)
+            }
+            None => black,
+        }
+    }
+}
+
+/// How a traced ray came about.
+#[derive(Copy, Clone, Debug)]
+enum Origin {
+    Camera,
+    /// Specular or volume scattering, which light sampling doesn't handle.
+    Unsampled,
+    /// Sampled from a BSDF with the given density.
+    Bsdf(f64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        light::{DirectionalLight, PointLight},
+        material::{Conductor, DiffuseLight, Lambertian, Material},
+        microfacet::Distribution,
+        quad::Quad,
+        sphere::Sphere,
+        units::{point::Point, vec3::Vec3},
+        PI,
+    };
+
+    /// A diffuse floor and a glossy wall lit by a small sphere, a large quad
+    /// facing down and a sun.
+    fn scene() -> Scene {
+        let floor: Material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let wall: Material = Arc::new(Conductor::gold(Distribution::Ggx, 0.2));
+        let light: Material = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+        let sphere = || Sphere::new(Point::new(0.0, 2.0, 0.0), 0.3, light.clone());
+        let quad = || {
+            Quad::new(
+                Point::new(0.0, 3.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                light.clone(),
+            )
+        };
+
+        let mut world = Hittables::new();
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -5.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            floor,
+        )));
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, 5.0),
+            Vec3::new(0.0, 10.0, 0.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            wall,
+        )));
+        world.add(Box::new(sphere()));
+        world.add(Box::new(quad()));
+        let mut lights = Lights::new();
+        lights.add_area(Box::new(sphere()));
+        lights.add_area(Box::new(quad()));
+        lights.add(Box::new(
+            DirectionalLight::new(Vec3::new(-0.3, -1.0, 0.5), Color::new(2.0, 2.0, 2.0))
+                .with_angular_diameter(40.0),
+        ));
+        Scene::new(world, lights)
+    }
+
+    #[test]
+    fn strategies_agree() {
+        let scene = scene();
+        let integrators = [
+            Integrator::Bsdf,
+            Integrator::NextEvent,
+            Integrator::Mis(Heuristic::Balance),
+            Integrator::Mis(Heuristic::Power),
+        ];
+        for direction in [Vec3::new(0.3, -0.5, 1.0), Vec3::new(0.0, 0.2, 1.0)] {
+            let r = Ray::new(Point::new(0.0, 1.0, -4.0), direction);
+            let estimates: Vec<Color> = integrators
+                .iter()
+                .map(|integrator| {
+                    let n = 40000;
+                    let sum = (0..n).fold(Color::default(), |acc, _| {
+                        acc + integrator.ray_color(&r, &scene, 4)
+                    });
+                    sum / n as f64
+                })
+                .collect();
+            for estimate in &estimates[1..] {
+                let difference = (*estimate - estimates[0]).length();
+                assert!(difference < 0.03 * estimates[0].length(), "{:?}", estimates);
+            }
+        }
+    }
+
+    #[test]
+    fn point_light_direct_lighting() {
+        let floor: Material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = Hittables::new();
+        world.add(Box::new(Quad::new(
+            Point::new(-5.0, 0.0, -5.0),
+            Vec3::new(0.0, 0.0, 10.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            floor,
+        )));
+        let mut lights = Lights::new();
+        lights.add(Box::new(PointLight::new(
+            Point::new(0.0, 2.0, 0.0),
+            Color::new(8.0, 8.0, 8.0),
+        )));
+        let scene = Scene::new(world, lights);
+        // Without bounces only the light sampled at the floor is left.
+        let r = Ray::new(Point::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let color = Integrator::default().ray_color(&r, &scene, 0);
+        assert!((color.x() - 0.5 / PI * 8.0 / 4.0).abs() < 1e-9);
+        assert_eq!(Integrator::Bsdf.ray_color(&r, &scene, 0), Color::default());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{constant_medium::sample_free_flight, spectrum::Dispersion, units::color::Color};
+
+/// Deepest nesting of dielectrics a ray keeps track of.
+const MAX_NESTING: usize = 8;
+
+/// Optical properties of the inside of a dielectric object.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Interior {
+    pub ir: f64,
+    pub absorption: Color,
+    pub dispersion: Option<Dispersion>,
+    /// Particles scattering light inside, turning the object translucent.
+    pub scattering: Option<Scattering>,
+}
+
+/// Homogeneous scattering inside an interior.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Scattering {
+    /// Scattering coefficient per unit of distance.
+    pub coefficient: Color,
+    /// Henyey–Greenstein asymmetry of the phase function.
+    pub g: f64,
+}
+
+/// Outcome of sampling how far a path travels through an interior.
+#[derive(Copy, Clone, Debug)]
+pub struct MediumSample {
+    /// Distance to a scattering event, `None` when the path reaches the
+    /// next surface.
+    pub distance: Option<f64>,
+    /// Transmittance, times the scattering coefficient at an event, over
+    /// the density of the sampled outcome.
+    pub weight: Color,
+}
+
+impl Default for Interior {
+    /// Vacuum, or air for all practical purposes.
+    fn default() -> Self {
+        Self {
+            ir: 1.0,
+            absorption: Color::default(),
+            dispersion: None,
+            scattering: None,
+        }
+    }
+}
+
+impl Interior {
+    pub fn new(ir: f64, absorption: Color) -> Self {
+        Self {
+            ir,
+            absorption,
+            dispersion: None,
+            scattering: None,
+        }
+    }
+
+    /// Index of refraction seen by a path, which depends on its wavelength
+    /// once it has been split by a dispersive interface.
+    pub fn ir_at(&self, wavelength: Option<f64>) -> f64 {
+        match (self.dispersion, wavelength) {
+            (Some(dispersion), Some(lambda)) => dispersion.ior(lambda),
+            _ => self.ir,
+        }
+    }
+
+    /// Beer–Lambert transmittance over `distance` travelled inside.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let a = self.absorption;
+        Color::new(
+            (-a.x() * distance).exp(),
+            (-a.y() * distance).exp(),
+            (-a.z() * distance).exp(),
+        )
+    }
+
+    /// Samples a free flight towards a surface `max_distance` away. Distances
+    /// follow the extinction of a channel picked with `u_channel`, weighted
+    /// by the average density over all channels so colored media converge
+    /// in every channel. Without scattering the path always reaches the
+    /// surface, only absorbed on the way.
+    pub fn sample_distance(&self, max_distance: f64, u_channel: f64, u: f64) -> MediumSample {
+        let scattering = match self.scattering {
+            Some(scattering) => scattering.coefficient,
+            None => {
+                return MediumSample {
+                    distance: None,
+                    weight: self.transmittance(max_distance),
+                }
+            }
+        };
+        let extinction = self.absorption + scattering;
+        let channel = ((u_channel * 3.0) as usize).min(2);
+        let distance = sample_free_flight(extinction[channel], u);
+        let scatters = distance < max_distance;
+        let distance = distance.min(max_distance);
+        let transmittance = Color::new(
+            (-extinction.x() * distance).exp(),
+            (-extinction.y() * distance).exp(),
+            (-extinction.z() * distance).exp(),
+        );
+        // Density of the outcome averaged over the channel choice: a
+        // scattering event at `distance`, or making it to the surface.
+        let density = if scatters {
+            extinction * transmittance
+        } else {
+            transmittance
+        };
+        let pdf = (density.x() + density.y() + density.z()) / 3.0;
+        if pdf <= 0.0 {
+            return MediumSample {
+                distance: None,
+                weight: Color::default(),
+            };
+        }
+        if scatters {
+            MediumSample {
+                distance: Some(distance),
+                weight: transmittance * scattering / pdf,
+            }
+        } else {
+            MediumSample {
+                distance: None,
+                weight: transmittance / pdf,
+            }
+        }
+    }
+}
+
+/// Stack of the dielectric interiors a ray is inside of, innermost on top,
+/// so nested objects (an ice cube in water) refract against the right
+/// index and absorb with the right coefficient.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct InteriorStack {
+    entries: [Interior; MAX_NESTING],
+    len: usize,
+}
+
+impl InteriorStack {
+    /// The medium the ray is currently travelling through.
+    pub fn current(&self) -> Interior {
+        if self.len == 0 {
+            Interior::default()
+        } else {
+            self.entries[self.len - 1]
+        }
+    }
+
+    /// The medium found on the other side when leaving `interior`.
+    pub fn outside(&self, interior: &Interior) -> Interior {
+        self.exiting(interior).current()
+    }
+
+    /// The stack after refracting into `interior`. Beyond the maximum
+    /// nesting the innermost entry is replaced.
+    pub fn entering(&self, interior: Interior) -> Self {
+        let mut stack = *self;
+        if stack.len == MAX_NESTING {
+            stack.len -= 1;
+        }
+        stack.entries[stack.len] = interior;
+        stack.len += 1;
+        stack
+    }
+
+    /// The stack after refracting out of `interior`, removing its innermost
+    /// occurrence. Leaving a medium we never entered changes nothing.
+    pub fn exiting(&self, interior: &Interior) -> Self {
+        let mut stack = *self;
+        if let Some(index) = stack.entries[..stack.len]
+            .iter()
+            .rposition(|entry| entry == interior)
+        {
+            stack.entries.copy_within(index + 1..stack.len, index);
+            stack.len -= 1;
+        }
+        stack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_media() {
+        let water = Interior::new(1.33, Color::new(0.1, 0.05, 0.0));
+        let ice = Interior::new(1.31, Color::default());
+        let stack = InteriorStack::default().entering(water).entering(ice);
+        assert_eq!(stack.current(), ice);
+        assert_eq!(stack.outside(&ice), water);
+
+        let stack = stack.exiting(&ice);
+        assert_eq!(stack.current(), water);
+        assert_eq!(stack.outside(&water), Interior::default());
+    }
+
+    #[test]
+    fn leaving_an_outer_medium_first() {
+        // Overlapping objects may be left in a different order than entered.
+        let glass = Interior::new(1.5, Color::default());
+        let water = Interior::new(1.33, Color::default());
+        let stack = InteriorStack::default()
+            .entering(glass)
+            .entering(water)
+            .exiting(&glass);
+        assert_eq!(stack.current(), water);
+    }
+
+    #[test]
+    fn free_flight_sampling_is_unbiased() {
+        let scattering = Color::new(2.0, 0.5, 0.0);
+        let medium = Interior {
+            scattering: Some(Scattering {
+                coefficient: scattering,
+                g: 0.0,
+            }),
+            ..Interior::new(1.3, Color::new(0.5, 1.0, 3.0))
+        };
+        let max_distance = 0.8;
+        let (mut surface, mut events) = (Color::default(), Color::default());
+        let n = 3000;
+        for i in 0..3 {
+            for j in 0..n {
+                let u_channel = (i as f64 + 0.5) / 3.0;
+                let u = (j as f64 + 0.5) / n as f64;
+                let sample = medium.sample_distance(max_distance, u_channel, u);
+                match sample.distance {
+                    Some(_) => events += sample.weight,
+                    None => surface += sample.weight,
+                }
+            }
+        }
+        let (surface, events) = (surface / (3 * n) as f64, events / (3 * n) as f64);
+        let extinction = medium.absorption + scattering;
+        for c in 0..3 {
+            let transmittance = (-extinction[c] * max_distance).exp();
+            let scattered = scattering[c] * (1.0 - transmittance) / extinction[c];
+            assert!((surface[c] - transmittance).abs() < 1e-2);
+            assert!((events[c] - scattered).abs() < 1e-2);
+        }
+    }
+}
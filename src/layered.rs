@@ -0,0 +1,218 @@
+use crate::{
+    fresnel::fresnel_dielectric,
+    material::{shading_frame, Bsdf, BsdfSample, Lobes, Material},
+    microfacet::{Distribution, Microfacet},
+    onb::Onb,
+    ray::{HitRecord, Ray},
+    units::{
+        color::Color,
+        vec3::{dot_product, reflect, unit_vector, Vec3},
+    },
+};
+
+/// A clear dielectric coating over any base material, like varnish on wood
+/// or the clear coat of car paint.
+///
+/// The coating reflects with a microfacet lobe. Light reaching the base
+/// crosses the coating twice, losing the Fresnel reflected part on the way
+/// in and out and being absorbed along the refracted path through a layer of
+/// the given thickness. Since the base never receives more than what the
+/// coating lets through, the layering conserves the energy of the base.
+/// Light reflected back down at the inside of the coating is dropped.
+#[derive(Debug, Clone)]
+pub struct Layered {
+    base: Material,
+    ior: f64,
+    microfacet: Microfacet,
+    absorption: Color,
+    thickness: f64,
+}
+
+impl Layered {
+    /// Coating with index of refraction `ior` whose surface has microfacet
+    /// width `alpha`, zero giving a polished coat.
+    pub fn new(base: Material, ior: f64, distribution: Distribution, alpha: f64) -> Self {
+        Self {
+            base,
+            ior,
+            microfacet: Microfacet::new(distribution, alpha),
+            absorption: Color::default(),
+            thickness: 0.0,
+        }
+    }
+    /// Tinted coating absorbing light with the given coefficient per unit of
+    /// distance along its path through a layer `thickness` thick.
+    pub fn with_absorption(mut self, absorption: Color, thickness: f64) -> Self {
+        self.absorption = absorption;
+        self.thickness = thickness;
+        self
+    }
+
+    /// Share of light making it through the coating to the base and back, for
+    /// local directions `wo` and `wi`.
+    fn transmittance(&self, wo: &Vec3, wi: &Vec3) -> Color {
+        let cos_o = wo.z().abs();
+        let cos_i = wi.z().abs();
+        let fresnel = (1.0 - fresnel_dielectric(cos_o, self.ior))
+            * (1.0 - fresnel_dielectric(cos_i, self.ior));
+        let path =
+            self.thickness * (1.0 / self.refracted_cos(cos_o) + 1.0 / self.refracted_cos(cos_i));
+        Color::new(
+            (-self.absorption.x() * path).exp(),
+            (-self.absorption.y() * path).exp(),
+            (-self.absorption.z() * path).exp(),
+        ) * fresnel
+    }
+
+    /// Cosine of a direction with the normal once refracted into the coating.
+    fn refracted_cos(&self, cos_theta: f64) -> f64 {
+        let sin2 = (1.0 - cos_theta * cos_theta) / (self.ior * self.ior);
+        (1.0 - sin2).max(0.0).sqrt()
+    }
+
+    /// Probability of sampling the coating rather than the base, following
+    /// the Fresnel reflectance of the coat and what the base can receive.
+    fn coat_probability(&self, wo: &Vec3) -> f64 {
+        let reflected = fresnel_dielectric(wo.z(), self.ior);
+        let through = self.transmittance(wo, wo);
+        let through = (through.x() + through.y() + through.z()) / 3.0;
+        if reflected + through <= 0.0 {
+            1.0
+        } else {
+            reflected / (reflected + through)
+        }
+    }
+
+    /// Value of both layers times `|cos θi|` and the density of sampling
+    /// `wi` from either one.
+    fn eval_and_pdf(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        frame: &Onb,
+        wo: &Vec3,
+        wi: &Vec3,
+    ) -> (Color, f64) {
+        let local_wi = frame.to_local(&unit_vector(*wi));
+        let (coat, coat_pdf) = if local_wi.z() > 0.0 && wo.z() > 0.0 {
+            let m = unit_vector(*wo + local_wi);
+            let wo_dot_m = dot_product(wo, &m);
+            let fresnel = fresnel_dielectric(wo_dot_m, self.ior);
+            let value =
+                fresnel * self.microfacet.d(&m) * self.microfacet.g(wo, &local_wi) / (4.0 * wo.z());
+            let pdf = self.microfacet.pdf_visible_normal(wo, &m) / (4.0 * wo_dot_m);
+            (Color::new(value, value, value), pdf)
+        } else {
+            (Color::default(), 0.0)
+        };
+        let base = self.base.eval(r_in, rec, wi) * self.transmittance(wo, &local_wi);
+        let base_pdf = self.base.pdf(r_in, rec, wi);
+        let p = self.coat_probability(wo);
+        (coat + base, p * coat_pdf + (1.0 - p) * base_pdf)
+    }
+}
+
+impl Bsdf for Layered {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let (frame, wo) = shading_frame(r_in, rec);
+        self.eval_and_pdf(r_in, rec, &frame, &wo, wi).0
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let p = self.coat_probability(&wo);
+        let (scattered, lobe) = if uc < p {
+            let m = self.microfacet.sample_visible_normal(&wo, u.0, u.1);
+            let wi = reflect(&-wo, &m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let scattered = r_in.spawn(rec.point, frame.local_vec(&wi));
+            (scattered, Lobes::GLOSSY | Lobes::REFLECTION)
+        } else {
+            let sample = self.base.sample(r_in, rec, (uc - p) / (1.0 - p), u)?;
+            if sample.lobe.is_specular() {
+                // A delta lobe can't be combined with the coat's density, only
+                // attenuated by the coating.
+                let wi = frame.to_local(&unit_vector(sample.scattered.direction()));
+                return Some(BsdfSample {
+                    weight: sample.weight * self.transmittance(&wo, &wi) / (1.0 - p),
+                    pdf: sample.pdf * (1.0 - p),
+                    ..sample
+                });
+            }
+            (sample.scattered, sample.lobe)
+        };
+        let (value, pdf) = self.eval_and_pdf(r_in, rec, &frame, &wo, &scattered.direction());
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            scattered,
+            weight: value / pdf,
+            pdf,
+            lobe,
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let (frame, wo) = shading_frame(r_in, rec);
+        self.eval_and_pdf(r_in, rec, &frame, &wo, wi).1
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::GLOSSY | Lobes::REFLECTION | self.base.lobes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        material::{Lambertian, Metal},
+        units::point::Point,
+    };
+
+    /// Average sample weight leaving a surface lit from everywhere with unit
+    /// radiance, seen from the given direction.
+    fn albedo(material: &Layered, direction: Vec3) -> Color {
+        let ray = Ray::new(Point::new(0.0, 1.0, 0.0), direction);
+        let record = HitRecord::new(Point::default(), Vec3::new(0.0, 1.0, 0.0), 1.0);
+        let n = 32;
+        let mut sum = Color::default();
+        for i in 0..n {
+            for j in 0..n {
+                for k in 0..32 {
+                    let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                    let uc = (k as f64 + 0.5) / 32.0;
+                    if let Some(sample) = material.sample(&ray, &record, uc, u) {
+                        sum += sample.weight;
+                    }
+                }
+            }
+        }
+        sum / (n * n * 32) as f64
+    }
+
+    #[test]
+    fn coated_white_diffuse_conserves_energy() {
+        let base: Material = Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)));
+        let material = Layered::new(base, 1.5, Distribution::Ggx, 0.1);
+        for direction in [Vec3::new(0.0, -1.0, 0.0), Vec3::new(1.0, -0.2, 0.0)] {
+            let albedo = albedo(&material, direction);
+            assert!(albedo.x() <= 1.0 && albedo.x() > 0.8, "{:?}", albedo);
+        }
+    }
+
+    #[test]
+    fn coating_absorbs_light_reaching_the_base() {
+        let base: Material = Arc::new(Metal::new(Color::new(1.0, 1.0, 1.0), 0.0));
+        let material = Layered::new(base, 1.5, Distribution::Ggx, 0.2)
+            .with_absorption(Color::new(0.0, 1.0, 4.0), 0.5);
+        let albedo = albedo(&material, Vec3::new(0.3, -1.0, 0.0));
+        assert!(albedo.x() > albedo.y() && albedo.y() > albedo.z());
+        assert!(albedo.x() <= 1.0, "{:?}", albedo);
+    }
+}
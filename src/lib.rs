@@ -1,12 +1,42 @@
+pub mod aabb;
+pub mod bdpt;
 pub mod camera;
+pub mod constant_medium;
+pub mod csg;
+pub mod cuboid;
+pub mod environment;
+pub mod film;
+pub mod fresnel;
+pub mod grid;
+pub mod grid_medium;
+pub mod heightfield;
+pub mod ies;
+pub mod integrator;
+pub mod interior;
+pub mod layered;
+pub mod light;
+pub mod light_sampler;
 pub mod material;
+pub mod microfacet;
+pub mod onb;
+pub mod phase;
+pub mod photon;
+pub mod principled;
+pub mod quad;
 pub mod ray;
+pub mod sampling;
+pub mod scene;
+pub mod sdf;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
+pub mod texture;
+pub mod thin_film;
 pub mod units;
 
 //import infinity for f64 and pi
 pub use std::f64::consts::PI;
-pub use std::f64::INFINITY;
+pub const INFINITY: f64 = f64::INFINITY;
 
 #[inline]
 pub fn degress_to_radies(degrees: f64) -> f64 {
@@ -0,0 +1,1007 @@
+use std::sync::{Arc, OnceLock};
+
+use crate::{
+    aabb::Aabb,
+    degress_to_radies,
+    ies::IesProfile,
+    light_sampler::{LightBounds, LightSampler, Selection},
+    onb::Onb,
+    ray::{HitRecord, Hittable, Ray},
+    sampling::{
+        sample_cosine_hemisphere, sample_uniform_cone, sample_uniform_disk, sample_uniform_sphere,
+        Distribution1D,
+    },
+    units::{
+        color::{luminance, Color},
+        point::Point,
+        vec3::{cross_product, dot_product, unit_vector, Vec3},
+    },
+    INFINITY, PI,
+};
+
+/// Light arriving at a point, drawn by `Light::sample`.
+#[derive(Copy, Clone, Debug)]
+pub enum LightSample {
+    /// Light from a single direction, as from a point light. `irradiance`
+    /// is what a surface facing the light receives, and only arrives if
+    /// nothing is in the way over `distance`.
+    Delta {
+        direction: Vec3,
+        distance: f64,
+        irradiance: Color,
+    },
+    /// Direction towards a light with some extent, drawn with density
+    /// `Light::pdf`. What it emits is found by tracing the direction.
+    Direction(Vec3),
+}
+
+/// Start of a path leaving a light, drawn by `Light::sample_emission`.
+#[derive(Clone)]
+pub struct EmissionSample {
+    /// Point on the light, facing outwards for surfaces.
+    pub record: HitRecord,
+    pub direction: Vec3,
+    /// Density of the point per unit area, one for point lights.
+    pub pdf_position: f64,
+    /// Density of the direction per unit solid angle.
+    pub pdf_direction: f64,
+    /// The light is a single point, which paths can't hit.
+    pub delta_position: bool,
+}
+
+/// Source of light that can be sampled from a shaded point.
+pub trait Light: Send + Sync {
+    fn sample(&self, point: &Point, u: (f64, f64)) -> Option<LightSample>;
+
+    /// Solid angle density with which `sample` draws `direction` from
+    /// `point`. Zero for delta lights, which can't be found by chance.
+    fn pdf(&self, _point: &Point, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Radiance arriving along a ray that escapes the scene, from lights
+    /// infinitely far away.
+    fn escaped(&self, _r: &Ray) -> Color {
+        Color::default()
+    }
+
+    /// Where the light is and where it shines, for picking among many
+    /// lights. `None` for lights at infinity.
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+
+    /// Estimate of the emitted power, for starting light paths on the
+    /// brightest lights. Lights at infinity count what crosses their
+    /// `SceneDisk`.
+    fn phi(&self) -> f64 {
+        self.bounds().map_or(0.0, |bounds| bounds.phi())
+    }
+
+    /// Called with the bounds of the scene once it is built, for lights at
+    /// infinity to find where the paths they emit start.
+    fn preprocess(&mut self, _scene_bounds: &Aabb) {}
+
+    /// Picks a point on the light and a direction leaving it, to trace light
+    /// paths. Lights at infinity start them on their `SceneDisk`.
+    fn sample_emission(&self, _u: (f64, f64), _v: (f64, f64)) -> Option<EmissionSample> {
+        None
+    }
+
+    /// Light leaving the light at `record` along `direction`: radiance for
+    /// surfaces, intensity for points.
+    fn emission(&self, _record: &HitRecord, _direction: &Vec3) -> Color {
+        Color::default()
+    }
+
+    /// Densities of position and direction with which `sample_emission`
+    /// starts at `record` along `direction`. The position density is zero
+    /// off the light and for point lights, which can't be hit.
+    fn emission_pdf(&self, _record: &HitRecord, _direction: &Vec3) -> (f64, f64) {
+        (0.0, 0.0)
+    }
+}
+
+/// Emissive object of the world sampled as a light. The object added here
+/// is only used for sampling, the one in the world provides the emission.
+pub struct AreaLight {
+    object: Box<dyn Hittable>,
+}
+
+impl AreaLight {
+    pub fn new(object: Box<dyn Hittable>) -> Self {
+        Self { object }
+    }
+}
+
+impl Light for AreaLight {
+    fn sample(&self, point: &Point, u: (f64, f64)) -> Option<LightSample> {
+        Some(LightSample::Direction(self.object.random(point, u)))
+    }
+
+    fn pdf(&self, point: &Point, direction: &Vec3) -> f64 {
+        self.object.pdf_value(point, direction)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        self.object.light_bounds()
+    }
+
+    fn sample_emission(&self, u: (f64, f64), v: (f64, f64)) -> Option<EmissionSample> {
+        let (record, pdf_position) = self.object.sample_surface(u)?;
+        // Cosine weighted around the normal, on either side for two sided
+        // emitters.
+        let two_sided = record.material.two_sided_emission();
+        let (side, v) = match (two_sided, v.0 < 0.5) {
+            (false, _) => (1.0, v),
+            (true, true) => (1.0, (v.0 * 2.0, v.1)),
+            (true, false) => (-1.0, (v.0 * 2.0 - 1.0, v.1)),
+        };
+        let direction =
+            Onb::build_from_w(&(record.normal * side)).local_vec(&sample_cosine_hemisphere(v));
+        let pdf_direction = self.emission_pdf(&record, &direction).1;
+        Some(EmissionSample {
+            record,
+            direction,
+            pdf_position,
+            pdf_direction,
+            delta_position: false,
+        })
+    }
+
+    fn emission(&self, record: &HitRecord, direction: &Vec3) -> Color {
+        let towards = Ray::new(record.point + *direction, -*direction);
+        let record = record.facing(&towards);
+        record.material.emitted(&towards, &record)
+    }
+
+    fn emission_pdf(&self, record: &HitRecord, direction: &Vec3) -> (f64, f64) {
+        let pdf_position = self.object.surface_pdf(&record.point);
+        let outward_normal = if record.front_face {
+            record.normal
+        } else {
+            -record.normal
+        };
+        let cosine = dot_product(&unit_vector(*direction), &outward_normal);
+        let pdf_direction = if record.material.two_sided_emission() {
+            cosine.abs() / (2.0 * PI)
+        } else {
+            cosine.max(0.0) / PI
+        };
+        (pdf_position, pdf_direction)
+    }
+}
+
+/// Smooth window fading light out to nothing at `range`, so lights can be
+/// limited in reach without a visible edge.
+fn range_falloff(distance: f64, range: Option<f64>) -> f64 {
+    match range {
+        Some(range) => (1.0 - (distance / range).powi(4)).clamp(0.0, 1.0).powi(2),
+        None => 1.0,
+    }
+}
+
+/// Goniometric distribution of a light, with its nadir pointing along
+/// `nadir`.
+struct Profile {
+    profile: Arc<IesProfile>,
+    nadir: Vec3,
+}
+
+impl Profile {
+    /// Share of the intensity emitted along `direction`. Horizontal angles
+    /// start from +x, or +z for a nadir along x, turned into the plane
+    /// across the nadir.
+    fn falloff(&self, direction: &Vec3) -> f64 {
+        let n = self.nadir;
+        let reference = if n.x().abs() > 0.9 {
+            Vec3::new(0.0, 0.0, 1.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let u = unit_vector(reference - n * dot_product(&n, &reference));
+        let v = cross_product(&n, &u);
+        let vertical = dot_product(direction, &n).clamp(-1.0, 1.0).acos();
+        let horizontal = dot_product(direction, &v).atan2(dot_product(direction, &u));
+        self.profile
+            .intensity(vertical.to_degrees(), horizontal.to_degrees())
+    }
+}
+
+/// Light emitted from a single point equally in all directions, or
+/// following an IES profile, falling off with the square of the distance.
+pub struct PointLight {
+    position: Point,
+    intensity: Color,
+    range: Option<f64>,
+    profile: Option<Profile>,
+}
+
+impl PointLight {
+    /// `intensity` is the power per unit of solid angle.
+    pub fn new(position: Point, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+            range: None,
+            profile: None,
+        }
+    }
+    /// Fades the light smoothly to nothing at `range`.
+    pub fn with_range(mut self, range: f64) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Shapes the light with a measured distribution whose nadir points
+    /// down, horizontal angles turning from +x towards +z. `intensity` then
+    /// tints and scales the profile's intensity.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(Profile {
+            profile,
+            nadir: Vec3::new(0.0, -1.0, 0.0),
+        });
+        self
+    }
+
+    /// Share of the intensity the profile gives `direction`, one without a
+    /// profile.
+    fn profile_falloff(&self, direction: &Vec3) -> f64 {
+        self.profile
+            .as_ref()
+            .map_or(1.0, |profile| profile.falloff(direction))
+    }
+
+    /// Intensity averaged over every direction, as a luminance.
+    fn average_intensity(&self) -> f64 {
+        luminance(self.intensity)
+            * self
+                .profile
+                .as_ref()
+                .map_or(1.0, |profile| profile.profile.average_intensity())
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: &Point, _u: (f64, f64)) -> Option<LightSample> {
+        let to_light = self.position - *point;
+        let distance = to_light.length();
+        let falloff = range_falloff(distance, self.range) / (distance * distance)
+            * self.profile_falloff(&(-to_light / distance));
+        Some(LightSample::Delta {
+            direction: to_light / distance,
+            distance,
+            irradiance: self.intensity * falloff,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        Some(LightBounds::new(
+            Aabb::new(self.position, self.position),
+            4.0 * PI * self.average_intensity(),
+            Vec3::new(0.0, 0.0, 1.0),
+            -1.0,
+            0.0,
+            false,
+        ))
+    }
+
+    fn sample_emission(&self, _u: (f64, f64), v: (f64, f64)) -> Option<EmissionSample> {
+        let direction = sample_uniform_sphere(v);
+        Some(EmissionSample {
+            record: HitRecord::new(self.position, direction, 0.0),
+            direction,
+            pdf_position: 1.0,
+            pdf_direction: 1.0 / (4.0 * PI),
+            delta_position: true,
+        })
+    }
+
+    fn emission(&self, _record: &HitRecord, direction: &Vec3) -> Color {
+        self.intensity * self.profile_falloff(&unit_vector(*direction))
+    }
+
+    fn emission_pdf(&self, _record: &HitRecord, _direction: &Vec3) -> (f64, f64) {
+        (0.0, 1.0 / (4.0 * PI))
+    }
+}
+
+/// Point light shining in a cone, at full intensity within `inner_angle`
+/// of its axis and fading smoothly to nothing at `outer_angle`.
+pub struct SpotLight {
+    light: PointLight,
+    axis: Vec3,
+    cos_inner: f64,
+    cos_outer: f64,
+}
+
+impl SpotLight {
+    /// Spot at `position` aimed at `target`, with angles from the axis in
+    /// degrees.
+    pub fn new(
+        position: Point,
+        target: Point,
+        intensity: Color,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Self {
+        let outer_angle = outer_angle.max(inner_angle);
+        Self {
+            light: PointLight::new(position, intensity),
+            axis: unit_vector(target - position),
+            cos_inner: degress_to_radies(inner_angle).cos(),
+            cos_outer: degress_to_radies(outer_angle).cos(),
+        }
+    }
+    /// Fades the light smoothly to nothing at `range`.
+    pub fn with_range(mut self, range: f64) -> Self {
+        self.light = self.light.with_range(range);
+        self
+    }
+
+    /// Shapes the light with a measured distribution whose nadir points
+    /// along the axis, within the cones. See `PointLight::with_profile`.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.light.profile = Some(Profile {
+            profile,
+            nadir: self.axis,
+        });
+        self
+    }
+
+    /// Share of the intensity emitted along `direction`, a smoothstep
+    /// between the two cones.
+    fn cone_falloff(&self, direction: &Vec3) -> f64 {
+        let cos_theta = dot_product(&self.axis, direction);
+        if self.cos_inner - self.cos_outer <= 0.0 {
+            return if cos_theta >= self.cos_outer {
+                1.0
+            } else {
+                0.0
+            };
+        }
+        let t = ((cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: &Point, u: (f64, f64)) -> Option<LightSample> {
+        match self.light.sample(point, u)? {
+            LightSample::Delta {
+                direction,
+                distance,
+                irradiance,
+            } => {
+                let falloff = self.cone_falloff(&-direction);
+                if falloff <= 0.0 {
+                    return None;
+                }
+                Some(LightSample::Delta {
+                    direction,
+                    distance,
+                    irradiance: irradiance * falloff,
+                })
+            }
+            sample => Some(sample),
+        }
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // Full intensity in the inner cone, and on average half of it in the
+        // fading band.
+        let phi = 2.0
+            * PI
+            * self.light.average_intensity()
+            * ((1.0 - self.cos_inner) + (self.cos_inner - self.cos_outer) / 2.0);
+        let theta_e = self.cos_outer.acos() - self.cos_inner.acos();
+        Some(LightBounds::new(
+            Aabb::new(self.light.position, self.light.position),
+            phi,
+            self.axis,
+            self.cos_inner,
+            theta_e.cos(),
+            false,
+        ))
+    }
+
+    fn sample_emission(&self, _u: (f64, f64), v: (f64, f64)) -> Option<EmissionSample> {
+        let direction =
+            Onb::build_from_w(&self.axis).local_vec(&sample_uniform_cone(v, self.cos_outer));
+        let record = HitRecord::new(self.light.position, direction, 0.0);
+        let pdf_direction = self.emission_pdf(&record, &direction).1;
+        Some(EmissionSample {
+            record,
+            direction,
+            pdf_position: 1.0,
+            pdf_direction,
+            delta_position: true,
+        })
+    }
+
+    fn emission(&self, record: &HitRecord, direction: &Vec3) -> Color {
+        let direction = unit_vector(*direction);
+        self.light.emission(record, &direction) * self.cone_falloff(&direction)
+    }
+
+    fn emission_pdf(&self, _record: &HitRecord, direction: &Vec3) -> (f64, f64) {
+        let cos_theta = dot_product(&self.axis, &unit_vector(*direction));
+        if cos_theta < self.cos_outer || self.cos_outer >= 1.0 {
+            return (0.0, 0.0);
+        }
+        (0.0, 1.0 / (2.0 * PI * (1.0 - self.cos_outer)))
+    }
+}
+
+/// Disk as wide as the bounding sphere of the scene, facing it from the
+/// side a light at infinity shines from, where paths leaving that light
+/// start. Every ray arriving from the direction that crosses the scene
+/// crosses the disk first.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SceneDisk {
+    center: Point,
+    radius: f64,
+}
+
+impl SceneDisk {
+    pub fn new(scene_bounds: &Aabb) -> Self {
+        Self {
+            center: scene_bounds.centroid(),
+            radius: scene_bounds.diagonal().length() / 2.0,
+        }
+    }
+
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    /// Power crossing the disk from a light whose radiance, in luminance,
+    /// integrates to `radiance_integral` over the sphere of directions.
+    pub fn phi(&self, radiance_integral: f64) -> f64 {
+        self.area() * radiance_integral
+    }
+
+    /// Starts a path leaving `light`: a direction it shines along, drawn
+    /// with `v` the way `Light::sample` picks it, from a point drawn with
+    /// `u` on the disk facing it. `None` before the disk is set up.
+    pub fn sample_emission(
+        &self,
+        light: &dyn Light,
+        u: (f64, f64),
+        v: (f64, f64),
+    ) -> Option<EmissionSample> {
+        if self.radius <= 0.0 {
+            return None;
+        }
+        let (to_light, pdf_direction) = match light.sample(&self.center, v)? {
+            LightSample::Delta { direction, .. } => (unit_vector(direction), 1.0),
+            LightSample::Direction(direction) => {
+                (unit_vector(direction), light.pdf(&self.center, &direction))
+            }
+        };
+        if pdf_direction <= 0.0 {
+            return None;
+        }
+        let offset = Onb::build_from_w(&to_light).local_vec(&sample_uniform_disk(u));
+        let point = self.center + (offset + to_light) * self.radius;
+        Some(EmissionSample {
+            record: HitRecord::new(point, -to_light, 0.0),
+            direction: -to_light,
+            pdf_position: 1.0 / self.area(),
+            pdf_direction,
+            delta_position: false,
+        })
+    }
+
+    /// Densities with which `sample_emission` starts at `record` along
+    /// `direction`, the position's zero off the disk facing `light`.
+    pub fn emission_pdf(
+        &self,
+        light: &dyn Light,
+        record: &HitRecord,
+        direction: &Vec3,
+    ) -> (f64, f64) {
+        if self.radius <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let to_light = -unit_vector(*direction);
+        let offset = record.point - self.center - to_light * self.radius;
+        let on_disk = dot_product(&offset, &to_light).abs() <= 1e-6 * self.radius
+            && offset.length() <= self.radius * (1.0 + 1e-9);
+        let pdf_position = if on_disk { 1.0 / self.area() } else { 0.0 };
+        (pdf_position, light.pdf(&self.center, &to_light))
+    }
+}
+
+/// Light from a source infinitely far away, like the sun. With an angular
+/// diameter it is a disk in the sky casting soft shadows, otherwise all the
+/// light arrives from one direction.
+pub struct DirectionalLight {
+    /// Direction towards the light.
+    to_light: Vec3,
+    irradiance: Color,
+    cos_theta_max: f64,
+    disk: SceneDisk,
+}
+
+impl DirectionalLight {
+    /// Light travelling along `direction`, giving `irradiance` to surfaces
+    /// facing it.
+    pub fn new(direction: Vec3, irradiance: Color) -> Self {
+        Self {
+            to_light: -unit_vector(direction),
+            irradiance,
+            cos_theta_max: 1.0,
+            disk: SceneDisk::default(),
+        }
+    }
+    /// Spreads the light over a disk of the given angular diameter in
+    /// degrees, about half a degree for the sun.
+    pub fn with_angular_diameter(mut self, angular_diameter: f64) -> Self {
+        self.cos_theta_max = degress_to_radies(angular_diameter / 2.0).cos();
+        self
+    }
+
+    fn is_delta(&self) -> bool {
+        self.cos_theta_max >= 1.0
+    }
+
+    /// Radiance of the disk, chosen so a surface facing it receives the
+    /// requested irradiance.
+    fn radiance(&self) -> Color {
+        let sin2_theta_max = 1.0 - self.cos_theta_max * self.cos_theta_max;
+        self.irradiance / (PI * sin2_theta_max)
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: &Point, u: (f64, f64)) -> Option<LightSample> {
+        if self.is_delta() {
+            return Some(LightSample::Delta {
+                direction: self.to_light,
+                distance: INFINITY,
+                irradiance: self.irradiance,
+            });
+        }
+        let direction = Onb::build_from_w(&self.to_light)
+            .local_vec(&sample_uniform_cone(u, self.cos_theta_max));
+        Some(LightSample::Direction(direction))
+    }
+
+    fn pdf(&self, _point: &Point, direction: &Vec3) -> f64 {
+        if self.is_delta()
+            || dot_product(&unit_vector(*direction), &self.to_light) < self.cos_theta_max
+        {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - self.cos_theta_max))
+    }
+
+    fn escaped(&self, r: &Ray) -> Color {
+        if self.is_delta()
+            || dot_product(&unit_vector(r.direction()), &self.to_light) < self.cos_theta_max
+        {
+            return Color::default();
+        }
+        self.radiance()
+    }
+
+    fn phi(&self) -> f64 {
+        // The disk's radiance over its solid angle, 2 E / (1 + cos θmax).
+        let radiance_integral = 2.0 * luminance(self.irradiance) / (1.0 + self.cos_theta_max);
+        self.disk.phi(radiance_integral)
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        self.disk = SceneDisk::new(scene_bounds);
+    }
+
+    fn sample_emission(&self, u: (f64, f64), v: (f64, f64)) -> Option<EmissionSample> {
+        self.disk.sample_emission(self, u, v)
+    }
+
+    fn emission(&self, record: &HitRecord, direction: &Vec3) -> Color {
+        if self.is_delta() {
+            return self.irradiance;
+        }
+        self.escaped(&Ray::new(record.point, -*direction))
+    }
+
+    fn emission_pdf(&self, record: &HitRecord, direction: &Vec3) -> (f64, f64) {
+        self.disk.emission_pdf(self, record, direction)
+    }
+}
+
+/// Every light of a scene. Sampling picks one of them as chosen by the
+/// `LightSampler`, uniformly unless told otherwise.
+#[derive(Default)]
+pub struct Lights {
+    lights: Vec<Box<dyn Light>>,
+    sampler: LightSampler,
+    /// Built on first use, once every light is added.
+    selection: OnceLock<Selection>,
+    emitters: OnceLock<Emitters>,
+    /// Given to lights added after `preprocess`.
+    scene_bounds: Option<Aabb>,
+}
+
+/// Lights split by whether they have bounds, with their power for starting
+/// light paths. Bidirectional paths only start on bounded lights, photons
+/// on any of them.
+struct Emitters {
+    bounded: Vec<usize>,
+    power: Option<Distribution1D>,
+    infinite: Vec<usize>,
+    /// Power of every light, by index.
+    all_power: Option<Distribution1D>,
+}
+
+impl Emitters {
+    fn new(lights: &[Box<dyn Light>]) -> Self {
+        let mut bounded = vec![];
+        let mut power = vec![];
+        let mut infinite = vec![];
+        for (index, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) => {
+                    bounded.push(index);
+                    power.push(bounds.phi());
+                }
+                None => infinite.push(index),
+            }
+        }
+        let all_power: Vec<f64> = lights.iter().map(|light| light.phi()).collect();
+        Self {
+            bounded,
+            power: (!power.is_empty()).then(|| Distribution1D::new(&power)),
+            infinite,
+            all_power: all_power
+                .iter()
+                .any(|&phi| phi > 0.0)
+                .then(|| Distribution1D::new(&all_power)),
+        }
+    }
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_sampler(mut self, sampler: LightSampler) -> Self {
+        self.sampler = sampler;
+        self.selection = OnceLock::new();
+        self
+    }
+
+    pub fn add(&mut self, mut light: Box<dyn Light>) {
+        if let Some(scene_bounds) = &self.scene_bounds {
+            light.preprocess(scene_bounds);
+        }
+        self.lights.push(light);
+        self.selection = OnceLock::new();
+        self.emitters = OnceLock::new();
+    }
+
+    /// Adds an emissive object of the world, see `AreaLight`.
+    pub fn add_area(&mut self, object: Box<dyn Hittable>) {
+        self.add(Box::new(AreaLight::new(object)));
+    }
+
+    /// Tells the lights where the scene lies, see `Light::preprocess`.
+    /// `Scene::new` calls it with the bounds of the world.
+    pub fn preprocess(&mut self, scene_bounds: &Aabb) {
+        for light in &mut self.lights {
+            light.preprocess(scene_bounds);
+        }
+        self.scene_bounds = Some(*scene_bounds);
+        self.emitters = OnceLock::new();
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    fn selection(&self) -> &Selection {
+        self.selection
+            .get_or_init(|| Selection::build(self.sampler, &self.lights))
+    }
+
+    /// Samples one light picked with `uc`. Delta samples are weighted by the
+    /// chance of picking their light, directions are drawn with density
+    /// `pdf`.
+    pub fn sample(&self, point: &Point, uc: f64, u: (f64, f64)) -> Option<LightSample> {
+        let (index, pmf) = self.selection().sample(point, uc)?;
+        match self.lights[index].sample(point, u)? {
+            LightSample::Delta {
+                direction,
+                distance,
+                irradiance,
+            } => Some(LightSample::Delta {
+                direction,
+                distance,
+                irradiance: irradiance / pmf,
+            }),
+            sample => Some(sample),
+        }
+    }
+
+    /// Density with which `sample` draws `direction`, over all the lights.
+    pub fn pdf(&self, point: &Point, direction: &Vec3) -> f64 {
+        self.selection().pdf(&self.lights, point, direction)
+    }
+
+    /// Radiance from lights at infinity arriving along an escaped ray.
+    pub fn escaped(&self, r: &Ray) -> Color {
+        self.lights
+            .iter()
+            .fold(Color::default(), |acc, light| acc + light.escaped(r))
+    }
+
+    pub fn light(&self, index: usize) -> &dyn Light {
+        self.lights[index].as_ref()
+    }
+
+    fn emitters(&self) -> &Emitters {
+        self.emitters.get_or_init(|| Emitters::new(&self.lights))
+    }
+
+    /// Starts a light path on one of the bounded lights, picked with `uc` in
+    /// proportion to its power. Returns the light's index and the chance of
+    /// picking it with the sample.
+    pub fn sample_emission(
+        &self,
+        uc: f64,
+        u: (f64, f64),
+        v: (f64, f64),
+    ) -> Option<(usize, f64, EmissionSample)> {
+        let emitters = self.emitters();
+        let (offset, pmf) = emitters.power.as_ref()?.sample_discrete(uc);
+        let index = emitters.bounded[offset];
+        let sample = self.lights[index].sample_emission(u, v)?;
+        Some((index, pmf, sample))
+    }
+
+    /// Like `sample_emission`, but picking among every light in proportion
+    /// to its power, so paths also start on the disks of lights at
+    /// infinity. Their densities aren't in `emission_pdf`, for photons only.
+    pub fn sample_any_emission(
+        &self,
+        uc: f64,
+        u: (f64, f64),
+        v: (f64, f64),
+    ) -> Option<(usize, f64, EmissionSample)> {
+        let (index, pmf) = self.emitters().all_power.as_ref()?.sample_discrete(uc);
+        let sample = self.lights[index].sample_emission(u, v)?;
+        Some((index, pmf, sample))
+    }
+
+    /// Densities with which `sample_emission` starts at `record`, whichever
+    /// light it lies on, along `direction`. The position density includes
+    /// the chance of picking the light.
+    pub fn emission_pdf(&self, record: &HitRecord, direction: &Vec3) -> (f64, f64) {
+        let emitters = self.emitters();
+        let power = match &emitters.power {
+            Some(power) => power,
+            None => return (0.0, 0.0),
+        };
+        let mut pdf = (0.0, 0.0);
+        for (offset, &index) in emitters.bounded.iter().enumerate() {
+            let (pdf_position, pdf_direction) = self.lights[index].emission_pdf(record, direction);
+            if pdf_position > 0.0 {
+                pdf = (
+                    pdf.0 + pdf_position * power.discrete_pdf(offset),
+                    pdf_direction,
+                );
+            }
+        }
+        pdf
+    }
+
+    /// Like `sample`, only picking uniformly among the lights at infinity.
+    pub fn sample_infinite(&self, point: &Point, uc: f64, u: (f64, f64)) -> Option<LightSample> {
+        let infinite = &self.emitters().infinite;
+        if infinite.is_empty() {
+            return None;
+        }
+        let count = infinite.len();
+        let index = infinite[((uc * count as f64) as usize).min(count - 1)];
+        match self.lights[index].sample(point, u)? {
+            LightSample::Delta {
+                direction,
+                distance,
+                irradiance,
+            } => Some(LightSample::Delta {
+                direction,
+                distance,
+                irradiance: irradiance * count as f64,
+            }),
+            sample => Some(sample),
+        }
+    }
+
+    /// Density with which `sample_infinite` draws `direction`.
+    pub fn pdf_infinite(&self, point: &Point, direction: &Vec3) -> f64 {
+        let infinite = &self.emitters().infinite;
+        if infinite.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = infinite
+            .iter()
+            .map(|&index| self.lights[index].pdf(point, direction))
+            .sum();
+        sum / infinite.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::EnvironmentMap, material::DiffuseLight, quad::Quad, texture::ImageTexture,
+        units::vec3::random_f64,
+    };
+
+    #[test]
+    fn spot_light_cones() {
+        let spot = SpotLight::new(
+            Point::new(0.0, 2.0, 0.0),
+            Point::default(),
+            Color::new(8.0, 8.0, 8.0),
+            20.0,
+            40.0,
+        );
+        let irradiance_at = |x: f64| match spot.sample(&Point::new(x, 0.0, 0.0), (0.5, 0.5)) {
+            Some(LightSample::Delta { irradiance, .. }) => irradiance.x(),
+            _ => 0.0,
+        };
+        // Inverse square law inside the inner cone, nothing past the outer one.
+        assert!((irradiance_at(0.0) - 2.0).abs() < 1e-9);
+        assert!((irradiance_at(0.5) - 8.0 / 4.25).abs() < 1e-9);
+        let between = irradiance_at(2.0 * 30f64.to_radians().tan());
+        assert!(between > 0.0 && between < 8.0 / (4.0 / 30f64.to_radians().cos().powi(2)));
+        assert_eq!(irradiance_at(2.0), 0.0);
+    }
+
+    #[test]
+    fn profiled_point_light() {
+        let profile = IesProfile::read(
+            "TILT=NONE\n1 1000 1 2 2 1 1 0 0 0\n1 1 10\n0 90\n0 90\n683 0\n683 0\n".as_bytes(),
+        )
+        .unwrap();
+        let light = PointLight::new(Point::new(0.0, 2.0, 0.0), Color::new(1.0, 0.5, 0.5))
+            .with_profile(Arc::new(profile));
+        let irradiance_at = |p: Point| match light.sample(&p, (0.5, 0.5)) {
+            Some(LightSample::Delta { irradiance, .. }) => irradiance,
+            _ => Color::default(),
+        };
+        // 683 cd is 1 W/sr straight down, halved at 45° and dark sideways.
+        let below = irradiance_at(Point::default());
+        assert!((below.x() - 0.25).abs() < 1e-9 && (below.y() - 0.125).abs() < 1e-9);
+        assert!((irradiance_at(Point::new(2.0, 0.0, 0.0)).x() - 0.5 / 8.0).abs() < 1e-9);
+        assert_eq!(irradiance_at(Point::new(2.0, 2.0, 0.0)).x(), 0.0);
+    }
+
+    #[test]
+    fn sun_disk_gives_requested_irradiance() {
+        let sun = DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Color::new(3.0, 3.0, 3.0))
+            .with_angular_diameter(10.0);
+        let origin = Point::default();
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let n = 100;
+        let mut irradiance = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let u = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                if let Some(LightSample::Direction(direction)) = sun.sample(&origin, u) {
+                    let radiance = sun.escaped(&Ray::new(origin, direction));
+                    let cosine = dot_product(&unit_vector(direction), &normal);
+                    irradiance += radiance.x() * cosine / sun.pdf(&origin, &direction);
+                }
+            }
+        }
+        irradiance /= (n * n) as f64;
+        assert!((irradiance - 3.0).abs() < 1e-3, "{}", irradiance);
+    }
+
+    /// Power of `light` estimated from its emission samples, checking that
+    /// `emission_pdf` gives the densities each sample reports.
+    fn emitted_power(light: &dyn Light) -> f64 {
+        let n = 40_000;
+        let mut power = 0.0;
+        for _ in 0..n {
+            let u = (random_f64(), random_f64());
+            let v = (random_f64(), random_f64());
+            let sample = light.sample_emission(u, v).unwrap();
+            let (pdf_position, pdf_direction) =
+                light.emission_pdf(&sample.record, &sample.direction);
+            assert!((pdf_direction - sample.pdf_direction).abs() < 1e-9);
+            let mut flux = light.emission(&sample.record, &sample.direction).x()
+                / (sample.pdf_position * sample.pdf_direction);
+            if !sample.delta_position {
+                assert!((pdf_position - sample.pdf_position).abs() < 1e-9);
+                flux *= dot_product(&sample.record.normal, &unit_vector(sample.direction)).abs();
+            }
+            power += flux;
+        }
+        power / n as f64
+    }
+
+    #[test]
+    fn emission_samples_carry_the_power_of_the_light() {
+        let quad = Quad::new(
+            Point::new(0.0, 2.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 3.0),
+            Arc::new(DiffuseLight::new(Color::new(1.5, 1.5, 1.5))),
+        );
+        let area = AreaLight::new(Box::new(quad));
+        let expected = PI * 6.0 * 1.5;
+        let power = emitted_power(&area);
+        assert!((power - expected).abs() < 1e-9 * expected, "{power}");
+
+        let point = PointLight::new(Point::default(), Color::new(2.0, 2.0, 2.0));
+        let expected = 4.0 * PI * 2.0;
+        let power = emitted_power(&point);
+        assert!((power - expected).abs() < 1e-9 * expected, "{power}");
+
+        // The spot fades between its cones, integrate it over the sphere.
+        let spot = SpotLight::new(
+            Point::default(),
+            Point::new(0.0, -1.0, 0.0),
+            Color::new(3.0, 3.0, 3.0),
+            20.0,
+            40.0,
+        );
+        let steps = 100_000;
+        let record = HitRecord::new(Point::default(), Vec3::new(0.0, -1.0, 0.0), 0.0);
+        let expected = (0..steps)
+            .map(|i| {
+                let theta = (i as f64 + 0.5) / steps as f64 * PI;
+                let direction = Vec3::new(theta.sin(), -theta.cos(), 0.0);
+                spot.emission(&record, &direction).x() * theta.sin() * 2.0 * PI * PI / steps as f64
+            })
+            .sum::<f64>();
+        let power = emitted_power(&spot);
+        assert!(
+            (power - expected).abs() < 0.02 * expected,
+            "{power} vs {expected}"
+        );
+    }
+
+    #[test]
+    fn lights_at_infinity_emit_through_the_scene_disk() {
+        // A disk of radius √3 around the unit cube.
+        let bounds = Aabb::new(Point::new(-1.0, -1.0, -1.0), Point::new(1.0, 1.0, 1.0));
+        let area = 3.0 * PI;
+
+        let mut sun = DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Color::new(3.0, 3.0, 3.0))
+            .with_angular_diameter(10.0);
+        assert!(sun.sample_emission((0.5, 0.5), (0.5, 0.5)).is_none());
+        sun.preprocess(&bounds);
+        let expected = area * 2.0 * 3.0 / (1.0 + degress_to_radies(5.0).cos());
+        let power = emitted_power(&sun);
+        assert!((power - expected).abs() < 1e-9 * expected, "{power}");
+        assert!((sun.phi() - expected).abs() < 1e-9 * expected);
+
+        // Gray everywhere, with one bright texel in the second row.
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Color::new(0.5, 0.5, 0.5); width * height];
+        pixels[width + 3] = Color::new(5.0, 5.0, 5.0);
+        let mut map = EnvironmentMap::new(ImageTexture::new(width, height, pixels));
+        map.preprocess(&bounds);
+        let row = |j: f64| (j * PI / height as f64).cos();
+        let texel = 2.0 * PI / width as f64 * (row(1.0) - row(2.0));
+        let expected = area * (0.5 * 4.0 * PI + 4.5 * texel);
+        let power = emitted_power(&map);
+        assert!(
+            (power - expected).abs() < 0.02 * expected,
+            "{power} vs {expected}"
+        );
+        assert!((map.phi() - expected).abs() < 0.01 * expected);
+    }
+}
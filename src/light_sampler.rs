@@ -0,0 +1,616 @@
+use crate::{
+    aabb::Aabb,
+    light::Light,
+    ray::Ray,
+    sampling::Distribution1D,
+    units::{
+        point::Point,
+        vec3::{cross_product, dot_product, unit_vector, Vec3},
+    },
+    INFINITY, PI,
+};
+
+/// Where a light is, how much it emits and in which directions, to estimate
+/// its contribution at a point without sampling it. Emission leaves along
+/// normals within `θo` of the axis `w`, spreading up to `θe` around each
+/// normal, as in Conty and Kulla's light trees.
+#[derive(Copy, Clone, Debug)]
+pub struct LightBounds {
+    bounds: Aabb,
+    phi: f64,
+    w: Vec3,
+    cos_theta_o: f64,
+    cos_theta_e: f64,
+    two_sided: bool,
+}
+
+impl LightBounds {
+    /// `phi` is an estimate of the emitted power.
+    pub fn new(
+        bounds: Aabb,
+        phi: f64,
+        w: Vec3,
+        cos_theta_o: f64,
+        cos_theta_e: f64,
+        two_sided: bool,
+    ) -> Self {
+        Self {
+            bounds,
+            phi,
+            w: unit_vector(w),
+            cos_theta_o,
+            cos_theta_e,
+            two_sided,
+        }
+    }
+
+    pub fn phi(&self) -> f64 {
+        self.phi
+    }
+
+    /// Conservative estimate of the light arriving at `point`: the power
+    /// over the squared distance, with the cosine of the smallest angle
+    /// between the emission cone and the direction to the point.
+    pub fn importance(&self, point: &Point) -> f64 {
+        let center = self.bounds.centroid();
+        let to_point = *point - center;
+        let distance_squared = to_point.length_squared();
+        let radius = self.bounds.diagonal().length() / 2.0;
+
+        let cos_theta_w = if distance_squared > 0.0 {
+            let cos = dot_product(&self.w, &to_point) / distance_squared.sqrt();
+            if self.two_sided {
+                cos.abs()
+            } else {
+                cos
+            }
+        } else {
+            1.0
+        };
+        let sin_theta_w = (1.0 - cos_theta_w * cos_theta_w).max(0.0).sqrt();
+        // Cone of directions the bounds fill seen from the point.
+        let cos_theta_b = if distance_squared < radius * radius {
+            -1.0
+        } else {
+            (1.0 - radius * radius / distance_squared).max(0.0).sqrt()
+        };
+        let sin_theta_b = (1.0 - cos_theta_b * cos_theta_b).max(0.0).sqrt();
+        let sin_theta_o = (1.0 - self.cos_theta_o * self.cos_theta_o).max(0.0).sqrt();
+
+        // cos(max(0, θw - θo - θb)) by angle differences.
+        let (cos_theta_x, sin_theta_x) = if cos_theta_w > self.cos_theta_o {
+            (1.0, 0.0)
+        } else {
+            (
+                cos_theta_w * self.cos_theta_o + sin_theta_w * sin_theta_o,
+                sin_theta_w * self.cos_theta_o - cos_theta_w * sin_theta_o,
+            )
+        };
+        let cos_theta_p = if cos_theta_x > cos_theta_b {
+            1.0
+        } else {
+            cos_theta_x * cos_theta_b + sin_theta_x * sin_theta_b
+        };
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+        let distance_squared = distance_squared.max(radius * radius).max(1e-12);
+        self.phi * cos_theta_p / distance_squared
+    }
+
+    /// Bounds of both lights together.
+    pub fn union(&self, other: &LightBounds) -> LightBounds {
+        let (w, cos_theta_o) =
+            union_cones((self.w, self.cos_theta_o), (other.w, other.cos_theta_o));
+        LightBounds {
+            bounds: self.bounds.union(&other.bounds),
+            phi: self.phi + other.phi,
+            w,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+            two_sided: self.two_sided || other.two_sided,
+        }
+    }
+
+    /// Surface area orientation heuristic: lights that are large, bright
+    /// and shine in many directions are expensive to put in one node.
+    fn cost(&self) -> f64 {
+        let theta_o = self.cos_theta_o.clamp(-1.0, 1.0).acos();
+        let theta_e = self.cos_theta_e.clamp(-1.0, 1.0).acos();
+        let theta_w = (theta_o + theta_e).min(PI);
+        let sin_theta_o = theta_o.sin();
+        let m_omega = 2.0 * PI * (1.0 - self.cos_theta_o)
+            + PI / 2.0
+                * (2.0 * theta_w * sin_theta_o
+                    - (theta_o - 2.0 * theta_w).cos()
+                    - 2.0 * theta_o * sin_theta_o
+                    + self.cos_theta_o);
+        self.phi * m_omega * self.bounds.surface_area().max(1e-12)
+    }
+}
+
+/// Smallest cone, as axis and cosine of its half angle, holding both cones.
+fn union_cones(a: (Vec3, f64), b: (Vec3, f64)) -> (Vec3, f64) {
+    let entire = (a.0, -1.0);
+    if a.1 <= -1.0 || b.1 <= -1.0 {
+        return entire;
+    }
+    let theta_a = a.1.clamp(-1.0, 1.0).acos();
+    let theta_b = b.1.clamp(-1.0, 1.0).acos();
+    let theta_d = dot_product(&a.0, &b.0).clamp(-1.0, 1.0).acos();
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return a;
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return b;
+    }
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    if theta_o >= PI {
+        return entire;
+    }
+    // Turn a's axis towards b's so the new cone touches both.
+    let axis = cross_product(&a.0, &b.0);
+    if axis.length_squared() == 0.0 {
+        return entire;
+    }
+    let k = unit_vector(axis);
+    let theta_r = theta_o - theta_a;
+    let v = a.0;
+    let w = v * theta_r.cos()
+        + cross_product(&k, &v) * theta_r.sin()
+        + k * (dot_product(&k, &v) * (1.0 - theta_r.cos()));
+    (unit_vector(w), theta_o.cos())
+}
+
+/// How `Lights` picks the light to sample at a point.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LightSampler {
+    /// Every light equally often.
+    #[default]
+    Uniform,
+    /// Lights in proportion to their power, wherever the point is.
+    Power,
+    /// Lights in proportion to their estimated contribution at the point,
+    /// found by descending a bounding volume hierarchy of the lights.
+    Bvh,
+}
+
+/// Light selection structure built by a `LightSampler` for a set of lights.
+/// Lights without bounds, like those at infinity, are picked uniformly as
+/// often as all the bounded ones together.
+pub(crate) enum Selection {
+    Uniform(usize),
+    Power {
+        infinite: Vec<usize>,
+        bounded: Vec<usize>,
+        distribution: Option<Distribution1D>,
+    },
+    Bvh {
+        infinite: Vec<usize>,
+        nodes: Vec<Node>,
+    },
+}
+
+pub(crate) struct Node {
+    bounds: LightBounds,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    /// Index of the light.
+    Leaf(usize),
+    /// Index of the second child, the first one follows the node.
+    Interior(usize),
+}
+
+impl Selection {
+    pub fn build(sampler: LightSampler, lights: &[Box<dyn Light>]) -> Self {
+        if sampler == LightSampler::Uniform {
+            return Selection::Uniform(lights.len());
+        }
+        // Lights bounded but emitting nothing are never picked.
+        let mut infinite = vec![];
+        let mut bounded = vec![];
+        for (index, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if bounds.phi() > 0.0 => bounded.push((index, bounds)),
+                Some(_) => {}
+                None => infinite.push(index),
+            }
+        }
+        match sampler {
+            LightSampler::Power => {
+                let power: Vec<f64> = bounded.iter().map(|(_, b)| b.phi()).collect();
+                Selection::Power {
+                    infinite,
+                    bounded: bounded.iter().map(|(index, _)| *index).collect(),
+                    distribution: (!power.is_empty()).then(|| Distribution1D::new(&power)),
+                }
+            }
+            _ => {
+                let mut nodes = vec![];
+                if !bounded.is_empty() {
+                    build_bvh(&mut bounded, &mut nodes);
+                }
+                Selection::Bvh { infinite, nodes }
+            }
+        }
+    }
+
+    /// Chance of picking one of the lights without bounds, and how many
+    /// of them there are.
+    fn infinite_share(&self) -> (f64, usize) {
+        let (infinite, has_bounded) = match self {
+            Selection::Uniform(_) => return (0.0, 0),
+            Selection::Power {
+                infinite, bounded, ..
+            } => (infinite.len(), !bounded.is_empty()),
+            Selection::Bvh { infinite, nodes } => (infinite.len(), !nodes.is_empty()),
+        };
+        let total = infinite + usize::from(has_bounded);
+        if total == 0 {
+            return (0.0, 0);
+        }
+        (infinite as f64 / total as f64, infinite)
+    }
+
+    /// Index of a light picked with `u` for `point`, and the probability of
+    /// picking it.
+    pub fn sample(&self, point: &Point, u: f64) -> Option<(usize, f64)> {
+        if let Selection::Uniform(count) = *self {
+            if count == 0 {
+                return None;
+            }
+            let index = ((u * count as f64) as usize).min(count - 1);
+            return Some((index, 1.0 / count as f64));
+        }
+        let (p_infinite, count) = self.infinite_share();
+        if u < p_infinite {
+            let index = ((u / p_infinite * count as f64) as usize).min(count - 1);
+            let light = match self {
+                Selection::Power { infinite, .. } | Selection::Bvh { infinite, .. } => {
+                    infinite[index]
+                }
+                Selection::Uniform(_) => unreachable!(),
+            };
+            return Some((light, p_infinite / count as f64));
+        }
+        let u = ((u - p_infinite) / (1.0 - p_infinite)).min(1.0 - f64::EPSILON);
+        let (index, pmf) = match self {
+            Selection::Power {
+                bounded,
+                distribution,
+                ..
+            } => {
+                let (offset, pmf) = distribution.as_ref()?.sample_discrete(u);
+                (bounded[offset], pmf)
+            }
+            Selection::Bvh { nodes, .. } => sample_bvh(nodes, point, u)?,
+            Selection::Uniform(_) => unreachable!(),
+        };
+        Some((index, pmf * (1.0 - p_infinite)))
+    }
+
+    /// Density with which sampling a light picked by `sample` draws
+    /// `direction` from `point`.
+    pub fn pdf(&self, lights: &[Box<dyn Light>], point: &Point, direction: &Vec3) -> f64 {
+        match self {
+            Selection::Uniform(count) => {
+                if *count == 0 {
+                    return 0.0;
+                }
+                let sum: f64 = lights.iter().map(|l| l.pdf(point, direction)).sum();
+                sum / *count as f64
+            }
+            Selection::Power {
+                infinite,
+                bounded,
+                distribution,
+            } => {
+                let (p_infinite, count) = self.infinite_share();
+                let mut pdf = infinite
+                    .iter()
+                    .map(|&i| lights[i].pdf(point, direction) * p_infinite / count as f64)
+                    .sum();
+                if let Some(distribution) = distribution {
+                    for (offset, &i) in bounded.iter().enumerate() {
+                        pdf += lights[i].pdf(point, direction)
+                            * distribution.discrete_pdf(offset)
+                            * (1.0 - p_infinite);
+                    }
+                }
+                pdf
+            }
+            Selection::Bvh { infinite, nodes } => {
+                let (p_infinite, count) = self.infinite_share();
+                let mut pdf = infinite
+                    .iter()
+                    .map(|&i| lights[i].pdf(point, direction) * p_infinite / count as f64)
+                    .sum();
+                if !nodes.is_empty() {
+                    // Only lights whose bounds the direction passes through
+                    // can have drawn it.
+                    let ray = Ray::new(*point, *direction);
+                    pdf += (1.0 - p_infinite) * bvh_pdf(nodes, lights, 0, 1.0, &ray, point);
+                }
+                pdf
+            }
+        }
+    }
+}
+
+/// Appends the nodes of a hierarchy over `lights` to `nodes`, splitting
+/// each node where the orientation heuristic is cheapest.
+fn build_bvh(lights: &mut [(usize, LightBounds)], nodes: &mut Vec<Node>) {
+    let bounds = lights[1..]
+        .iter()
+        .fold(lights[0].1, |acc, (_, b)| acc.union(b));
+    if lights.len() == 1 {
+        nodes.push(Node {
+            bounds,
+            kind: NodeKind::Leaf(lights[0].0),
+        });
+        return;
+    }
+
+    let centroids = lights[1..].iter().fold(
+        Aabb::new(lights[0].1.bounds.centroid(), lights[0].1.bounds.centroid()),
+        |acc, (_, b)| acc.union(&Aabb::new(b.bounds.centroid(), b.bounds.centroid())),
+    );
+    const BUCKETS: usize = 12;
+    let bucket = |b: &LightBounds, axis: usize| {
+        let (min, extent) = (centroids.min()[axis], centroids.diagonal()[axis]);
+        (((b.bounds.centroid()[axis] - min) / extent * BUCKETS as f64) as usize).min(BUCKETS - 1)
+    };
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        if centroids.diagonal()[axis] <= 0.0 {
+            continue;
+        }
+        let mut buckets: [Option<LightBounds>; BUCKETS] = [None; BUCKETS];
+        for (_, b) in lights.iter() {
+            let slot = &mut buckets[bucket(b, axis)];
+            *slot = Some(slot.map_or(*b, |s| s.union(b)));
+        }
+        let merge = |slots: &[Option<LightBounds>]| {
+            slots
+                .iter()
+                .flatten()
+                .fold(None, |acc: Option<LightBounds>, b| {
+                    Some(acc.map_or(*b, |a| a.union(b)))
+                })
+        };
+        for split in 0..BUCKETS - 1 {
+            let cost = [merge(&buckets[..=split]), merge(&buckets[split + 1..])]
+                .iter()
+                .flatten()
+                .map(LightBounds::cost)
+                .sum::<f64>();
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, split));
+            }
+        }
+    }
+
+    let mid = match best {
+        Some((_, axis, split)) => {
+            let mut mid = 0;
+            for i in 0..lights.len() {
+                if bucket(&lights[i].1, axis) <= split {
+                    lights.swap(i, mid);
+                    mid += 1;
+                }
+            }
+            mid
+        }
+        None => 0,
+    };
+    // Every centroid in one place or one side empty: halve by count.
+    let mid = if mid == 0 || mid == lights.len() {
+        lights.len() / 2
+    } else {
+        mid
+    };
+
+    let index = nodes.len();
+    nodes.push(Node {
+        bounds,
+        kind: NodeKind::Interior(0),
+    });
+    let (first, second) = lights.split_at_mut(mid);
+    build_bvh(first, nodes);
+    nodes[index].kind = NodeKind::Interior(nodes.len());
+    build_bvh(second, nodes);
+}
+
+fn sample_bvh(nodes: &[Node], point: &Point, mut u: f64) -> Option<(usize, f64)> {
+    if nodes[0].bounds.importance(point) <= 0.0 {
+        return None;
+    }
+    let mut index = 0;
+    let mut pmf = 1.0;
+    loop {
+        match nodes[index].kind {
+            NodeKind::Leaf(light) => return Some((light, pmf)),
+            NodeKind::Interior(second) => {
+                let i0 = nodes[index + 1].bounds.importance(point);
+                let i1 = nodes[second].bounds.importance(point);
+                if i0 + i1 <= 0.0 {
+                    return None;
+                }
+                let p0 = i0 / (i0 + i1);
+                if u < p0 {
+                    index += 1;
+                    u = (u / p0).min(1.0 - f64::EPSILON);
+                    pmf *= p0;
+                } else {
+                    index = second;
+                    u = ((u - p0) / (1.0 - p0)).min(1.0 - f64::EPSILON);
+                    pmf *= 1.0 - p0;
+                }
+            }
+        }
+    }
+}
+
+/// Sum of the densities of the lights under node `index`, reached with
+/// probability `pmf`, whose bounds `ray` passes through.
+fn bvh_pdf(
+    nodes: &[Node],
+    lights: &[Box<dyn Light>],
+    index: usize,
+    pmf: f64,
+    ray: &Ray,
+    point: &Point,
+) -> f64 {
+    let node = &nodes[index];
+    if pmf <= 0.0 || node.bounds.bounds.hit(ray, 0.0, INFINITY).is_none() {
+        return 0.0;
+    }
+    match node.kind {
+        NodeKind::Leaf(light) => {
+            if index == 0 && node.bounds.importance(point) <= 0.0 {
+                return 0.0;
+            }
+            pmf * lights[light].pdf(point, &ray.direction())
+        }
+        NodeKind::Interior(second) => {
+            let i0 = nodes[index + 1].bounds.importance(point);
+            let i1 = nodes[second].bounds.importance(point);
+            if i0 + i1 <= 0.0 {
+                return 0.0;
+            }
+            let p0 = i0 / (i0 + i1);
+            bvh_pdf(nodes, lights, index + 1, pmf * p0, ray, point)
+                + bvh_pdf(nodes, lights, second, pmf * (1.0 - p0), ray, point)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        light::{LightSample, Lights, PointLight},
+        material::DiffuseLight,
+        sphere::Sphere,
+        units::color::Color,
+    };
+
+    /// Spheres of different brightness scattered around the origin.
+    fn sphere_lights(sampler: LightSampler) -> (Lights, f64) {
+        let mut lights = Lights::new().with_sampler(sampler);
+        let mut solid_angle = 0.0;
+        for i in 0..24 {
+            let t = i as f64;
+            let center = Point::new(
+                6.0 * (t * 2.4).cos(),
+                1.0 + (t * 0.7).sin() * 3.0,
+                6.0 * (t * 2.4).sin() + t * 0.5,
+            );
+            let radius = 0.1 + 0.02 * (i % 5) as f64;
+            let emit = Color::new(1.0, 1.0, 1.0) * (1.0 + (i % 7) as f64);
+            let sphere = Sphere::new(center, radius, Arc::new(DiffuseLight::new(emit)));
+            lights.add_area(Box::new(sphere));
+            let sin2 = radius * radius / center.length_squared();
+            solid_angle += 2.0 * PI * (1.0 - (1.0 - sin2).sqrt());
+        }
+        (lights, solid_angle)
+    }
+
+    #[test]
+    fn samplers_are_unbiased_for_area_lights() {
+        // Averaging 1 / pdf over sampled directions measures the solid angle
+        // the lights cover, which only works out if picking a light and
+        // `pdf` agree.
+        for sampler in [
+            LightSampler::Uniform,
+            LightSampler::Power,
+            LightSampler::Bvh,
+        ] {
+            let (lights, solid_angle) = sphere_lights(sampler);
+            let origin = Point::default();
+            let n = 20000;
+            let mut sum = 0.0;
+            for i in 0..n {
+                let uc = (i as f64 + 0.5) / n as f64;
+                let u = ((i as f64 * 0.618034) % 1.0, (i as f64 * 0.414214) % 1.0);
+                if let Some(LightSample::Direction(direction)) = lights.sample(&origin, uc, u) {
+                    sum += 1.0 / lights.pdf(&origin, &direction);
+                }
+            }
+            let estimate = sum / n as f64;
+            assert!(
+                (estimate - solid_angle).abs() < 0.02 * solid_angle,
+                "{:?} {} {}",
+                sampler,
+                estimate,
+                solid_angle
+            );
+        }
+    }
+
+    #[test]
+    fn samplers_are_unbiased_for_point_lights() {
+        let point = Point::new(0.3, 0.0, -0.2);
+        let mut exact = 0.0;
+        let mut all = vec![];
+        for sampler in [
+            LightSampler::Uniform,
+            LightSampler::Power,
+            LightSampler::Bvh,
+        ] {
+            let mut lights = Lights::new().with_sampler(sampler);
+            exact = 0.0;
+            for i in 0..50 {
+                let t = i as f64;
+                let position = Point::new(t.cos() * (2.0 + t), 3.0, t.sin() * (2.0 + t));
+                let intensity = 1.0 + (i % 3) as f64;
+                lights.add(Box::new(PointLight::new(
+                    position,
+                    Color::new(intensity, intensity, intensity),
+                )));
+                exact += intensity / (position - point).length_squared();
+            }
+            let n = 100000;
+            let mut sum = 0.0;
+            for i in 0..n {
+                let uc = (i as f64 + 0.5) / n as f64;
+                if let Some(LightSample::Delta { irradiance, .. }) =
+                    lights.sample(&point, uc, (0.5, 0.5))
+                {
+                    sum += irradiance.x();
+                }
+            }
+            all.push(sum / n as f64);
+        }
+        for estimate in all {
+            assert!(
+                (estimate - exact).abs() < 1e-3 * exact,
+                "{} {}",
+                estimate,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn bvh_prefers_close_lights() {
+        let mut lights = Lights::new().with_sampler(LightSampler::Bvh);
+        let white = Color::new(1.0, 1.0, 1.0);
+        lights.add(Box::new(PointLight::new(Point::new(0.0, 1.0, 0.0), white)));
+        lights.add(Box::new(PointLight::new(Point::new(50.0, 1.0, 0.0), white)));
+        let near = (0..100)
+            .filter(|i| {
+                let uc = (*i as f64 + 0.5) / 100.0;
+                match lights.sample(&Point::default(), uc, (0.5, 0.5)) {
+                    Some(LightSample::Delta { distance, .. }) => distance < 2.0,
+                    _ => false,
+                }
+            })
+            .count();
+        assert!(near > 95, "{}", near);
+    }
+}
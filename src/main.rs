@@ -1,13 +1,18 @@
+use std::sync::Arc;
+
 use rayon::prelude::*;
 use rustracer::{
     camera::Camera,
-    material::{Dielectric, Lambertian, Material, Metal},
-    ray::{self, Hittables},
+    integrator::Integrator,
+    light::Lights,
+    material::{Dielectric, Lambertian, Metal},
+    ray::Hittables,
+    scene::Scene,
     sphere::Sphere,
     units::{
         color::{write_color, Color},
         point::Point,
-        vec3::{random_f64, random_f64_range, unit_vector, Vec3},
+        vec3::{random_f64, random_f64_range, Vec3},
     },
 };
 
@@ -20,7 +25,9 @@ fn main() {
     let aperture = 0.1;
 
     // World
-    let world = random_scene();
+    // Lights sampled directly, emissive objects of the world among them.
+    let scene = Scene::new(random_scene(), Lights::new());
+    let integrator = Integrator::default();
 
     let lookfrom = Point::new(13.0, 2.0, 3.0);
     let lookat = Point::new(0.0, 0.0, 0.0);
@@ -49,7 +56,7 @@ fn main() {
                     let u = (i as f64 + random_f64()) / (image_width - 1) as f64;
                     let v = (j as f64 + random_f64()) / (image_height - 1) as f64;
                     let ray = camera.get_ray(u, v);
-                    ray_color(&ray, &world, max_depth)
+                    integrator.ray_color(&ray, &scene, max_depth)
                 })
                 .reduce(|| pixel_color, |acc, x| acc + x);
 
@@ -59,24 +66,11 @@ fn main() {
     eprintln!("Done");
 }
 
-fn ray_color(r: &ray::Ray, world: &Hittables, depth: i32) -> Color {
-    if depth < 0 {
-        return Color::new(0.0, 0.0, 0.0);
-    }
-    if let Some(rec) = world.hit(r, 0.001, f64::INFINITY) {
-        let (attenuation, scattered) = rec.material.scatter(r, &rec);
-        return ray_color(&scattered, world, depth - 1) * attenuation;
-    }
-    let unit_direction = unit_vector(r.direction());
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
-}
-
 fn random_scene() -> Hittables {
     let mut world = Hittables::new();
 
     // Make the ground material
-    let ground_material = Material::Lambertian(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
     world.add(Box::new(Sphere::new(
         Point::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -94,34 +88,34 @@ fn random_scene() -> Hittables {
             if (center - Point::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 if choose_mat < 0.8 {
                     let albedo = Color::random() * Color::random();
-                    let material = Material::Lambertian(Lambertian::new(albedo));
+                    let material = Arc::new(Lambertian::new(albedo));
                     world.add(Box::new(Sphere::new(center, 0.2, material)));
                 } else if choose_mat < 0.95 {
                     let albedo = Color::random_with_range(0.5, 1.0);
                     let fuzz = random_f64_range(0.0, 0.5);
-                    let material = Material::Metal(Metal::new(albedo, fuzz));
+                    let material = Arc::new(Metal::new(albedo, fuzz));
                     world.add(Box::new(Sphere::new(center, 0.2, material)));
                 } else {
-                    let material = Material::Dielectric(Dielectric::new(1.5));
+                    let material = Arc::new(Dielectric::new(1.5));
                     world.add(Box::new(Sphere::new(center, 0.2, material)));
                 }
             }
         }
     }
 
-    let material1 = Material::Dielectric(Dielectric::new(1.5));
+    let material1 = Arc::new(Dielectric::new(1.5));
     world.add(Box::new(Sphere::new(
         Point::new(0.0, 1.0, 0.0),
         1.0,
         material1,
     )));
-    let material2 = Material::Lambertian(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
     world.add(Box::new(Sphere::new(
         Point::new(-4.0, 1.0, 0.0),
         1.0,
         material2,
     )));
-    let material3 = Material::Metal(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
     world.add(Box::new(Sphere::new(
         Point::new(4.0, 1.0, 0.0),
         1.0,
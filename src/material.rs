@@ -1,26 +1,327 @@
+use std::{fmt::Debug, ops::BitOr, sync::Arc};
+
+use crate::fresnel::{fresnel_conductor, fresnel_dielectric};
+use crate::interior::{Interior, Scattering};
+use crate::microfacet::{Distribution, Microfacet};
+use crate::onb::Onb;
+use crate::phase::{henyey_greenstein, sample_henyey_greenstein};
 use crate::ray::{HitRecord, Ray};
-use crate::units::vec3::{dot_product, random_f64, random_in_unit_sphere, refract, unit_vector};
-use crate::units::{
-    color::Color,
-    vec3::{random_unit_vector, reflect},
-};
-#[derive(Copy, Clone, Debug)]
-pub enum Material {
-    Lambertian(Lambertian),
-    Metal(Metal),
-    Dielectric(Dielectric),
+use crate::sampling::{sample_cosine_hemisphere, sample_uniform_ball, sample_uniform_sphere};
+use crate::spectrum::{Dispersion, Wavelengths, LAMBDA_D};
+use crate::texture::{SolidColor, Texture};
+use crate::thin_film::ThinFilm;
+use crate::units::vec3::{dot_product, refract, unit_vector, Vec3};
+use crate::units::{color::Color, point::Point, vec3::reflect};
+use crate::PI;
+
+/// Set of flags describing the kinds of scattering a BSDF does.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Lobes(u8);
+
+impl Lobes {
+    pub const REFLECTION: Lobes = Lobes(1);
+    pub const TRANSMISSION: Lobes = Lobes(1 << 1);
+    pub const DIFFUSE: Lobes = Lobes(1 << 2);
+    pub const GLOSSY: Lobes = Lobes(1 << 3);
+    /// Perfectly sharp scattering, a delta distribution that `eval` and `pdf`
+    /// can't represent. Such lobes can only be sampled.
+    pub const SPECULAR: Lobes = Lobes(1 << 4);
+
+    pub fn contains(self, other: Lobes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_specular(self) -> bool {
+        self.contains(Lobes::SPECULAR)
+    }
+
+    /// Whether some lobe spreads light over directions, so that `eval` can
+    /// be lit by sampled lights.
+    pub fn has_non_specular(self) -> bool {
+        self.contains(Lobes::DIFFUSE) || self.contains(Lobes::GLOSSY)
+    }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+impl BitOr for Lobes {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Lobes(self.0 | rhs.0)
+    }
+}
+
+/// Incident direction drawn by `Bsdf::sample`.
+#[derive(Debug)]
+pub struct BsdfSample {
+    /// Ray leaving the hit point, continuing the media and wavelength of the path.
+    pub scattered: Ray,
+    /// `eval / pdf` for the sampled direction. Samples reweighting the
+    /// wavelengths of a spectral path include the change in film response.
+    pub weight: Color,
+    /// Solid angle density of the direction, meaningless for specular lobes.
+    pub pdf: f64,
+    /// The lobe the direction was sampled from.
+    pub lobe: Lobes,
+}
+
+/// Scattering function of a surface or a medium. Directions are in world
+/// space: `wo` is the reverse of `r_in`'s direction and `rec.normal` faces
+/// it, `wi` is the direction light arrives from.
+pub trait Bsdf: Send + Sync + Debug {
+    /// Light scattered towards `wo` per unit of irradiance arriving from
+    /// `wi`: the BSDF value times `|cos θi|`. Phase functions have no
+    /// cosine term.
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color;
+
+    /// Samples `wi` with uniform numbers, `uc` for picking a lobe and `u`
+    /// for the direction. `None` means the path is absorbed.
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<BsdfSample>;
+
+    /// Solid angle density with which `sample` picks `wi`.
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64;
+
+    /// Every lobe this BSDF may sample.
+    fn lobes(&self) -> Lobes;
+
+    /// Radiance the surface emits towards the origin of `r_in`.
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    /// Radiance emitted on average over the surface, to estimate the power
+    /// of lights.
+    fn average_emission(&self) -> Color {
+        Color::default()
+    }
+
+    /// Whether the back face emits as much as the front one.
+    fn two_sided_emission(&self) -> bool {
+        false
+    }
+}
+
+pub type Material = Arc<dyn Bsdf>;
+
+/// Local shading frame at a hit and `wo` expressed in it. Since the normal
+/// faces the incoming ray `wo` is always above the surface.
+pub(crate) fn shading_frame(r_in: &Ray, rec: &HitRecord) -> (Onb, Vec3) {
+    let frame = Onb::build_from_w(&rec.normal);
+    let wo = frame.to_local(&-unit_vector(r_in.direction()));
+    (frame, wo)
+}
+
+#[derive(Clone, Debug)]
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
+}
+
+impl Default for Lambertian {
+    fn default() -> Self {
+        Self::new(Color::default())
+    }
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)))
+    }
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
         Self { albedo }
     }
 }
+
+impl Bsdf for Lambertian {
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let cos_theta = dot_product(&unit_vector(*wi), &rec.normal);
+        if cos_theta <= 0.0 {
+            return Color::default();
+        }
+        self.albedo.value(rec.u, rec.v, &rec.point) * (cos_theta / PI)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let frame = Onb::build_from_w(&rec.normal);
+        let wi = sample_cosine_hemisphere(u);
+        Some(BsdfSample {
+            scattered: r_in.spawn(rec.point, frame.local_vec(&wi)),
+            weight: self.albedo.value(rec.u, rec.v, &rec.point),
+            pdf: wi.z() / PI,
+            lobe: Lobes::DIFFUSE | Lobes::REFLECTION,
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        dot_product(&unit_vector(*wi), &rec.normal).max(0.0) / PI
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::DIFFUSE | Lobes::REFLECTION
+    }
+}
+
+/// Light source emitting the same radiance in every direction from its
+/// front face, or from both faces, and scattering nothing. The radiance may
+/// vary over the surface with a texture, like a screen or a neon sign.
+#[derive(Clone, Debug)]
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+    intensity: f64,
+    two_sided: bool,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::textured(Arc::new(SolidColor::new(emit)))
+    }
+    pub fn textured(emit: Arc<dyn Texture>) -> Self {
+        Self {
+            emit,
+            intensity: 1.0,
+            two_sided: false,
+        }
+    }
+
+    /// Scales the emitted radiance.
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Emits from the back face as well.
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+}
+
+impl Bsdf for DiffuseLight {
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        Color::default()
+    }
+
+    fn sample(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _uc: f64,
+        _u: (f64, f64),
+    ) -> Option<BsdfSample> {
+        None
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::default()
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if !rec.front_face && !self.two_sided {
+            return Color::default();
+        }
+        self.emit.value(rec.u, rec.v, &rec.point) * self.intensity
+    }
+
+    fn average_emission(&self) -> Color {
+        let n = 8;
+        let sum = (0..n * n).fold(Color::default(), |acc, i| {
+            let (u, v) = (
+                ((i % n) as f64 + 0.5) / n as f64,
+                ((i / n) as f64 + 0.5) / n as f64,
+            );
+            acc + self.emit.value(u, v, &Point::default())
+        });
+        sum * self.intensity / (n * n) as f64
+    }
+
+    fn two_sided_emission(&self) -> bool {
+        self.two_sided
+    }
+}
+
+/// Rough diffuse surface made of V-shaped Lambertian microfacets whose slope
+/// angles have standard deviation `sigma` radians, using the qualitative
+/// Oren–Nayar model. A `sigma` of zero is exactly `Lambertian`; rough
+/// surfaces look flatter and brighten towards the light at grazing angles.
+#[derive(Clone, Debug)]
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Color, sigma: f64) -> Self {
+        Self::textured(Arc::new(SolidColor::new(albedo)), sigma)
+    }
+    pub fn textured(albedo: Arc<dyn Texture>, sigma: f64) -> Self {
+        let sigma2 = sigma * sigma;
+        Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    /// The model's factor on top of the Lambertian `albedo / π` for two
+    /// directions in the local shading frame, both above the surface.
+    fn roughness_factor(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        let sin_theta_o = (1.0 - wo.z() * wo.z()).max(0.0).sqrt();
+        let sin_theta_i = (1.0 - wi.z() * wi.z()).max(0.0).sqrt();
+        // cos(φi - φo) without computing either angle.
+        let cos_delta_phi = if sin_theta_o > 1e-4 && sin_theta_i > 1e-4 {
+            ((wi.x() * wo.x() + wi.y() * wo.y()) / (sin_theta_i * sin_theta_o)).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        // α is the larger polar angle, β the smaller one.
+        let (sin_alpha, tan_beta) = if wi.z() > wo.z() {
+            (sin_theta_o, sin_theta_i / wi.z())
+        } else {
+            (sin_theta_i, sin_theta_o / wo.z())
+        };
+        self.a + self.b * cos_delta_phi.max(0.0) * sin_alpha * tan_beta
+    }
+}
+
+impl Bsdf for OrenNayar {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = frame.to_local(&unit_vector(*wi));
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return Color::default();
+        }
+        self.albedo.value(rec.u, rec.v, &rec.point)
+            * (self.roughness_factor(&wo, &wi) * wi.z() / PI)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = sample_cosine_hemisphere(u);
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            scattered: r_in.spawn(rec.point, frame.local_vec(&wi)),
+            weight: self.albedo.value(rec.u, rec.v, &rec.point) * self.roughness_factor(&wo, &wi),
+            pdf: wi.z() / PI,
+            lobe: Lobes::DIFFUSE | Lobes::REFLECTION,
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        dot_product(&unit_vector(*wi), &rec.normal).max(0.0) / PI
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::DIFFUSE | Lobes::REFLECTION
+    }
+}
+
+/// Mirror blurred by jittering the reflected direction within a sphere of
+/// radius `fuzz`. The jitter has no density, so this counts as specular.
 #[derive(Copy, Clone, Debug, Default)]
 pub struct Metal {
     albedo: Color,
@@ -33,58 +334,718 @@ impl Metal {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+impl Bsdf for Metal {
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        Color::default()
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let reflected = reflect(&unit_vector(r_in.direction()), &(rec.normal));
+        let direction = reflected + self.fuzz * sample_uniform_ball(uc, u);
+        // Fuzz can push the reflection below the surface, where it is absorbed.
+        if dot_product(&direction, &rec.normal) <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            scattered: r_in.spawn(rec.point, direction),
+            weight: self.albedo,
+            pdf: 0.0,
+            lobe: Lobes::SPECULAR | Lobes::REFLECTION,
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::SPECULAR | Lobes::REFLECTION
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct Dielectric {
     ir: f64,
+    absorption: Color,
+    dispersion: Option<Dispersion>,
+    scattering: Option<Scattering>,
+    thin_film: Option<ThinFilm>,
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self::with_absorption(ir, Color::default())
+    }
+    /// Colored glass absorbing light inside with the given coefficient per
+    /// unit of distance.
+    pub fn with_absorption(ir: f64, absorption: Color) -> Self {
+        Self {
+            ir,
+            absorption,
+            dispersion: None,
+            scattering: None,
+            thin_film: None,
+        }
+    }
+    /// Glass whose index of refraction varies with the wavelength, splitting
+    /// white light into a spectrum.
+    pub fn with_dispersion(dispersion: Dispersion, absorption: Color) -> Self {
+        Self {
+            ir: dispersion.ior(LAMBDA_D),
+            absorption,
+            dispersion: Some(dispersion),
+            scattering: None,
+            thin_film: None,
+        }
+    }
+    /// Colored glass whose interior tints white light to `color` after
+    /// travelling `distance` through it.
+    pub fn with_color_at_distance(ir: f64, color: Color, distance: f64) -> Self {
+        Self::with_absorption(ir, absorption_for(color, distance))
+    }
+    /// Translucent material like wax, marble or skin: a smooth interface over
+    /// a medium in which paths random walk until they leave again. `albedo`
+    /// is the chance of a particle scattering rather than absorbing light,
+    /// `mean_free_path` the average distance between interactions, both per
+    /// channel, and `g` the anisotropy of the phase function.
+    pub fn subsurface(ir: f64, albedo: Color, mean_free_path: Color, g: f64) -> Self {
+        let extinction = Color::new(
+            1.0 / mean_free_path.x(),
+            1.0 / mean_free_path.y(),
+            1.0 / mean_free_path.z(),
+        );
+        let coefficient = albedo * extinction;
+        Self {
+            scattering: Some(Scattering { coefficient, g }),
+            ..Self::with_absorption(ir, extinction - coefficient)
+        }
+    }
+    /// Coats the surface with a thin film, a soap bubble being a dielectric
+    /// of index one with a film of water.
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.thin_film = Some(film);
+        self
+    }
+    pub fn interior(&self) -> Interior {
+        Interior {
+            ir: self.ir,
+            absorption: self.absorption,
+            dispersion: self.dispersion,
+            scattering: self.scattering,
+        }
     }
     pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
         let r0 = r0.powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
-}
 
-impl Material {
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> (Color, Ray) {
-        match self {
-            Material::Lambertian(l) => {
-                let mut scatter_direction = rec.normal + random_unit_vector();
-                if scatter_direction.near_zero() {
-                    scatter_direction = rec.normal;
+    /// Scatters an RGB path, which only glass that doesn't disperse sees.
+    fn sample_rgb(&self, r_in: &Ray, rec: &HitRecord, uc: f64) -> Option<BsdfSample> {
+        let (n_i, n_t) = interface_iors(r_in, rec, &self.interior());
+        let refraction_ratio = n_i / n_t;
+        let unit_direction = unit_vector(r_in.direction());
+        let neg_unit_direction = unit_direction * -1.0;
+        let cos_theta = dot_product(&neg_unit_direction, &rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+        // A film makes the reflectance colored: reflect with its average and
+        // weight each lobe by how far its color is from it.
+        let (reflectance, probability) = match &self.thin_film {
+            Some(_) if cannot_refract => (Color::new(1.0, 1.0, 1.0), 1.0),
+            Some(film) => {
+                let no_k = Color::default();
+                let base = Color::new(n_t, n_t, n_t);
+                let r = film.reflectance(rec, cos_theta, n_i, base, no_k);
+                (r, (r.x() + r.y() + r.z()) / 3.0)
+            }
+            None if cannot_refract => (Color::new(1.0, 1.0, 1.0), 1.0),
+            None => {
+                let r = Dielectric::reflectance(cos_theta, refraction_ratio);
+                (Color::new(r, r, r), r)
+            }
+        };
+        let (scattered, weight, lobe) = if probability > uc {
+            let direction = reflect(&unit_direction, &rec.normal);
+            let weight = reflectance / probability;
+            (r_in.spawn(rec.point, direction), weight, Lobes::REFLECTION)
+        } else {
+            let direction = refract(&unit_direction, &rec.normal, refraction_ratio);
+            let scattered = transmitted(r_in, rec, self.interior(), direction);
+            let weight = (Color::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - probability);
+            (scattered, weight, Lobes::TRANSMISSION)
+        };
+        Some(BsdfSample {
+            scattered,
+            weight,
+            pdf: 0.0,
+            lobe: Lobes::SPECULAR | lobe,
+        })
+    }
+
+    /// Scatters a spectral path. The hero wavelength picks the direction,
+    /// reflection takes every wavelength along while refraction through
+    /// indices that depend on the wavelength only takes the hero.
+    fn sample_spectral(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        uc: f64,
+        wavelengths: Wavelengths,
+    ) -> Option<BsdfSample> {
+        let interior = self.interior();
+        let iors = |lambda: f64| interface_iors_at(r_in, rec, &interior, Some(lambda));
+        let unit_direction = unit_vector(r_in.direction());
+        let cos_theta = dot_product(&-unit_direction, &rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+        let reflectance = |lambda: f64| {
+            let (n_i, n_t) = iors(lambda);
+            if n_i / n_t * sin_theta > 1.0 {
+                return 1.0;
+            }
+            match &self.thin_film {
+                Some(film) => {
+                    let base = Color::new(n_t, n_t, n_t);
+                    film.reflectance_at(rec, cos_theta, n_i, base, Color::default(), lambda)
                 }
-                let scattered = Ray::new(rec.point, scatter_direction);
-                let attenuation = l.albedo; //p; // we can divide albedo / p as well.
-                (attenuation, scattered)
+                None => Dielectric::reflectance(cos_theta, n_i / n_t),
+            }
+        };
+        let probability = wavelengths.mean(reflectance);
+        let from = r_in.wavelengths();
+
+        if probability > uc {
+            let (reflected, weight) =
+                wavelengths.rescale(from, |lambda| reflectance(lambda) / probability);
+            let direction = reflect(&unit_direction, &rec.normal);
+            return Some(BsdfSample {
+                scattered: r_in.spawn(rec.point, direction).with_wavelengths(reflected),
+                weight,
+                pdf: 0.0,
+                lobe: Lobes::SPECULAR | Lobes::REFLECTION,
+            });
+        }
+
+        let (n_i, n_t) = iors(wavelengths.hero());
+        if n_i / n_t * sin_theta > 1.0 {
+            return None;
+        }
+        let dispersive = wavelengths.active().any(|lambda| {
+            let (a, b) = iors(lambda);
+            a / b != n_i / n_t
+        });
+        let followers = if dispersive {
+            wavelengths.hero_only()
+        } else {
+            wavelengths
+        };
+        let (refracted, weight) = followers.rescale(from, |lambda| {
+            (1.0 - reflectance(lambda)) / (1.0 - probability)
+        });
+        let direction = refract(&unit_direction, &rec.normal, n_i / n_t);
+        Some(BsdfSample {
+            scattered: transmitted(r_in, rec, interior, direction).with_wavelengths(refracted),
+            weight,
+            pdf: 0.0,
+            lobe: Lobes::SPECULAR | Lobes::TRANSMISSION,
+        })
+    }
+}
+
+impl Bsdf for Dielectric {
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        Color::default()
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        // The first dispersive interface a path meets turns it spectral.
+        match (self.dispersion, r_in.wavelengths()) {
+            (Some(_), None) => self.sample_spectral(r_in, rec, uc, Wavelengths::sample(u.0)),
+            (_, Some(wavelengths)) => self.sample_spectral(r_in, rec, uc, *wavelengths),
+            (None, None) => self.sample_rgb(r_in, rec, uc),
+        }
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::SPECULAR | Lobes::REFLECTION | Lobes::TRANSMISSION
+    }
+}
+
+/// Phase function scattering uniformly over the sphere, for participating media.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Isotropic {
+    albedo: Color,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Bsdf for Isotropic {
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> Color {
+        self.albedo / (4.0 * PI)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            scattered: r_in.spawn(rec.point, sample_uniform_sphere(u)),
+            weight: self.albedo,
+            pdf: 1.0 / (4.0 * PI),
+            lobe: Lobes::DIFFUSE | Lobes::REFLECTION | Lobes::TRANSMISSION,
+        })
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _wi: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::DIFFUSE | Lobes::REFLECTION | Lobes::TRANSMISSION
+    }
+}
+
+/// Anisotropic phase function for participating media. `g` in `(-1, 1)`
+/// goes from back scattering to forward scattering, `0` is isotropic.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HenyeyGreenstein {
+    albedo: Color,
+    g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Color, g: f64) -> Self {
+        Self { albedo, g }
+    }
+}
+
+impl Bsdf for HenyeyGreenstein {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        self.albedo * self.pdf(r_in, rec, wi)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let direction = unit_vector(r_in.direction());
+        let wi = sample_henyey_greenstein(&direction, self.g, u);
+        Some(BsdfSample {
+            scattered: r_in.spawn(rec.point, wi),
+            weight: self.albedo,
+            pdf: henyey_greenstein(dot_product(&direction, &wi), self.g),
+            lobe: Lobes::GLOSSY | Lobes::REFLECTION | Lobes::TRANSMISSION,
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, _rec: &HitRecord, wi: &Vec3) -> f64 {
+        let cos_theta = dot_product(&unit_vector(r_in.direction()), &unit_vector(*wi));
+        henyey_greenstein(cos_theta, self.g)
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::GLOSSY | Lobes::REFLECTION | Lobes::TRANSMISSION
+    }
+}
+
+/// Rough metal described by a microfacet distribution and a complex index
+/// of refraction `eta + i k` per color channel.
+#[derive(Clone, Debug, Default)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    microfacet: Microfacet,
+    thin_film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    /// `alpha` is the microfacet width, zero gives a polished mirror.
+    pub fn new(eta: Color, k: Color, distribution: Distribution, alpha: f64) -> Self {
+        Self {
+            eta,
+            k,
+            microfacet: Microfacet::new(distribution, alpha),
+            thin_film: None,
+        }
+    }
+    /// Covers the metal with a thin film, like an anodized oxide layer.
+    pub fn with_thin_film(mut self, film: ThinFilm) -> Self {
+        self.thin_film = Some(film);
+        self
+    }
+    pub fn gold(distribution: Distribution, alpha: f64) -> Self {
+        Self::new(
+            Color::new(0.143119, 0.374957, 1.44248),
+            Color::new(3.98316, 2.38572, 1.60322),
+            distribution,
+            alpha,
+        )
+    }
+    pub fn copper(distribution: Distribution, alpha: f64) -> Self {
+        Self::new(
+            Color::new(0.200438, 0.924033, 1.10221),
+            Color::new(3.91295, 2.45285, 2.14219),
+            distribution,
+            alpha,
+        )
+    }
+    pub fn aluminum(distribution: Distribution, alpha: f64) -> Self {
+        Self::new(
+            Color::new(1.65746, 0.880369, 0.521229),
+            Color::new(9.22387, 6.26952, 4.837),
+            distribution,
+            alpha,
+        )
+    }
+    pub fn silver(distribution: Distribution, alpha: f64) -> Self {
+        Self::new(
+            Color::new(0.155265, 0.116723, 0.138342),
+            Color::new(4.82835, 3.12225, 2.14696),
+            distribution,
+            alpha,
+        )
+    }
+    /// Reflectance for the path of `r_in`. A film reweights the wavelengths
+    /// of a spectral path, those the reflected path carries are returned.
+    fn fresnel(&self, r_in: &Ray, rec: &HitRecord, cos_theta: f64) -> (Color, Option<Wavelengths>) {
+        let outer = r_in.interior().current();
+        match (&self.thin_film, r_in.wavelengths()) {
+            (Some(film), Some(wavelengths)) => {
+                let (reflected, factor) = wavelengths.rescale(Some(wavelengths), |lambda| {
+                    let n_outer = outer.ir_at(Some(lambda));
+                    film.reflectance_at(rec, cos_theta, n_outer, self.eta, self.k, lambda)
+                });
+                (factor, Some(reflected))
+            }
+            (Some(film), None) => {
+                let n_outer = outer.ir_at(None);
+                (
+                    film.reflectance(rec, cos_theta, n_outer, self.eta, self.k),
+                    None,
+                )
+            }
+            (None, _) => (fresnel_conductor(cos_theta, self.eta, self.k), None),
+        }
+    }
+}
+
+impl Bsdf for Conductor {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = frame.to_local(&unit_vector(*wi));
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return Color::default();
+        }
+        let m = unit_vector(wo + wi);
+        let (fresnel, _) = self.fresnel(r_in, rec, dot_product(&wo, &m));
+        fresnel * (self.microfacet.d(&m) * self.microfacet.g(&wo, &wi) / (4.0 * wo.z()))
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, _uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        // Mirror around a micro normal visible from wo. With visible normal
+        // sampling the weight reduces to F * G2 / G1.
+        let (frame, wo) = shading_frame(r_in, rec);
+        let m = self.microfacet.sample_visible_normal(&wo, u.0, u.1);
+        let wi = reflect(&-wo, &m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let wo_dot_m = dot_product(&wo, &m);
+        let (fresnel, wavelengths) = self.fresnel(r_in, rec, wo_dot_m);
+        let mut scattered = r_in.spawn(rec.point, frame.local_vec(&wi));
+        if let Some(wavelengths) = wavelengths {
+            scattered = scattered.with_wavelengths(wavelengths);
+        }
+        Some(BsdfSample {
+            scattered,
+            weight: fresnel * (self.microfacet.g(&wo, &wi) / self.microfacet.g1(&wo)),
+            pdf: self.microfacet.pdf_visible_normal(&wo, &m) / (4.0 * wo_dot_m),
+            lobe: Lobes::GLOSSY | Lobes::REFLECTION,
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = frame.to_local(&unit_vector(*wi));
+        if wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return 0.0;
+        }
+        let m = unit_vector(wo + wi);
+        self.microfacet.pdf_visible_normal(&wo, &m) / (4.0 * dot_product(&wo, &m))
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::GLOSSY | Lobes::REFLECTION
+    }
+}
+
+/// Frosted glass: microfacet reflection and transmission after Walter et
+/// al. 2007, with the exact dielectric Fresnel term.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RoughDielectric {
+    ir: f64,
+    absorption: Color,
+    microfacet: Microfacet,
+}
+
+impl RoughDielectric {
+    /// `ir` is the index of refraction, as for `Dielectric::new`, and `alpha`
+    /// the microfacet width.
+    pub fn new(ir: f64, distribution: Distribution, alpha: f64) -> Self {
+        Self::with_absorption(ir, Color::default(), distribution, alpha)
+    }
+    pub fn with_absorption(
+        ir: f64,
+        absorption: Color,
+        distribution: Distribution,
+        alpha: f64,
+    ) -> Self {
+        Self {
+            ir,
+            absorption,
+            microfacet: Microfacet::new(distribution, alpha),
+        }
+    }
+    pub fn interior(&self) -> Interior {
+        Interior::new(self.ir, self.absorption)
+    }
+}
+
+impl Bsdf for RoughDielectric {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = frame.to_local(&unit_vector(*wi));
+        let (n_i, n_t) = interface_iors(r_in, rec, &self.interior());
+        let value = rough_dielectric_eval(&self.microfacet, n_t / n_i, &wo, &wi).0;
+        Color::new(value, value, value)
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        // Pick reflection or refraction through a visible micro normal with
+        // probability given by its Fresnel term, which leaves G2 / G1 as the
+        // weight of either lobe.
+        let (frame, wo) = shading_frame(r_in, rec);
+        let (n_i, n_t) = interface_iors(r_in, rec, &self.interior());
+        let eta = n_t / n_i;
+        let m = self.microfacet.sample_visible_normal(&wo, u.0, u.1);
+        let wo_dot_m = dot_product(&wo, &m);
+        if wo_dot_m <= 0.0 {
+            return None;
+        }
+        let (wi, scattered, lobe) = if uc < fresnel_dielectric(wo_dot_m, eta) {
+            let wi = reflect(&-wo, &m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let scattered = r_in.spawn(rec.point, frame.local_vec(&wi));
+            (wi, scattered, Lobes::REFLECTION)
+        } else {
+            let wi = refract(&-wo, &m, 1.0 / eta);
+            if wi.z() >= 0.0 {
+                return None;
             }
-            Material::Metal(m) => {
-                let reflected = reflect(&unit_vector(r_in.direction()), &(rec.normal));
-                let scattered = Ray::new(rec.point, reflected + m.fuzz * random_in_unit_sphere());
-                let attenuation = m.albedo;
-                (attenuation, scattered)
+            let direction = frame.local_vec(&wi);
+            let scattered = transmitted(r_in, rec, self.interior(), direction);
+            (wi, scattered, Lobes::TRANSMISSION)
+        };
+        let weight = self.microfacet.g(&wo, &wi) / self.microfacet.g1(&wo);
+        Some(BsdfSample {
+            scattered,
+            weight: Color::new(weight, weight, weight),
+            pdf: rough_dielectric_eval(&self.microfacet, eta, &wo, &wi).1,
+            lobe: Lobes::GLOSSY | lobe,
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = frame.to_local(&unit_vector(*wi));
+        let (n_i, n_t) = interface_iors(r_in, rec, &self.interior());
+        rough_dielectric_eval(&self.microfacet, n_t / n_i, &wo, &wi).1
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::GLOSSY | Lobes::REFLECTION | Lobes::TRANSMISSION
+    }
+}
+
+/// Walter et al.'s rough dielectric in the local frame, where `wo` is above
+/// the surface and `eta` is the relative index across it. Returns the BSDF
+/// value times `|cos θi|` and the density of sampling `wi` with visible
+/// normals and Fresnel lobe selection. Transmission leaves out the `1 / η²`
+/// radiance scaling, like the smooth `Dielectric` does.
+pub(crate) fn rough_dielectric_eval(
+    microfacet: &Microfacet,
+    eta: f64,
+    wo: &Vec3,
+    wi: &Vec3,
+) -> (f64, f64) {
+    if wo.z() <= 0.0 || wi.z() == 0.0 {
+        return (0.0, 0.0);
+    }
+    let reflecting = wi.z() > 0.0;
+    let m = if reflecting {
+        unit_vector(*wo + *wi)
+    } else {
+        unit_vector(*wo + eta * *wi)
+    };
+    let m = if m.z() < 0.0 { -m } else { m };
+    let wo_dot_m = dot_product(wo, &m);
+    let wi_dot_m = dot_product(wi, &m);
+    // Discard back facing micro normals.
+    if wo_dot_m <= 0.0 || (reflecting && wi_dot_m <= 0.0) || (!reflecting && wi_dot_m >= 0.0) {
+        return (0.0, 0.0);
+    }
+
+    let fresnel = fresnel_dielectric(wo_dot_m, eta);
+    let d = microfacet.d(&m);
+    let g = microfacet.g(wo, wi);
+    let pdf_m = microfacet.pdf_visible_normal(wo, &m);
+    if reflecting {
+        let value = fresnel * d * g / (4.0 * wo.z());
+        let pdf = fresnel * pdf_m / (4.0 * wo_dot_m);
+        (value, pdf)
+    } else {
+        let denom = (wo_dot_m + eta * wi_dot_m).powi(2);
+        let jacobian = eta * eta * wi_dot_m.abs() / denom;
+        let value = (1.0 - fresnel) * d * g * wo_dot_m * jacobian / wo.z();
+        let pdf = (1.0 - fresnel) * pdf_m * jacobian;
+        (value, pdf)
+    }
+}
+
+/// Absorption coefficient that turns white into `color` over `distance`.
+fn absorption_for(color: Color, distance: f64) -> Color {
+    let coefficient = |c: f64| -c.clamp(1e-6, 1.0).ln() / distance;
+    Color::new(
+        coefficient(color.x()),
+        coefficient(color.y()),
+        coefficient(color.z()),
+    )
+}
+
+/// Indices of refraction on the incident and transmitted side of a
+/// dielectric interface, taking the media the ray is nested in into account.
+pub(crate) fn interface_iors(r_in: &Ray, rec: &HitRecord, interior: &Interior) -> (f64, f64) {
+    interface_iors_at(r_in, rec, interior, r_in.wavelength())
+}
+
+/// Indices of refraction as in `interface_iors`, seen by `wavelength`.
+fn interface_iors_at(
+    r_in: &Ray,
+    rec: &HitRecord,
+    interior: &Interior,
+    wavelength: Option<f64>,
+) -> (f64, f64) {
+    let stack = r_in.interior();
+    if rec.front_face {
+        (
+            stack.current().ir_at(wavelength),
+            interior.ir_at(wavelength),
+        )
+    } else {
+        (
+            interior.ir_at(wavelength),
+            stack.outside(interior).ir_at(wavelength),
+        )
+    }
+}
+
+/// The ray refracted through the interface of `interior`, entering or
+/// leaving it.
+pub(crate) fn transmitted(r_in: &Ray, rec: &HitRecord, interior: Interior, direction: Vec3) -> Ray {
+    let stack = r_in.interior();
+    let stack = if rec.front_face {
+        stack.entering(interior)
+    } else {
+        stack.exiting(&interior)
+    };
+    r_in.spawn(rec.point, direction).with_interior(stack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        spectrum::WAVELENGTH_SAMPLES,
+        units::{point::Point, vec3::random_f64},
+    };
+
+    fn hit_on_plane() -> (Ray, HitRecord) {
+        let ray = Ray::new(Point::new(-1.0, 1.0, 0.3), Vec3::new(1.0, -1.0, -0.3));
+        let record = HitRecord::new(Point::default(), Vec3::new(0.0, 1.0, 0.0), 1.0);
+        (ray, record)
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let (ray, record) = hit_on_plane();
+        let albedo = Color::new(0.8, 0.5, 0.2);
+        let rough = OrenNayar::new(albedo, 0.0);
+        let smooth = Lambertian::new(albedo);
+        for wi in [Vec3::new(0.3, 1.0, 0.1), Vec3::new(-2.0, 0.5, 1.0)] {
+            let difference = rough.eval(&ray, &record, &wi) - smooth.eval(&ray, &record, &wi);
+            assert!(difference.length() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn oren_nayar_sample_weight_matches_eval() {
+        let (ray, record) = hit_on_plane();
+        let material = OrenNayar::new(Color::new(0.8, 0.5, 0.2), 0.5);
+        for u in [(0.1, 0.2), (0.5, 0.9), (0.95, 0.4)] {
+            let sample = material.sample(&ray, &record, 0.5, u).unwrap();
+            let wi = sample.scattered.direction();
+            let pdf = material.pdf(&ray, &record, &wi);
+            let expected = material.eval(&ray, &record, &wi) / pdf;
+            assert!((sample.pdf - pdf).abs() < 1e-9);
+            assert!((sample.weight - expected).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn fuzzy_metal_samples_follow_their_inputs() {
+        let (ray, record) = hit_on_plane();
+        let metal = Metal::new(Color::new(0.9, 0.9, 0.9), 1.0);
+        let direction = |uc, u| {
+            metal
+                .sample(&ray, &record, uc, u)
+                .map(|sample| sample.scattered.direction())
+        };
+        assert_eq!(direction(0.3, (0.2, 0.7)), direction(0.3, (0.2, 0.7)));
+        // Fuzz pointing straight down pushes the reflection below the surface.
+        assert!(direction(1.0, (0.5, 0.75)).is_none());
+        for i in 0..1000 {
+            let u = ((i % 10) as f64 / 10.0, (i / 100) as f64 / 10.0);
+            if let Some(wi) = direction(((i / 10) % 10) as f64 / 10.0, u) {
+                assert!(dot_product(&wi, &record.normal) > 0.0);
             }
-            Material::Dielectric(d) => {
-                let attennuation = Color::new(1.0, 1.0, 1.0);
-                let refraction_ratio = if rec.front_face { 1.0 / d.ir } else { d.ir };
-                let unit_direction = unit_vector(r_in.direction());
-                let neg_unit_direction = unit_direction * -1.0;
-                let cos_theta = dot_product(&neg_unit_direction, &rec.normal).min(1.0);
-                let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
-                let cannot_refract = refraction_ratio * sin_theta > 1.0;
-                let direction = if cannot_refract
-                    || Dielectric::reflectance(cos_theta, refraction_ratio) > random_f64()
-                {
-                    reflect(&unit_direction, &rec.normal)
-                } else {
-                    refract(&unit_direction, &rec.normal, refraction_ratio)
-                };
-                let scattered = Ray::new(rec.point, direction);
-                (attennuation, scattered)
+        }
+    }
+
+    #[test]
+    fn dispersive_glass_recombines_to_white() {
+        let (ray, mut record) = hit_on_plane();
+        record.front_face = true;
+        let glass = Dielectric::with_dispersion(Dispersion::diamond(), Color::default());
+        let n = 20_000;
+        let mut total = Color::default();
+        for i in 0..n {
+            let u = (i as f64 + 0.5) / n as f64;
+            let sample = glass.sample(&ray, &record, random_f64(), (u, 0.5)).unwrap();
+            let wavelengths = sample.scattered.wavelengths().unwrap();
+            // Reflection carries every wavelength, refraction the hero only.
+            let carried = wavelengths.active().count();
+            if sample.lobe.contains(Lobes::TRANSMISSION) {
+                assert_eq!(carried, 1);
+            } else {
+                assert_eq!(carried, WAVELENGTH_SAMPLES);
             }
+            total += sample.weight;
         }
+        let mean = total / n as f64;
+        assert!((mean - Color::new(1.0, 1.0, 1.0)).length() < 0.02, "{mean}");
     }
 }
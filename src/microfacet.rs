@@ -0,0 +1,305 @@
+use crate::{
+    units::vec3::{cross_product, unit_vector, Vec3},
+    PI,
+};
+
+/// Normal distribution function of a microfacet surface.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Distribution {
+    #[default]
+    Ggx,
+    Beckmann,
+}
+
+/// Isotropic microfacet model with Smith height-correlated masking and
+/// shadowing. Every direction is given in the local shading frame, where
+/// the macro surface normal is +z.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Microfacet {
+    distribution: Distribution,
+    alpha: f64,
+}
+
+impl Microfacet {
+    /// `alpha` is the width of the distribution, as in `roughness²`.
+    pub fn new(distribution: Distribution, alpha: f64) -> Self {
+        // Very small widths break the sampling routines numerically.
+        Self {
+            distribution,
+            alpha: alpha.max(1e-4),
+        }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Density of micro normals `m`, normalised so that the projected area
+    /// `∫ D(m) cos θm dm` is one.
+    pub fn d(&self, m: &Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let cos2 = m.z() * m.z();
+        let tan2 = (1.0 - cos2) / cos2;
+        let alpha2 = self.alpha * self.alpha;
+        match self.distribution {
+            Distribution::Ggx => {
+                let root = alpha2 + tan2;
+                alpha2 / (PI * cos2 * cos2 * root * root)
+            }
+            Distribution::Beckmann => (-tan2 / alpha2).exp() / (PI * alpha2 * cos2 * cos2),
+        }
+    }
+
+    /// Smith auxiliary function Λ(w).
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 >= 1.0 {
+            return 0.0;
+        }
+        let tan = ((1.0 - cos2) / cos2).sqrt();
+        match self.distribution {
+            Distribution::Ggx => 0.5 * (-1.0 + (1.0 + (self.alpha * tan).powi(2)).sqrt()),
+            Distribution::Beckmann => {
+                let a = 1.0 / (self.alpha * tan);
+                if a >= 1.6 {
+                    0.0
+                } else {
+                    (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+                }
+            }
+        }
+    }
+
+    /// Masking of direction `w`.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Joint masking and shadowing of `wo` and `wi`.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of normals visible from `wo`, the distribution sampled by
+    /// `sample_visible_normal`.
+    pub fn pdf_visible_normal(&self, wo: &Vec3, m: &Vec3) -> f64 {
+        let cos_o = wo.z().abs();
+        if cos_o == 0.0 {
+            return 0.0;
+        }
+        let wo_dot_m = wo.x() * m.x() + wo.y() * m.y() + wo.z() * m.z();
+        self.g1(wo) * wo_dot_m.max(0.0) * self.d(m) / cos_o
+    }
+
+    /// Samples a micro normal visible from `wo` (which must be above the
+    /// surface) with the uniform random numbers `u1` and `u2`.
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        match self.distribution {
+            Distribution::Ggx => self.sample_ggx(wo, u1, u2),
+            Distribution::Beckmann => self.sample_beckmann(wo, u1, u2),
+        }
+    }
+
+    /// Heitz 2018, "Sampling the GGX Distribution of Visible Normals".
+    fn sample_ggx(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let vh = unit_vector(Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = cross_product(&vh, &t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        unit_vector(Vec3::new(
+            self.alpha * nh.x(),
+            self.alpha * nh.y(),
+            nh.z().max(1e-6),
+        ))
+    }
+
+    /// Heitz and d'Eon 2014, "Importance Sampling Microfacet-Based BSDFs
+    /// using the Distribution of Visible Normals": sample slopes for the
+    /// stretched unit roughness configuration, then rotate and unstretch.
+    fn sample_beckmann(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let stretched = unit_vector(Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+        let cos_theta = stretched.z();
+        let (sin_phi, cos_phi) = if cos_theta < 0.99999 {
+            let r = (stretched.x() * stretched.x() + stretched.y() * stretched.y()).sqrt();
+            (stretched.y() / r, stretched.x() / r)
+        } else {
+            (0.0, 1.0)
+        };
+
+        let (slope_x, slope_y) = sample_beckmann_slopes(cos_theta, u1, u2);
+        let slope_x_rotated = cos_phi * slope_x - sin_phi * slope_y;
+        let slope_y_rotated = sin_phi * slope_x + cos_phi * slope_y;
+        unit_vector(Vec3::new(
+            -self.alpha * slope_x_rotated,
+            -self.alpha * slope_y_rotated,
+            1.0,
+        ))
+    }
+}
+
+/// Visible slopes of the unit Beckmann distribution for an incident
+/// direction with the given `cos_theta` and azimuth zero.
+fn sample_beckmann_slopes(cos_theta: f64, u1: f64, u2: f64) -> (f64, f64) {
+    if cos_theta > 0.99999 {
+        let r = (-(1.0 - u1).ln()).sqrt();
+        let phi = 2.0 * PI * u2;
+        return (r * phi.cos(), r * phi.sin());
+    }
+
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let tan_theta = sin_theta / cos_theta;
+    let cot_theta = 1.0 / tan_theta;
+    let inv_sqrt_pi = 1.0 / PI.sqrt();
+
+    // Invert the slope CDF with a bisection safeguarded Newton search,
+    // starting from a fitted guess.
+    let mut a = -1.0;
+    let mut c = erf(cot_theta);
+    let u = u1.max(1e-6);
+    let theta = cos_theta.acos();
+    let fit = 1.0 + theta * (-0.876 + theta * (0.4265 - 0.0594 * theta));
+    let mut b = c - (1.0 + c) * (1.0 - u).powf(fit);
+    let normalization = 1.0 / (1.0 + c + inv_sqrt_pi * tan_theta * (-cot_theta * cot_theta).exp());
+
+    for _ in 0..100 {
+        if !(a..=c).contains(&b) {
+            b = 0.5 * (a + c);
+        }
+        let inv_erf = erfinv(b);
+        let value =
+            normalization * (1.0 + b + inv_sqrt_pi * tan_theta * (-inv_erf * inv_erf).exp()) - u;
+        if value.abs() < 1e-5 {
+            break;
+        }
+        if value > 0.0 {
+            c = b;
+        } else {
+            a = b;
+        }
+        let derivative = normalization * (1.0 - inv_erf * tan_theta);
+        b -= value / derivative;
+    }
+
+    (erfinv(b), erfinv(2.0 * u2.max(1e-6) - 1.0))
+}
+
+/// Error function, Abramowitz and Stegun 7.1.26.
+fn erf(x: f64) -> f64 {
+    let sign = x.signum();
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let y = 1.0
+        - (((((1.061405429 * t - 1.453152027) * t) + 1.421413741) * t - 0.284496736) * t
+            + 0.254829592)
+            * t
+            * (-x * x).exp();
+    sign * y
+}
+
+/// Inverse error function, Giles' single precision approximation.
+fn erfinv(x: f64) -> f64 {
+    let x = x.clamp(-0.99999, 0.99999);
+    let mut w = -((1.0 - x) * (1.0 + x)).ln();
+    let p = if w < 5.0 {
+        w -= 2.5;
+        [
+            3.43273939e-07,
+            -3.5233877e-06,
+            -4.39150654e-06,
+            0.00021858087,
+            -0.00125372503,
+            -0.00417768164,
+            0.246640727,
+            1.50140941,
+        ]
+        .iter()
+        .fold(2.81022636e-08, |p, c| c + p * w)
+    } else {
+        w = w.sqrt() - 3.0;
+        [
+            0.000100950558,
+            0.00134934322,
+            -0.00367342844,
+            0.00573950773,
+            -0.0076224613,
+            0.00943887047,
+            1.00167406,
+            2.83297682,
+        ]
+        .iter()
+        .fold(-0.000200214257, |p, c| c + p * w)
+    };
+    p * x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::vec3::random_f64;
+
+    /// Midpoint rule over the upper hemisphere.
+    fn integrate(f: impl Fn(&Vec3) -> f64) -> f64 {
+        let (n_theta, n_phi) = (1000, 200);
+        let d_theta = 0.5 * PI / n_theta as f64;
+        let d_phi = 2.0 * PI / n_phi as f64;
+        let mut sum = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let m = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(&m) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn distributions_are_normalised() {
+        for distribution in [Distribution::Ggx, Distribution::Beckmann] {
+            let microfacet = Microfacet::new(distribution, 0.3);
+            let projected_area = integrate(|m| microfacet.d(m) * m.z());
+            assert!((projected_area - 1.0).abs() < 1e-2, "{distribution:?}");
+        }
+    }
+
+    #[test]
+    fn visible_normals_follow_their_pdf() {
+        let wo = unit_vector(Vec3::new(0.6, 0.2, 0.5));
+        for distribution in [Distribution::Ggx, Distribution::Beckmann] {
+            let microfacet = Microfacet::new(distribution, 0.5);
+            let expected = integrate(|m| m.z() * microfacet.pdf_visible_normal(&wo, m));
+            let n = 200_000;
+            let estimate = (0..n)
+                .map(|_| {
+                    microfacet
+                        .sample_visible_normal(&wo, random_f64(), random_f64())
+                        .z()
+                })
+                .sum::<f64>()
+                / n as f64;
+            assert!(
+                (estimate - expected).abs() < 5e-3,
+                "{distribution:?}: {estimate} vs {expected}"
+            );
+        }
+    }
+}
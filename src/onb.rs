@@ -0,0 +1,53 @@
+use crate::units::vec3::{cross_product, dot_product, unit_vector, Vec3};
+
+/// Orthonormal basis, used to turn directions sampled around the z axis
+/// into world space directions around an arbitrary `w`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn build_from_w(n: &Vec3) -> Self {
+        let w = unit_vector(*n);
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = unit_vector(cross_product(&w, &a));
+        let u = cross_product(&w, &v);
+        Self { u, v, w }
+    }
+
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        a * self.u + b * self.v + c * self.w
+    }
+
+    pub fn local_vec(&self, a: &Vec3) -> Vec3 {
+        self.local(a.x(), a.y(), a.z())
+    }
+
+    /// Inverse of `local_vec`: expresses a world space vector in this basis.
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(
+            dot_product(a, &self.u),
+            dot_product(a, &self.v),
+            dot_product(a, &self.w),
+        )
+    }
+}
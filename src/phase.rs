@@ -0,0 +1,50 @@
+use crate::onb::Onb;
+use crate::units::vec3::Vec3;
+use crate::PI;
+
+/// Henyey–Greenstein phase function. `cos_theta` is measured between the
+/// propagation direction and the scattered direction, so `g > 0` favours
+/// forward scattering and `g < 0` back scattering.
+pub fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.max(0.0).sqrt())
+}
+
+/// Samples a scattered direction around `direction` following the
+/// Henyey–Greenstein distribution, from two uniform numbers.
+pub fn sample_henyey_greenstein(direction: &Vec3, g: f64, u: (f64, f64)) -> Vec3 {
+    let (u1, u2) = u;
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u1
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+        (1.0 + g * g - s * s) / (2.0 * g)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * u2;
+    Onb::build_from_w(direction).local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn henyey_greenstein_is_isotropic_for_zero_g() {
+        let expected = 1.0 / (4.0 * PI);
+        assert!((henyey_greenstein(0.3, 0.0) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn henyey_greenstein_integrates_to_one() {
+        let n = 100_000;
+        let d_cos = 2.0 / n as f64;
+        let integral: f64 = (0..n)
+            .map(|i| {
+                let cos_theta = -1.0 + (i as f64 + 0.5) * d_cos;
+                2.0 * PI * henyey_greenstein(cos_theta, 0.7) * d_cos
+            })
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-3);
+    }
+}
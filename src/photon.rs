@@ -0,0 +1,690 @@
+use rayon::prelude::*;
+
+use crate::{
+    camera::Camera,
+    integrator::Integrator,
+    ray::{HitRecord, Ray},
+    scene::Scene,
+    units::{
+        color::Color,
+        point::Point,
+        vec3::{dot_product, random_f64, unit_vector, Vec3},
+    },
+    PI,
+};
+
+/// Light a photon carried to where it landed.
+#[derive(Copy, Clone, Debug)]
+pub struct Photon {
+    pub position: Point,
+    /// Direction the photon travelled in, unit length.
+    pub direction: Vec3,
+    /// Share of the power of the lights the photon stands for.
+    pub power: Color,
+}
+
+/// Photons in a balanced kd-tree. The tree is implicit: each slice of the
+/// array holds its node at the middle, the photon splitting the others at
+/// its median along the node's axis.
+#[derive(Clone, Debug, Default)]
+pub struct PhotonMap {
+    photons: Vec<Photon>,
+    axes: Vec<usize>,
+}
+
+impl PhotonMap {
+    pub fn new(mut photons: Vec<Photon>) -> Self {
+        let mut axes = vec![0; photons.len()];
+        build(&mut photons, &mut axes);
+        Self { photons, axes }
+    }
+
+    /// Traces `count` photons from the lights, bouncing at most `max_depth`
+    /// times, and keeps those that land on surfaces light can be gathered
+    /// on. Light arriving straight from the lights isn't stored, sampling
+    /// the lights finds it with less noise.
+    ///
+    /// Lights at infinity emit photons from a disk facing the scene, see
+    /// `SceneDisk`, so the caustics they cast show as well.
+    pub fn trace(scene: &Scene, count: usize, max_depth: usize) -> Self {
+        let photons = (0..count)
+            .into_par_iter()
+            .flat_map_iter(|_| trace_photon(scene, count, max_depth))
+            .collect();
+        Self::new(photons)
+    }
+
+    pub fn len(&self) -> usize {
+        self.photons.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.photons.is_empty()
+    }
+
+    /// Calls `f` with every photon within `radius` of `point`.
+    pub fn for_each_within(&self, point: &Point, radius: f64, mut f: impl FnMut(&Photon)) {
+        search(&self.photons, &self.axes, point, radius, &mut f);
+    }
+}
+
+fn build(photons: &mut [Photon], axes: &mut [usize]) {
+    if photons.is_empty() {
+        return;
+    }
+    // Split along the axis the photons spread the most over.
+    let (min, max) = photons.iter().fold(
+        (
+            Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        ),
+        |(min, max), photon| {
+            let p = photon.position;
+            (
+                Vec3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())),
+                Vec3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())),
+            )
+        },
+    );
+    let extent = max - min;
+    let axis = (0..3)
+        .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+        .unwrap_or(0);
+    let mid = photons.len() / 2;
+    photons.select_nth_unstable_by(mid, |a, b| a.position[axis].total_cmp(&b.position[axis]));
+    axes[mid] = axis;
+    let (left, right) = photons.split_at_mut(mid);
+    let (left_axes, right_axes) = axes.split_at_mut(mid);
+    build(left, left_axes);
+    build(&mut right[1..], &mut right_axes[1..]);
+}
+
+fn search(
+    photons: &[Photon],
+    axes: &[usize],
+    point: &Point,
+    radius: f64,
+    f: &mut impl FnMut(&Photon),
+) {
+    if photons.is_empty() {
+        return;
+    }
+    let mid = photons.len() / 2;
+    let photon = &photons[mid];
+    if (photon.position - *point).length_squared() <= radius * radius {
+        f(photon);
+    }
+    let d = point[axes[mid]] - photon.position[axes[mid]];
+    if d <= radius {
+        search(&photons[..mid], &axes[..mid], point, radius, f);
+    }
+    if d >= -radius {
+        search(&photons[mid + 1..], &axes[mid + 1..], point, radius, f);
+    }
+}
+
+/// Photons one path from a light leaves behind, each carrying its share of
+/// the power of `count` paths.
+fn trace_photon(scene: &Scene, count: usize, max_depth: usize) -> Vec<Photon> {
+    let mut photons = vec![];
+    let lights = &scene.lights;
+    let (index, pmf, emission) = match lights.sample_any_emission(
+        random_f64(),
+        (random_f64(), random_f64()),
+        (random_f64(), random_f64()),
+    ) {
+        Some(sample) => sample,
+        None => return photons,
+    };
+    let pdf = emission.pdf_position * pmf * emission.pdf_direction;
+    if pdf <= 0.0 {
+        return photons;
+    }
+    let direction = unit_vector(emission.direction);
+    let mut beta =
+        lights.light(index).emission(&emission.record, &direction) / (pdf * count as f64);
+    if !emission.delta_position {
+        beta *= dot_product(&emission.record.normal, &direction).abs();
+    }
+
+    let mut ray = Ray::new(emission.record.point, direction);
+    for depth in 0..=max_depth {
+        let rec = match scene.world.hit(&ray, 0.001, f64::INFINITY) {
+            Some(rec) => rec,
+            None => break,
+        };
+        let length = ray.direction().length();
+        beta = beta * ray.interior().current().transmittance(rec.t * length);
+        if depth > 0 && rec.material.lobes().has_non_specular() {
+            photons.push(Photon {
+                position: rec.point,
+                direction: ray.direction() / length,
+                power: beta,
+            });
+        }
+        let sample = rec
+            .material
+            .sample(&ray, &rec, random_f64(), (random_f64(), random_f64()));
+        let sample = match sample {
+            Some(sample) => sample,
+            None => break,
+        };
+        beta = beta * sample.weight;
+        if beta == Color::default() {
+            break;
+        }
+        ray = sample.scattered;
+    }
+    photons
+}
+
+/// Where a camera path stops to gather photons: the first surface it
+/// reaches through specular bounces that isn't purely specular.
+struct VisiblePoint {
+    record: HitRecord,
+    ray: Ray,
+    beta: Color,
+}
+
+impl VisiblePoint {
+    /// Follows the camera ray `r` to its visible point. Returns the light
+    /// found on the way and lighting the point directly, with the point
+    /// unless the path escaped or ended.
+    fn trace(r: &Ray, scene: &Scene, max_depth: usize) -> (Color, Option<VisiblePoint>) {
+        let (world, lights) = (&scene.world, &scene.lights);
+        let mut radiance = Color::default();
+        let mut beta = Color::new(1.0, 1.0, 1.0);
+        let mut ray = *r;
+        for depth in 0..=max_depth {
+            let rec = match world.hit(&ray, 0.001, f64::INFINITY) {
+                Some(rec) => rec,
+                None => {
+                    let escaped = match (depth, &scene.backplate) {
+                        (0, Some(backplate)) => backplate.radiance(&ray.direction()),
+                        _ => scene.background.radiance(&ray) + lights.escaped(&ray),
+                    };
+                    return (radiance + beta * escaped, None);
+                }
+            };
+            beta = beta
+                * ray
+                    .interior()
+                    .current()
+                    .transmittance(rec.t * ray.direction().length());
+            radiance += beta * rec.material.emitted(&ray, &rec);
+            if rec.material.lobes().has_non_specular() {
+                radiance += beta * Integrator::NextEvent.sample_lights(&ray, &rec, world, lights);
+                let point = VisiblePoint {
+                    record: rec,
+                    ray,
+                    beta,
+                };
+                return (radiance, Some(point));
+            }
+            let sample =
+                rec.material
+                    .sample(&ray, &rec, random_f64(), (random_f64(), random_f64()));
+            let sample = match sample {
+                Some(sample) => sample,
+                None => break,
+            };
+            beta = beta * sample.weight;
+            ray = sample.scattered;
+        }
+        (radiance, None)
+    }
+
+    /// Flux of the photons within `radius` scattered towards the camera, not
+    /// yet weighted by `beta`, and how many there were.
+    fn gather(&self, map: &PhotonMap, radius: f64) -> (Color, usize) {
+        let (record, material) = (&self.record, &self.record.material);
+        let mut flux = Color::default();
+        let mut count = 0;
+        map.for_each_within(&record.point, radius, |photon| {
+            let wi = -photon.direction;
+            // The BSDF comes with the cosine, the photon's power doesn't.
+            let cos_theta = dot_product(&wi, &record.normal).abs();
+            if cos_theta < 1e-6 {
+                return;
+            }
+            flux += material.eval(&self.ray, record, &wi) * photon.power / cos_theta;
+            count += 1;
+        });
+        (flux, count)
+    }
+}
+
+/// Photon mapping: photons traced from the lights once are gathered where
+/// camera paths first reach a surface that isn't purely specular, within a
+/// fixed radius. Direct light is sampled, everything else, caustics
+/// included, comes from the photons. Smaller radii blur less but need more
+/// photons.
+#[derive(Copy, Clone, Debug)]
+pub struct PhotonMapping {
+    photons: usize,
+    radius: f64,
+    max_depth: usize,
+}
+
+impl PhotonMapping {
+    /// Traces `photons` paths from the lights and gathers within `radius`.
+    /// Paths from either end bounce at most `max_depth` times.
+    pub fn new(photons: usize, radius: f64, max_depth: usize) -> Self {
+        Self {
+            photons,
+            radius,
+            max_depth,
+        }
+    }
+
+    /// Traces the photons, once for every image rendered of the scene.
+    pub fn photon_map(&self, scene: &Scene) -> PhotonMap {
+        PhotonMap::trace(scene, self.photons, self.max_depth)
+    }
+
+    /// Radiance arriving along the camera ray `r`, using the photons `map`
+    /// traced in the same scene.
+    pub fn ray_color(&self, r: &Ray, scene: &Scene, map: &PhotonMap) -> Color {
+        let (radiance, point) = VisiblePoint::trace(r, scene, self.max_depth);
+        match point {
+            Some(point) => {
+                let (flux, _) = point.gather(map, self.radius);
+                radiance + point.beta * flux / (PI * self.radius * self.radius)
+            }
+            None => radiance,
+        }
+    }
+}
+
+/// Stochastic progressive photon mapping, after Hachisuka and Jensen 2009.
+/// Each iteration traces a camera path per pixel and a new set of photons,
+/// and shrinks the gather radius of every pixel that found photons, so the
+/// blur of photon mapping vanishes as iterations add up.
+#[derive(Copy, Clone, Debug)]
+pub struct Sppm {
+    photons_per_iteration: usize,
+    initial_radius: f64,
+    alpha: f64,
+    max_depth: usize,
+}
+
+/// Running estimate of a pixel.
+#[derive(Copy, Clone, Debug)]
+struct PixelStatistics {
+    radius: f64,
+    /// Photons the estimate stands for, after the radius reductions.
+    count: f64,
+    /// Flux gathered within the current radius.
+    flux: Color,
+    /// Light found by the camera paths directly, summed.
+    direct: Color,
+}
+
+impl Sppm {
+    /// Traces `photons_per_iteration` paths from the lights each iteration,
+    /// gathering within `initial_radius` at first. Paths from either end
+    /// bounce at most `max_depth` times.
+    pub fn new(photons_per_iteration: usize, initial_radius: f64, max_depth: usize) -> Self {
+        Self {
+            photons_per_iteration,
+            initial_radius,
+            alpha: 2.0 / 3.0,
+            max_depth,
+        }
+    }
+
+    /// Share of the new photons each iteration keeps in the estimate, which
+    /// sets how fast the radius shrinks, two thirds by default. Smaller
+    /// values shrink it faster, with more noise.
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Renders `iterations` passes of a `width` by `height` image. Pixel
+    /// `(i, j)` is at `j * width + i`, with rows counted from the bottom
+    /// like `Camera::get_ray` counts `t`.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        width: usize,
+        height: usize,
+        iterations: usize,
+    ) -> Vec<Color> {
+        let mut pixels = vec![
+            PixelStatistics {
+                radius: self.initial_radius,
+                count: 0.0,
+                flux: Color::default(),
+                direct: Color::default(),
+            };
+            width * height
+        ];
+        for _ in 0..iterations {
+            self.iterate(scene, camera, width, height, &mut pixels);
+        }
+
+        let n = iterations.max(1) as f64;
+        pixels
+            .iter()
+            .map(|pixel| pixel.direct / n + pixel.flux / (n * PI * pixel.radius * pixel.radius))
+            .collect()
+    }
+
+    /// One pass: a camera path per pixel, a new set of photons gathered at
+    /// their visible points, and the radii shrunk where photons were found.
+    fn iterate(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        width: usize,
+        height: usize,
+        pixels: &mut [PixelStatistics],
+    ) {
+        let points: Vec<Option<VisiblePoint>> = pixels
+            .par_iter_mut()
+            .enumerate()
+            .map(|(index, pixel)| {
+                let (i, j) = (index % width, index / width);
+                let s = (i as f64 + random_f64()) / (width - 1).max(1) as f64;
+                let t = (j as f64 + random_f64()) / (height - 1).max(1) as f64;
+                let (radiance, point) =
+                    VisiblePoint::trace(&camera.get_ray(s, t), scene, self.max_depth);
+                pixel.direct += radiance;
+                point
+            })
+            .collect();
+
+        let map = PhotonMap::trace(scene, self.photons_per_iteration, self.max_depth);
+        pixels
+            .par_iter_mut()
+            .zip(points.par_iter())
+            .for_each(|(pixel, point)| {
+                let point = match point {
+                    Some(point) => point,
+                    None => return,
+                };
+                let (flux, found) = point.gather(&map, pixel.radius);
+                if found == 0 {
+                    return;
+                }
+                // Keep a share alpha of the new photons and shrink the
+                // radius so the density of the kept ones holds.
+                let found = found as f64;
+                let count = pixel.count + self.alpha * found;
+                let radius = pixel.radius * (count / (pixel.count + found)).sqrt();
+                let shrink = (radius / pixel.radius).powi(2);
+                pixel.flux = (pixel.flux + point.beta * flux) * shrink;
+                pixel.count = count;
+                pixel.radius = radius;
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        aabb::Aabb,
+        light::{DirectionalLight, Lights},
+        material::{Dielectric, DiffuseLight, Lambertian, Material},
+        quad::Quad,
+        ray::Hittables,
+        scene::Background,
+        sdf::{SdfBox, SdfObject, SdfSphere, Translate},
+        sphere::Sphere,
+    };
+
+    #[test]
+    fn finds_the_photons_within_the_radius() {
+        let photons: Vec<Photon> = (0..2000)
+            .map(|_| Photon {
+                position: Vec3::random() * 2.0,
+                direction: Vec3::new(0.0, -1.0, 0.0),
+                power: Color::new(1.0, 1.0, 1.0),
+            })
+            .collect();
+        let map = PhotonMap::new(photons.clone());
+        assert_eq!(map.len(), photons.len());
+        for _ in 0..20 {
+            let point = Vec3::random() * 2.0;
+            let radius = 0.3 * random_f64();
+            let mut found = 0;
+            map.for_each_within(&point, radius, |_| found += 1);
+            let expected = photons
+                .iter()
+                .filter(|p| (p.position - point).length() <= radius)
+                .count();
+            assert_eq!(found, expected);
+        }
+    }
+
+    /// A gray box open at the front with a red wall, a ball and a light in
+    /// the ceiling, where most of the light has bounced. The camera doesn't
+    /// see the light.
+    fn scene() -> Scene {
+        let gray: Material = Arc::new(Lambertian::new(Color::new(0.7, 0.7, 0.7)));
+        let red: Material = Arc::new(Lambertian::new(Color::new(0.7, 0.1, 0.1)));
+        let light: Material = Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0)));
+        let light = || {
+            Quad::new(
+                Point::new(-0.3, 1.999, -0.3),
+                Vec3::new(0.6, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 0.6),
+                light.clone(),
+            )
+        };
+        let mut world = Hittables::new();
+        let walls = [
+            (
+                Point::new(-1.0, 0.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                &gray,
+            ),
+            (
+                Point::new(-1.0, 2.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                &gray,
+            ),
+            (
+                Point::new(-1.0, 0.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                &gray,
+            ),
+            (
+                Point::new(-1.0, 0.0, -1.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                &red,
+            ),
+            (
+                Point::new(1.0, 0.0, -1.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                Vec3::new(0.0, 0.0, 2.0),
+                &gray,
+            ),
+        ];
+        for (q, u, v, material) in walls {
+            world.add(Box::new(Quad::new(q, u, v, material.clone())));
+        }
+        world.add(Box::new(Sphere::new(Point::new(0.3, 0.4, -0.2), 0.4, gray)));
+        world.add(Box::new(light()));
+        let mut lights = Lights::new();
+        lights.add_area(Box::new(light()));
+        Scene::new(world, lights).with_background(Background::Solid(Color::default()))
+    }
+
+    fn camera() -> Camera {
+        Camera::new(
+            Point::new(0.0, 1.0, 3.0),
+            Point::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            30.0,
+            1.0,
+            0.0,
+            3.0,
+        )
+    }
+
+    #[test]
+    fn glass_ball_focuses_the_sun() {
+        // A ball of radius 0.5 over the floor, in a sun giving irradiance
+        // one. Its focus lies a quarter above the floor.
+        let mut world = Hittables::new();
+        world.add(Box::new(Quad::new(
+            Point::new(-2.0, 0.0, -2.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::new(4.0, 0.0, 0.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point::new(0.0, 1.0, 0.0),
+            0.5,
+            Arc::new(Dielectric::new(1.5)),
+        )));
+        let mut lights = Lights::new();
+        lights.add(Box::new(DirectionalLight::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        )));
+        let scene = Scene::new(world, lights);
+
+        let map = PhotonMap::trace(&scene, 200_000, 4);
+        let power_within = |radius: f64| {
+            let mut power = 0.0;
+            map.for_each_within(&Point::default(), radius, |photon| {
+                power += photon.power.x()
+            });
+            power
+        };
+        // The open floor is lit straight from the sun, which the photons
+        // leave out. Under the ball they bring what it lets through, the
+        // most of it near the focus.
+        let caustic = power_within(0.2) / (PI * 0.2 * 0.2);
+        assert!(caustic > 1.5, "{caustic}");
+        let transmitted = power_within(1.0) / (PI * 0.5 * 0.5);
+        assert!((0.75..0.95).contains(&transmitted), "{transmitted}");
+    }
+
+    #[test]
+    fn sun_lights_a_world_of_distance_fields() {
+        // A white ball over a white slab, both sphere traced. The only
+        // bounds of the world are theirs, so the sun's disk comes from them.
+        let white: Material = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+        let slab = Translate::new(
+            Box::new(SdfBox::new(Vec3::new(2.0, 0.5, 2.0))),
+            Vec3::new(0.0, -0.5, 0.0),
+        );
+        let ball = Translate::new(Box::new(SdfSphere::new(0.5)), Vec3::new(0.0, 1.0, 0.0));
+        let mut world = Hittables::new();
+        world.add(Box::new(SdfObject::new(
+            Box::new(slab),
+            Aabb::new(Point::new(-2.0, -1.0, -2.0), Point::new(2.0, 0.0, 2.0)),
+            1e-6,
+            256,
+            white.clone(),
+        )));
+        world.add(Box::new(SdfObject::new(
+            Box::new(ball),
+            Aabb::new(Point::new(-0.5, 0.5, -0.5), Point::new(0.5, 1.5, 0.5)),
+            1e-6,
+            256,
+            white,
+        )));
+        let mut lights = Lights::new();
+        lights.add(Box::new(DirectionalLight::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Color::new(1.0, 1.0, 1.0),
+        )));
+        let scene = Scene::new(world, lights);
+
+        // Light bounced off the slab lands under the ball.
+        let map = PhotonMap::trace(&scene, 20_000, 4);
+        assert!(map.len() > 100, "{}", map.len());
+        let mut underside = 0;
+        map.for_each_within(&Point::default(), 10.0, |photon| {
+            assert!(photon.power.x().is_finite() && photon.power.x() > 0.0);
+            if photon.position.y() > 0.5 && photon.position.y() < 1.0 {
+                underside += 1;
+            }
+        });
+        assert!(underside > 100, "{underside}");
+    }
+
+    #[test]
+    fn sppm_radii_shrink() {
+        let (scene, camera) = (scene(), camera());
+        let sppm = Sppm::new(2_000, 0.2, 4);
+        let mut pixels = vec![
+            PixelStatistics {
+                radius: 0.2,
+                count: 0.0,
+                flux: Color::default(),
+                direct: Color::default(),
+            };
+            16
+        ];
+        sppm.iterate(&scene, &camera, 4, 4, &mut pixels);
+        // The first photons found are kept at two thirds, over two thirds of
+        // the area.
+        assert!(pixels.iter().filter(|pixel| pixel.count > 0.0).count() > 8);
+        for pixel in pixels.iter().filter(|pixel| pixel.count > 0.0) {
+            assert!((pixel.radius * pixel.radius - 0.04 * 2.0 / 3.0).abs() < 1e-12);
+        }
+        for _ in 0..4 {
+            let previous: Vec<f64> = pixels.iter().map(|pixel| pixel.radius).collect();
+            sppm.iterate(&scene, &camera, 4, 4, &mut pixels);
+            for (pixel, radius) in pixels.iter().zip(previous) {
+                assert!(pixel.radius <= radius);
+            }
+        }
+        let mean = pixels.iter().map(|pixel| pixel.radius).sum::<f64>() / pixels.len() as f64;
+        assert!(mean < 0.15, "{mean}");
+    }
+
+    /// Mean over the image of the camera rays' estimates.
+    fn image_mean(size: usize, samples: usize, estimate: impl Fn(&Ray) -> Color) -> Color {
+        let camera = camera();
+        let mut sum = Color::default();
+        for j in 0..size {
+            for i in 0..size {
+                for _ in 0..samples {
+                    let s = (i as f64 + random_f64()) / (size - 1) as f64;
+                    let t = (j as f64 + random_f64()) / (size - 1) as f64;
+                    sum += estimate(&camera.get_ray(s, t));
+                }
+            }
+        }
+        sum / (size * size * samples) as f64
+    }
+
+    #[test]
+    fn agrees_with_the_path_tracer() {
+        let scene = scene();
+        let size = 4;
+        let reference = image_mean(size, 1000, |r| {
+            Integrator::default().ray_color(r, &scene, 12)
+        });
+
+        let photon_mapping = PhotonMapping::new(50_000, 0.05, 12);
+        let map = photon_mapping.photon_map(&scene);
+        let estimate = image_mean(size, 100, |r| photon_mapping.ray_color(r, &scene, &map));
+        assert!(
+            (estimate - reference).length() < 0.05 * reference.length(),
+            "{estimate:?} against {reference:?}"
+        );
+
+        let image = Sppm::new(10_000, 0.1, 12).render(&scene, &camera(), size, size, 32);
+        let estimate = image.iter().fold(Color::default(), |acc, c| acc + *c) / image.len() as f64;
+        assert!(
+            (estimate - reference).length() < 0.05 * reference.length(),
+            "{estimate:?} against {reference:?}"
+        );
+    }
+}
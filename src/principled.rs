@@ -0,0 +1,587 @@
+use std::sync::Arc;
+
+use crate::{
+    fresnel::fresnel_dielectric,
+    interior::Interior,
+    material::{
+        interface_iors, rough_dielectric_eval, shading_frame, transmitted, Bsdf, BsdfSample, Lobes,
+    },
+    microfacet::{Distribution, Microfacet},
+    ray::{HitRecord, Ray},
+    sampling::sample_cosine_hemisphere,
+    texture::{SolidColor, Texture},
+    units::{
+        color::Color,
+        vec3::{dot_product, reflect, refract, unit_vector, Vec3},
+    },
+    PI,
+};
+
+/// Disney's principled BSDF (Burley 2012, with the 2015 specular
+/// transmission extension). Every parameter but the index of refraction is
+/// a texture; the scalar ones are in `[0, 1]` and read the red channel. The
+/// index stays a number because it identifies the interior in the nesting
+/// stack of a path, which must match where the path enters and leaves.
+///
+/// Sampling picks one lobe with a probability following its estimated
+/// contribution, and weights the direction by the full BSDF over the
+/// combined density of all lobes.
+#[derive(Debug, Clone)]
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+    specular: Arc<dyn Texture>,
+    specular_tint: Arc<dyn Texture>,
+    sheen: Arc<dyn Texture>,
+    sheen_tint: Arc<dyn Texture>,
+    clearcoat: Arc<dyn Texture>,
+    clearcoat_gloss: Arc<dyn Texture>,
+    transmission: Arc<dyn Texture>,
+    ior: f64,
+}
+
+/// Parameters looked up at a hit point.
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: f64,
+    specular_tint: f64,
+    sheen: f64,
+    sheen_tint: f64,
+    clearcoat: f64,
+    clearcoat_gloss: f64,
+    transmission: f64,
+}
+
+fn scalar(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::scalar(value))
+}
+
+fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+fn lerp(a: Color, b: Color, t: f64) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+/// Color normalised by its luminance, used to tint lobes by the base hue.
+fn tint(base_color: Color) -> Color {
+    let l = luminance(base_color);
+    if l > 0.0 {
+        base_color / l
+    } else {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// Smith masking for the clearcoat lobe, which uses a fixed GGX width.
+fn smith_g_ggx(cos_theta: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let c2 = cos_theta * cos_theta;
+    1.0 / (cos_theta + (a2 + c2 - a2 * c2).sqrt())
+}
+
+impl Principled {
+    /// Dielectric with the given base color and Disney's default parameters.
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: scalar(0.0),
+            roughness: scalar(0.5),
+            specular: scalar(0.5),
+            specular_tint: scalar(0.0),
+            sheen: scalar(0.0),
+            sheen_tint: scalar(0.5),
+            clearcoat: scalar(0.0),
+            clearcoat_gloss: scalar(1.0),
+            transmission: scalar(0.0),
+            ior: 1.5,
+        }
+    }
+
+    pub fn with_metallic(self, metallic: Arc<dyn Texture>) -> Self {
+        Self { metallic, ..self }
+    }
+
+    pub fn with_roughness(self, roughness: Arc<dyn Texture>) -> Self {
+        Self { roughness, ..self }
+    }
+
+    pub fn with_specular(self, specular: Arc<dyn Texture>) -> Self {
+        Self { specular, ..self }
+    }
+
+    pub fn with_specular_tint(self, specular_tint: Arc<dyn Texture>) -> Self {
+        Self {
+            specular_tint,
+            ..self
+        }
+    }
+
+    pub fn with_sheen(self, sheen: Arc<dyn Texture>, sheen_tint: Arc<dyn Texture>) -> Self {
+        Self {
+            sheen,
+            sheen_tint,
+            ..self
+        }
+    }
+
+    pub fn with_clearcoat(
+        self,
+        clearcoat: Arc<dyn Texture>,
+        clearcoat_gloss: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            clearcoat,
+            clearcoat_gloss,
+            ..self
+        }
+    }
+
+    /// Specular transmission through a rough dielectric of index `ior`.
+    pub fn with_transmission(self, transmission: Arc<dyn Texture>, ior: f64) -> Self {
+        Self {
+            transmission,
+            ior,
+            ..self
+        }
+    }
+
+    pub fn interior(&self) -> Interior {
+        Interior::new(self.ior, Color::default())
+    }
+
+    fn parameters(&self, rec: &HitRecord) -> Parameters {
+        let (u, v, p) = (rec.u, rec.v, &rec.point);
+        let unit = |t: &Arc<dyn Texture>| t.scalar(u, v, p).clamp(0.0, 1.0);
+        Parameters {
+            base_color: self.base_color.value(u, v, p),
+            metallic: unit(&self.metallic),
+            roughness: unit(&self.roughness),
+            specular: self.specular.scalar(u, v, p).max(0.0),
+            specular_tint: unit(&self.specular_tint),
+            sheen: self.sheen.scalar(u, v, p).max(0.0),
+            sheen_tint: unit(&self.sheen_tint),
+            clearcoat: self.clearcoat.scalar(u, v, p).max(0.0),
+            clearcoat_gloss: unit(&self.clearcoat_gloss),
+            transmission: unit(&self.transmission),
+        }
+    }
+
+    /// Lobe weights and sampling probabilities at a hit.
+    fn lobes_at(&self, r_in: &Ray, rec: &HitRecord) -> LobeModel {
+        let params = self.parameters(rec);
+        let alpha = (params.roughness * params.roughness).max(1e-3);
+        let (n_i, n_t) = interface_iors(r_in, rec, &self.interior());
+
+        // Weights of the four lobes, following Disney's blending.
+        let dielectric = 1.0 - params.metallic;
+        let diffuse = dielectric * (1.0 - params.transmission);
+        let transmission = dielectric * params.transmission;
+        let specular = 1.0 - transmission;
+        let clearcoat = 0.25 * params.clearcoat;
+
+        let tint = tint(params.base_color);
+        let specular_color = lerp(
+            params.specular * 0.08 * lerp(Color::new(1.0, 1.0, 1.0), tint, params.specular_tint),
+            params.base_color,
+            params.metallic,
+        );
+
+        // Light arriving from inside can only belong to the transmission lobe.
+        let weights = if rec.front_face {
+            [diffuse, specular, transmission, clearcoat]
+        } else {
+            [0.0, 0.0, transmission, 0.0]
+        };
+        let mut probabilities = [
+            weights[0] * luminance(params.base_color).max(0.01),
+            weights[1] * luminance(specular_color).max(0.04),
+            weights[2],
+            weights[3] * 0.04,
+        ];
+        let total: f64 = probabilities.iter().sum();
+        if total > 0.0 {
+            probabilities.iter_mut().for_each(|p| *p /= total);
+        }
+
+        LobeModel {
+            microfacet: Microfacet::new(Distribution::Ggx, alpha),
+            eta: n_t / n_i,
+            weights,
+            probabilities,
+            specular_color,
+            sheen_color: params.sheen * lerp(Color::new(1.0, 1.0, 1.0), tint, params.sheen_tint),
+            clearcoat_alpha: 0.1 * (1.0 - params.clearcoat_gloss) + 0.001 * params.clearcoat_gloss,
+            params,
+        }
+    }
+}
+
+struct LobeModel {
+    params: Parameters,
+    microfacet: Microfacet,
+    eta: f64,
+    weights: [f64; 4],
+    probabilities: [f64; 4],
+    specular_color: Color,
+    sheen_color: Color,
+    clearcoat_alpha: f64,
+}
+
+/// GTR1 distribution of the clearcoat lobe.
+fn gtr1(cos_theta: f64, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta * cos_theta))
+}
+
+impl LobeModel {
+    /// Value times `|cos θi|` and sampling density of each lobe, in the
+    /// local frame.
+    fn lobes(&self, wo: &Vec3, wi: &Vec3) -> [(Color, f64); 4] {
+        let black = (Color::default(), 0.0);
+        let mut lobes = [black; 4];
+        let white = Color::new(1.0, 1.0, 1.0);
+        let reflecting = wi.z() > 0.0 && wo.z() > 0.0;
+
+        if reflecting && self.weights[0] > 0.0 {
+            // Diffuse with retro-reflection, plus sheen at grazing angles.
+            let h = unit_vector(*wi + *wo);
+            let cos_d = dot_product(wi, &h);
+            let fd90 = 0.5 + 2.0 * self.params.roughness * cos_d * cos_d;
+            let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+                * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+            // Retro-reflection adds energy as the surface roughens, which
+            // Frostbite's normalisation takes back out (Lagarde 2014).
+            let energy = 1.0 - self.params.roughness * (1.0 - 1.0 / 1.51);
+            let sheen = schlick_weight(cos_d) * self.sheen_color;
+            let value = self.params.base_color * (energy * retro / PI) + sheen;
+            lobes[0] = (self.weights[0] * wi.z() * value, wi.z() / PI);
+        }
+        if reflecting && self.weights[1] > 0.0 {
+            let m = unit_vector(*wo + *wi);
+            let fresnel = lerp(
+                self.specular_color,
+                white,
+                schlick_weight(dot_product(wi, &m)),
+            );
+            let value = self.microfacet.d(&m) * self.microfacet.g(wo, wi) / (4.0 * wo.z());
+            let pdf = self.microfacet.pdf_visible_normal(wo, &m) / (4.0 * dot_product(wo, &m));
+            lobes[1] = (self.weights[1] * value * fresnel, pdf);
+        }
+        if self.weights[2] > 0.0 {
+            // Rough dielectric, tinted by the base color when refracting.
+            let (value, pdf) = rough_dielectric_eval(&self.microfacet, self.eta, wo, wi);
+            let base = self.params.base_color;
+            let color = if wi.z() < 0.0 {
+                Color::new(base.x().sqrt(), base.y().sqrt(), base.z().sqrt())
+            } else {
+                white
+            };
+            lobes[2] = (self.weights[2] * value * color, pdf);
+        }
+        if reflecting && self.weights[3] > 0.0 {
+            let h = unit_vector(*wo + *wi);
+            let wo_dot_h = dot_product(wo, &h);
+            let d = gtr1(h.z(), self.clearcoat_alpha);
+            let fresnel = 0.04 + 0.96 * schlick_weight(wo_dot_h);
+            // This form of G already folds in 1 / (4 cos_i cos_o).
+            let g = smith_g_ggx(wi.z(), 0.25) * smith_g_ggx(wo.z(), 0.25);
+            let value = self.weights[3] * d * fresnel * g * wi.z();
+            lobes[3] = (white * value, d * h.z() / (4.0 * wo_dot_h));
+        }
+        lobes
+    }
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        self.lobes(wo, wi)
+            .iter()
+            .zip(self.probabilities)
+            .fold((Color::default(), 0.0), |(value, pdf), (lobe, p)| {
+                (value + lobe.0, pdf + p * lobe.1)
+            })
+    }
+}
+
+impl Bsdf for Principled {
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> Color {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = frame.to_local(&unit_vector(*wi));
+        self.lobes_at(r_in, rec).eval(&wo, &wi).0
+    }
+
+    fn sample(&self, r_in: &Ray, rec: &HitRecord, uc: f64, u: (f64, f64)) -> Option<BsdfSample> {
+        let model = self.lobes_at(r_in, rec);
+        let (frame, wo) = shading_frame(r_in, rec);
+
+        // Pick a lobe and rescale uc so it can be used again inside the lobe.
+        let mut lobe = 0;
+        let mut uc = uc;
+        while lobe < 3 && uc >= model.probabilities[lobe] {
+            uc -= model.probabilities[lobe];
+            lobe += 1;
+        }
+        if model.probabilities[lobe] <= 0.0 {
+            return None;
+        }
+        let uc = (uc / model.probabilities[lobe]).min(1.0);
+
+        let wi = match lobe {
+            0 => sample_cosine_hemisphere(u),
+            1 => reflect(&-wo, &model.microfacet.sample_visible_normal(&wo, u.0, u.1)),
+            2 => {
+                let m = model.microfacet.sample_visible_normal(&wo, u.0, u.1);
+                let wo_dot_m = dot_product(&wo, &m);
+                if wo_dot_m <= 0.0 {
+                    return None;
+                }
+                if uc < fresnel_dielectric(wo_dot_m, model.eta) {
+                    let wi = reflect(&-wo, &m);
+                    if wi.z() <= 0.0 {
+                        return None;
+                    }
+                    wi
+                } else {
+                    let wi = refract(&-wo, &m, 1.0 / model.eta);
+                    if wi.z() >= 0.0 {
+                        return None;
+                    }
+                    wi
+                }
+            }
+            _ => {
+                let a2 = model.clearcoat_alpha.powi(2);
+                let cos_h = ((1.0 - a2.powf(1.0 - u.0)) / (1.0 - a2)).max(0.0).sqrt();
+                let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+                let phi = 2.0 * PI * u.1;
+                reflect(
+                    &-wo,
+                    &Vec3::new(sin_h * phi.cos(), sin_h * phi.sin(), cos_h),
+                )
+            }
+        };
+
+        // Reflections below the surface have no density in their lobe, the
+        // transmission lobe would claim them in `eval`.
+        if lobe != 2 && wi.z() <= 0.0 {
+            return None;
+        }
+        let (value, pdf) = model.eval(&wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let direction = frame.local_vec(&wi);
+        let (scattered, side) = if wi.z() < 0.0 {
+            let scattered = transmitted(r_in, rec, self.interior(), direction);
+            (scattered, Lobes::TRANSMISSION)
+        } else {
+            (r_in.spawn(rec.point, direction), Lobes::REFLECTION)
+        };
+        let kind = if lobe == 0 {
+            Lobes::DIFFUSE
+        } else {
+            Lobes::GLOSSY
+        };
+        Some(BsdfSample {
+            scattered,
+            weight: value / pdf,
+            pdf,
+            lobe: kind | side,
+        })
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, wi: &Vec3) -> f64 {
+        let (frame, wo) = shading_frame(r_in, rec);
+        let wi = frame.to_local(&unit_vector(*wi));
+        self.lobes_at(r_in, rec).eval(&wo, &wi).1
+    }
+
+    fn lobes(&self) -> Lobes {
+        Lobes::DIFFUSE | Lobes::GLOSSY | Lobes::REFLECTION | Lobes::TRANSMISSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        sampling::sample_uniform_sphere,
+        units::{point::Point, vec3::random_f64},
+    };
+
+    fn hit_on_plane(front_face: bool) -> (Ray, HitRecord) {
+        let ray = Ray::new(Point::new(-1.0, 1.0, 0.3), Vec3::new(1.0, -1.0, -0.3));
+        let mut record = HitRecord::new(Point::default(), Vec3::new(0.0, 1.0, 0.0), 1.0);
+        record.front_face = front_face;
+        (ray, record)
+    }
+
+    /// Parameter sets exercising each lobe, alone and blended.
+    fn materials() -> Vec<(&'static str, Principled)> {
+        let white = || scalar(1.0);
+        let base = || -> Arc<dyn Texture> { Arc::new(SolidColor::new(Color::new(0.8, 0.5, 0.2))) };
+        vec![
+            ("default", Principled::new(white())),
+            (
+                "rough diffuse",
+                Principled::new(base()).with_roughness(scalar(1.0)),
+            ),
+            (
+                "metal",
+                Principled::new(base())
+                    .with_metallic(scalar(1.0))
+                    .with_roughness(scalar(0.3)),
+            ),
+            (
+                "half metal",
+                Principled::new(white())
+                    .with_metallic(scalar(0.5))
+                    .with_roughness(scalar(0.6)),
+            ),
+            (
+                "glass",
+                Principled::new(white())
+                    .with_roughness(scalar(0.4))
+                    .with_transmission(scalar(1.0), 1.5),
+            ),
+            (
+                "half glass",
+                Principled::new(base())
+                    .with_roughness(scalar(0.7))
+                    .with_transmission(scalar(0.5), 1.33),
+            ),
+            (
+                "clearcoat",
+                Principled::new(base())
+                    .with_roughness(scalar(0.8))
+                    .with_clearcoat(scalar(1.0), scalar(0.0)),
+            ),
+            (
+                "white rough diffuse",
+                Principled::new(white())
+                    .with_roughness(scalar(1.0))
+                    .with_sheen(scalar(1.0), scalar(0.0)),
+            ),
+            (
+                "sheen",
+                Principled::new(base())
+                    .with_roughness(scalar(0.5))
+                    .with_sheen(scalar(1.0), scalar(0.5)),
+            ),
+            (
+                "everything",
+                Principled::new(white())
+                    .with_metallic(scalar(0.3))
+                    .with_roughness(scalar(0.5))
+                    .with_sheen(scalar(1.0), scalar(0.0))
+                    .with_clearcoat(scalar(1.0), scalar(0.5))
+                    .with_transmission(scalar(0.5), 1.5),
+            ),
+        ]
+    }
+
+    /// Hits seen from outside, and from inside for transmissive materials.
+    fn hits(material: &Principled) -> Vec<(Ray, HitRecord)> {
+        let (ray, record) = hit_on_plane(false);
+        let mut hits = vec![hit_on_plane(true)];
+        if material.transmission.scalar(0.0, 0.0, &record.point) > 0.0 {
+            hits.push((ray, record));
+        }
+        hits
+    }
+
+    #[test]
+    fn sample_weight_matches_eval() {
+        for (name, material) in materials() {
+            for (ray, record) in hits(&material) {
+                for _ in 0..1000 {
+                    let u = (random_f64(), random_f64());
+                    let Some(sample) = material.sample(&ray, &record, random_f64(), u) else {
+                        continue;
+                    };
+                    let wi = sample.scattered.direction();
+                    let pdf = material.pdf(&ray, &record, &wi);
+                    let expected = material.eval(&ray, &record, &wi) / pdf;
+                    assert!((sample.pdf - pdf).abs() < 1e-6 * pdf, "{name}");
+                    assert!(
+                        (sample.weight - expected).length() < 1e-6 * expected.length(),
+                        "{name}: {} vs {expected}",
+                        sample.weight
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_matches_sampled_directions() {
+        // Compare a histogram of sampled directions, binned by the cosine to
+        // the normal and the azimuth, with the integral of pdf over each bin.
+        const BINS: usize = 8;
+        const STEPS: usize = 32;
+        let bin = |d: Vec3| {
+            let d = unit_vector(d);
+            let cos = ((d.y() + 1.0) / 2.0 * BINS as f64) as usize;
+            let phi = ((d.z().atan2(d.x()) + PI) / (2.0 * PI) * BINS as f64) as usize;
+            cos.min(BINS - 1) * BINS + phi.min(BINS - 1)
+        };
+        let n = 100_000;
+        for (name, material) in materials() {
+            for (ray, record) in hits(&material) {
+                let mut expected = [0.0; BINS * BINS];
+                let step = 1.0 / (BINS * STEPS) as f64;
+                for i in 0..BINS * STEPS {
+                    for j in 0..BINS * STEPS {
+                        let u = ((i as f64 + 0.5) * step, (j as f64 + 0.5) * step);
+                        let wi = sample_uniform_sphere(u);
+                        // Each cell covers an equal solid angle of 4π step².
+                        expected[bin(wi)] +=
+                            material.pdf(&ray, &record, &wi) * 4.0 * PI * step * step;
+                    }
+                }
+
+                let mut observed = [0.0; BINS * BINS];
+                for _ in 0..n {
+                    let u = (random_f64(), random_f64());
+                    if let Some(sample) = material.sample(&ray, &record, random_f64(), u) {
+                        observed[bin(sample.scattered.direction())] += 1.0;
+                    }
+                }
+                for (observed, expected) in observed.iter().zip(expected) {
+                    let mean = expected * n as f64;
+                    assert!(
+                        (observed - mean).abs() < 5.0 * mean.sqrt() + 0.002 * n as f64,
+                        "{name}: {observed} samples where {mean} are expected"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn white_furnace_albedo_stays_below_one() {
+        let n = 100_000;
+        for (name, material) in materials() {
+            for (ray, record) in hits(&material) {
+                let mut total = Color::default();
+                for _ in 0..n {
+                    let u = (random_f64(), random_f64());
+                    if let Some(sample) = material.sample(&ray, &record, random_f64(), u) {
+                        total += sample.weight;
+                    }
+                }
+                let albedo = total / n as f64;
+                for c in 0..3 {
+                    assert!(albedo[c] <= 1.01, "{name}: {albedo}");
+                }
+            }
+        }
+    }
+}
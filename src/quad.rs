@@ -0,0 +1,232 @@
+use std::sync::OnceLock;
+
+use crate::{
+    aabb::Aabb,
+    light_sampler::LightBounds,
+    material::Material,
+    ray::{HitRecord, Hittable, Ray},
+    sampling::Distribution2D,
+    units::{
+        color::luminance,
+        point::Point,
+        vec3::{cross_product, dot_product, unit_vector, Vec3},
+    },
+    INFINITY, PI,
+};
+
+/// Parallelogram with a corner at `q` and sides `u` and `v`. The front face
+/// is the side `u × v` points to.
+pub struct Quad {
+    q: Point,
+    u: Vec3,
+    v: Vec3,
+    /// `n / (n · n)` for the unnormalized normal `n`, to find the planar
+    /// coordinates of a hit.
+    w: Vec3,
+    normal: Vec3,
+    area: f64,
+    material: Material,
+    /// Where on the surface the material emits, built when the quad is first
+    /// sampled as a light.
+    emission: OnceLock<Distribution2D>,
+}
+
+/// Resolution of the table of emission light sampling follows.
+const EMISSION_RESOLUTION: usize = 64;
+
+impl Quad {
+    pub fn new(q: Point, u: Vec3, v: Vec3, material: Material) -> Self {
+        let n = cross_product(&u, &v);
+        Self {
+            q,
+            u,
+            v,
+            w: n / dot_product(&n, &n),
+            normal: unit_vector(n),
+            area: n.length(),
+            material,
+            emission: OnceLock::new(),
+        }
+    }
+
+    /// Distribution over the planar coordinates following the emitted
+    /// luminance, uniform for materials emitting the same everywhere.
+    fn emission(&self) -> &Distribution2D {
+        self.emission.get_or_init(|| {
+            let n = EMISSION_RESOLUTION;
+            let mut func = Vec::with_capacity(n * n);
+            for j in 0..n {
+                for i in 0..n {
+                    let (alpha, beta) = ((i as f64 + 0.5) / n as f64, (j as f64 + 0.5) / n as f64);
+                    let point = self.q + alpha * self.u + beta * self.v;
+                    let toward = Ray::new(point + self.normal, -self.normal);
+                    let mut record = HitRecord::new(point, self.normal, 1.0);
+                    record.set_face_normal(&toward, &self.normal);
+                    (record.u, record.v) = (alpha, beta);
+                    func.push(luminance(self.material.emitted(&toward, &record)));
+                }
+            }
+            // Keep every part of the surface reachable, in case the table
+            // misses emission between its samples.
+            let floor = 1e-2 * func.iter().sum::<f64>() / func.len() as f64;
+            func.iter_mut().for_each(|f| *f += floor);
+            Distribution2D::new(&func, n, n)
+        })
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let denom = dot_product(&self.normal, &ray.direction());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = dot_product(&self.normal, &(self.q - ray.origin())) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let planar = point - self.q;
+        let alpha = dot_product(&self.w, &cross_product(&planar, &self.v));
+        let beta = dot_product(&self.w, &cross_product(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut record = HitRecord::new(point, self.normal, t);
+        record.set_face_normal(ray, &self.normal);
+        (record.u, record.v) = (alpha, beta);
+        record.material = self.material.clone();
+        Some(record)
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        // Points are sampled by area following the emission, converted to
+        // solid angle.
+        let record = match self.hit(&Ray::new(*origin, *direction), 0.001, INFINITY) {
+            Some(record) => record,
+            None => return 0.0,
+        };
+        let distance_squared = record.t * record.t * direction.length_squared();
+        let cosine = dot_product(direction, &self.normal).abs() / direction.length();
+        let pdf_area = self.emission().pdf(record.u, record.v) / self.area;
+        pdf_area * distance_squared / cosine
+    }
+
+    fn random(&self, origin: &Point, u: (f64, f64)) -> Vec3 {
+        // Points are drawn where the surface emits more.
+        let ((alpha, beta), _) = self.emission().sample_continuous(u);
+        self.q + alpha * self.u + beta * self.v - *origin
+    }
+
+    fn sample_surface(&self, u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        let ((alpha, beta), pdf) = self.emission().sample_continuous(u);
+        let point = self.q + alpha * self.u + beta * self.v;
+        let mut record = HitRecord::new(point, self.normal, 0.0);
+        record.front_face = true;
+        (record.u, record.v) = (alpha, beta);
+        record.material = self.material.clone();
+        Some((record, pdf / self.area))
+    }
+
+    fn surface_pdf(&self, point: &Point) -> f64 {
+        let planar = *point - self.q;
+        let scale = self.u.length().max(self.v.length()).max(1.0);
+        if dot_product(&planar, &self.normal).abs() > 1e-6 * scale {
+            return 0.0;
+        }
+        let alpha = dot_product(&self.w, &cross_product(&planar, &self.v));
+        let beta = dot_product(&self.w, &cross_product(&self.u, &planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return 0.0;
+        }
+        self.emission().pdf(alpha, beta) / self.area
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let bounds = corners.iter().fold(Aabb::new(self.q, self.q), |acc, p| {
+            acc.union(&Aabb::new(*p, *p))
+        });
+        Some(bounds.padded(1e-4))
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        // Diffuse emission from the front face, or both.
+        let two_sided = self.material.two_sided_emission();
+        let sides = if two_sided { 2.0 } else { 1.0 };
+        Some(LightBounds::new(
+            self.bounding_box()?,
+            sides * PI * self.area * luminance(self.material.average_emission()),
+            self.normal,
+            1.0,
+            0.0,
+            two_sided,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::{
+        material::{DiffuseLight, Lambertian},
+        texture::ImageTexture,
+        units::color::Color,
+    };
+
+    #[test]
+    fn light_density_matches_solid_angle() {
+        // A small square far away covers about area / distance² steradians.
+        let quad = Quad::new(
+            Point::new(-0.05, -0.05, 10.0),
+            Vec3::new(0.1, 0.0, 0.0),
+            Vec3::new(0.0, 0.1, 0.0),
+            Arc::new(Lambertian::default()),
+        );
+        let origin = Point::default();
+        let direction = quad.random(&origin, (0.3, 0.6));
+        let pdf = quad.pdf_value(&origin, &direction);
+        assert!((pdf - 100.0 / 0.01).abs() / pdf < 1e-3);
+        assert_eq!(quad.pdf_value(&origin, &Vec3::new(1.0, 0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn textured_emission_is_sampled_where_it_is_bright() {
+        // Lower half bright, upper half dark, facing the origin.
+        let image = ImageTexture::new(2, 1, vec![Color::new(10.0, 10.0, 10.0), Color::default()]);
+        let light = DiffuseLight::textured(Arc::new(image)).with_intensity(2.0);
+        let quad = Quad::new(
+            Point::new(-1.0, -1.0, 4.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Arc::new(light),
+        );
+        let origin = Point::default();
+        let n = 64;
+        let (mut bright, mut power) = (0, 0.0);
+        for i in 0..n * n {
+            let u = (
+                ((i % n) as f64 + 0.5) / n as f64,
+                ((i / n) as f64 + 0.5) / n as f64,
+            );
+            let direction = quad.random(&origin, u);
+            if direction.y() < 0.0 {
+                bright += 1;
+            }
+            let r = Ray::new(origin, direction);
+            let record = quad.hit(&r, 0.001, INFINITY).unwrap();
+            let radiance = record.material.emitted(&r, &record).x();
+            power += radiance / quad.pdf_value(&origin, &direction);
+        }
+        assert!(bright > 95 * n * n / 100, "{}", bright);
+        // Still an unbiased estimate of the light arriving: the bright half
+        // covers half the solid angle of a square seen face on.
+        let power = power / (n * n) as f64;
+        let exact = 20.0 * 2.0 * (1.0f64 / 17.0).asin();
+        assert!((power - exact).abs() < 0.01 * exact, "{} {}", power, exact);
+    }
+}
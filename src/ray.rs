@@ -1,20 +1,70 @@
+use std::sync::{Arc, OnceLock};
+
 use crate::{
+    aabb::Aabb,
+    interior::InteriorStack,
+    light_sampler::LightBounds,
     material::{Lambertian, Material},
+    spectrum::Wavelengths,
     units::{
         point::Point,
         vec3::{dot_product, Vec3},
     },
 };
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct Ray {
     origin: Point,
     direction: Vec3,
+    interior: InteriorStack,
+    wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
     pub fn new(origin: Point, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            interior: InteriorStack::default(),
+            wavelengths: None,
+        }
+    }
+
+    /// New ray continuing the path of this one, in the same media.
+    pub fn spawn(&self, origin: Point, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction,
+            interior: self.interior,
+            wavelengths: self.wavelengths,
+        }
+    }
+
+    pub fn with_interior(self, interior: InteriorStack) -> Self {
+        Self { interior, ..self }
+    }
+
+    pub fn interior(&self) -> InteriorStack {
+        self.interior
+    }
+
+    pub fn with_wavelengths(self, wavelengths: Wavelengths) -> Self {
+        Self {
+            wavelengths: Some(wavelengths),
+            ..self
+        }
+    }
+
+    /// The wavelengths carried by the path, if it went through a dispersive
+    /// interface. Paths are RGB until then.
+    pub fn wavelengths(&self) -> Option<&Wavelengths> {
+        self.wavelengths.as_ref()
+    }
+
+    /// The hero wavelength in nanometers of a spectral path, the one its
+    /// directions are chosen for.
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelengths.map(|w| w.hero())
     }
 
     pub fn origin(&self) -> Point {
@@ -30,15 +80,71 @@ impl Ray {
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+
+    /// Every span of the ray's line that lies inside the object, sorted by `t`
+    /// and including spans behind the origin. The records carry outward
+    /// normals. Only closed solids can answer this, the default reports no
+    /// spans so the object can't be used in constructive solid geometry.
+    fn intervals(&self, _ray: &Ray) -> Vec<Interval> {
+        vec![]
+    }
+
+    /// Solid angle density, seen from `origin`, with which `random` picks
+    /// `direction`. Objects that can't be sampled as lights report zero.
+    fn pdf_value(&self, _origin: &Point, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    /// Direction from `origin` towards a point sampled on the object with
+    /// two uniform numbers, for sampling it as a light.
+    fn random(&self, _origin: &Point, _u: (f64, f64)) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    /// Box holding the whole object, `None` for objects without bounds.
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// Where the object is and how much it emits, for picking it among many
+    /// lights. `None` for objects that can't be sampled as lights.
+    fn light_bounds(&self) -> Option<LightBounds> {
+        None
+    }
+
+    /// Point on the surface picked with two uniform numbers, as a record
+    /// facing outwards, with the density per unit area of picking it. Used
+    /// to start light paths on emissive objects.
+    fn sample_surface(&self, _u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        None
+    }
+
+    /// Density per unit area with which `sample_surface` picks `point`,
+    /// zero for points off the surface.
+    fn surface_pdf(&self, _point: &Point) -> f64 {
+        0.0
+    }
+}
+
+/// Material of records whose primitive doesn't set one, shared so creating
+/// a record doesn't allocate.
+fn default_material() -> Material {
+    static DEFAULT: OnceLock<Material> = OnceLock::new();
+    DEFAULT
+        .get_or_init(|| Arc::new(Lambertian::default()))
+        .clone()
 }
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub point: Point,
     pub normal: Vec3,
     pub material: Material,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
@@ -48,7 +154,9 @@ impl HitRecord {
             point,
             normal,
             t,
-            material: Material::Lambertian(Lambertian::default()),
+            u: 0.0,
+            v: 0.0,
+            material: default_material(),
             front_face: false,
         }
     }
@@ -60,6 +168,43 @@ impl HitRecord {
             -*outward_normal
         };
     }
+
+    /// The same hit as seen by `ray` arriving at it, with the normal turned
+    /// to face it.
+    pub fn facing(&self, ray: &Ray) -> HitRecord {
+        let outward_normal = if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        };
+        let mut record = self.clone();
+        record.set_face_normal(ray, &outward_normal);
+        record
+    }
+}
+
+/// Part of a ray inside a solid, from the record where it enters to the
+/// record where it leaves.
+#[derive(Clone)]
+pub struct Interval {
+    pub enter: HitRecord,
+    pub exit: HitRecord,
+}
+
+impl Interval {
+    /// Picks the first boundary of the interval that lies in `[t_min, t_max]`
+    /// and orients its normal against the ray.
+    pub fn first_hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        [&self.enter, &self.exit]
+            .into_iter()
+            .find(|record| t_min <= record.t && record.t <= t_max)
+            .map(|record| {
+                let mut record = record.clone();
+                let outward_normal = record.normal;
+                record.set_face_normal(ray, &outward_normal);
+                record
+            })
+    }
 }
 
 #[derive(Default)]
@@ -89,4 +234,20 @@ impl Hittables {
 
         hit_anything
     }
+
+    /// Box holding every object that has bounds, `None` when none does.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.objects
+            .iter()
+            .filter_map(|object| object.bounding_box())
+            .reduce(|acc, bounds| acc.union(&bounds))
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
 }
@@ -0,0 +1,194 @@
+use crate::{units::vec3::Vec3, PI};
+
+/// Cosine weighted direction around +z, from two uniform numbers.
+pub fn sample_cosine_hemisphere(u: (f64, f64)) -> Vec3 {
+    let phi = 2.0 * PI * u.0;
+    let r = u.1.sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u.1).max(0.0).sqrt())
+}
+
+/// Uniformly distributed direction over the unit sphere.
+pub fn sample_uniform_sphere(u: (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u.0;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniformly distributed point in the unit ball, from three uniform numbers.
+pub fn sample_uniform_ball(uc: f64, u: (f64, f64)) -> Vec3 {
+    uc.cbrt() * sample_uniform_sphere(u)
+}
+
+/// Uniformly distributed point on the unit disk in the xy plane.
+pub fn sample_uniform_disk(u: (f64, f64)) -> Vec3 {
+    let r = u.0.sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), 0.0)
+}
+
+/// Uniformly distributed direction within the cone around +z whose half
+/// angle has cosine `cos_theta_max`.
+pub fn sample_uniform_cone(u: (f64, f64), cos_theta_max: f64) -> Vec3 {
+    let z = 1.0 + u.0 * (cos_theta_max - 1.0);
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.1;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Multiple importance sampling weight of a sample drawn with density
+/// `f_pdf` when `g_pdf` could have drawn it too, each strategy taking one
+/// sample.
+pub fn balance_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    if f_pdf + g_pdf <= 0.0 {
+        return 0.0;
+    }
+    f_pdf / (f_pdf + g_pdf)
+}
+
+/// Like `balance_heuristic` with the densities squared, which lowers the
+/// variance further when one strategy is much better than the other.
+pub fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let (f, g) = (f_pdf * f_pdf, g_pdf * g_pdf);
+    if f.is_infinite() {
+        return 1.0;
+    }
+    if f + g <= 0.0 {
+        return 0.0;
+    }
+    f / (f + g)
+}
+
+/// Piecewise constant density over `[0, 1)` proportional to a tabulated
+/// function, sampled by inverting its cumulative distribution.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// `func` holds the non negative function value of each of equally wide
+    /// steps. A function that is zero everywhere is sampled uniformly.
+    pub fn new(func: &[f64]) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f64 / n as f64
+            };
+        }
+        Self {
+            func: func.iter().map(|f| f.abs()).collect(),
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Integral of the function over `[0, 1)`.
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps `u` to a point in `[0, 1)`, returning it with its density and
+    /// the index of the step it falls in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // Last entry of the cdf not above u.
+        let offset = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.count() - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let x = ((offset as f64 + du) / self.count() as f64).min(1.0 - f64::EPSILON);
+        (x, self.pdf(x), offset)
+    }
+
+    /// Picks one of the steps with probability proportional to its value,
+    /// returning it with that probability.
+    pub fn sample_discrete(&self, u: f64) -> (usize, f64) {
+        let (_, _, offset) = self.sample_continuous(u);
+        (offset, self.discrete_pdf(offset))
+    }
+
+    /// Probability of `sample_discrete` picking step `index`.
+    pub fn discrete_pdf(&self, index: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[index] / (self.integral * self.count() as f64)
+        } else {
+            1.0 / self.count() as f64
+        }
+    }
+
+    /// Density of sampling `x`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        let n = self.count();
+        let offset = ((x * n as f64) as usize).min(n - 1);
+        if self.integral > 0.0 {
+            self.func[offset] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Piecewise constant density over the unit square, proportional to a
+/// function tabulated over a grid: a marginal density picks the row and
+/// the row's own density the column.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `nv` rows of `nu` values.
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Self {
+        let conditional: Vec<Distribution1D> = func
+            .chunks_exact(nu)
+            .take(nv)
+            .map(Distribution1D::new)
+            .collect();
+        let marginal: Vec<f64> = conditional.iter().map(|c| c.integral()).collect();
+        Self {
+            conditional,
+            marginal: Distribution1D::new(&marginal),
+        }
+    }
+
+    /// Maps two uniform numbers to a point `(u, v)` of the square, returned
+    /// with its density.
+    pub fn sample_continuous(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u.1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u.0);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    /// Integral of the function over the square.
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    /// Density of sampling `(u, v)`.
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let rows = self.conditional.len();
+        let row = ((v * rows as f64) as usize).min(rows - 1);
+        self.conditional[row].pdf(u) * self.marginal.pdf(v)
+    }
+}
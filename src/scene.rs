@@ -0,0 +1,64 @@
+use crate::{
+    environment::EnvironmentMap,
+    light::Lights,
+    ray::{Hittables, Ray},
+    units::{color::Color, vec3::unit_vector},
+};
+
+/// What rays escaping the scene see, on top of the lights at infinity.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum Background {
+    /// White at the horizon fading to blue overhead.
+    #[default]
+    Gradient,
+    Solid(Color),
+}
+
+impl Background {
+    pub fn radiance(&self, r: &Ray) -> Color {
+        match self {
+            Background::Gradient => {
+                let unit_direction = unit_vector(r.direction());
+                let t = 0.5 * (unit_direction.y() + 1.0);
+                Color::new(1.0, 1.0, 1.0) * (1.0 - t) + Color::new(0.5, 0.7, 1.0) * t
+            }
+            Background::Solid(color) => *color,
+        }
+    }
+}
+
+/// Everything rendered: the objects, the lights sampled directly and what
+/// lies beyond.
+pub struct Scene {
+    pub world: Hittables,
+    pub lights: Lights,
+    pub background: Background,
+    /// Seen by camera rays escaping the scene in place of the background and
+    /// the lights, without lighting anything.
+    pub backplate: Option<EnvironmentMap>,
+}
+
+impl Scene {
+    /// `lights` must hold every emissive object of the `world`, see
+    /// `Lights::add_area`. They learn the bounds of the world here, see
+    /// `Lights::preprocess`.
+    pub fn new(world: Hittables, mut lights: Lights) -> Self {
+        if let Some(bounds) = world.bounding_box() {
+            lights.preprocess(&bounds);
+        }
+        Self {
+            world,
+            lights,
+            background: Background::default(),
+            backplate: None,
+        }
+    }
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = background;
+        self
+    }
+    pub fn with_backplate(mut self, backplate: EnvironmentMap) -> Self {
+        self.backplate = Some(backplate);
+        self
+    }
+}
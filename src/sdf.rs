@@ -0,0 +1,500 @@
+use crate::{
+    aabb::Aabb,
+    material::Material,
+    ray::{HitRecord, Hittable, Ray},
+    units::{
+        point::Point,
+        vec3::{dot_product, unit_vector, Vec3},
+    },
+};
+
+/// Signed distance field: negative inside the shape, positive outside.
+/// The value must never overestimate the distance to the surface, otherwise
+/// sphere tracing can step through it.
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: Point) -> f64;
+}
+
+/// Renders an `Sdf` by sphere tracing. Marching stops when the field drops
+/// below `epsilon` or after `max_steps` evaluations. Fields don't know their
+/// extent, so `bounds` must enclose the surface: rays only march inside it.
+pub struct SdfObject {
+    sdf: Box<dyn Sdf>,
+    bounds: Aabb,
+    epsilon: f64,
+    max_steps: usize,
+    material: Material,
+}
+
+impl SdfObject {
+    pub fn new(
+        sdf: Box<dyn Sdf>,
+        bounds: Aabb,
+        epsilon: f64,
+        max_steps: usize,
+        material: Material,
+    ) -> Self {
+        Self {
+            sdf,
+            bounds,
+            epsilon,
+            max_steps,
+            material,
+        }
+    }
+
+    /// Outward normal from the central difference gradient of the field.
+    fn normal(&self, p: Point) -> Vec3 {
+        let h = self.epsilon;
+        let gradient = |axis: usize| {
+            let mut offset = Vec3::default();
+            offset[axis] = h;
+            self.sdf.distance(p + offset) - self.sdf.distance(p - offset)
+        };
+        unit_vector(Vec3::new(gradient(0), gradient(1), gradient(2)))
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (_, t_max) = self.bounds.hit(ray, t_min, t_max)?;
+        let ray_length = ray.direction().length();
+        let start = ray.at(t_min);
+        let start_distance = self.sdf.distance(start);
+        // March on the side of the surface the ray starts on. Rays leaving a
+        // surface start inside the epsilon band, pick the side they head to.
+        let side = if start_distance.abs() >= self.epsilon {
+            start_distance.signum()
+        } else if dot_product(&self.normal(start), &ray.direction()) > 0.0 {
+            1.0
+        } else {
+            -1.0
+        };
+
+        let mut t = t_min;
+        let mut left_surface = start_distance.abs() >= self.epsilon;
+        for _ in 0..self.max_steps {
+            if t > t_max {
+                return None;
+            }
+            let point = ray.at(t);
+            let distance = side * self.sdf.distance(point);
+            if distance < self.epsilon {
+                if left_surface {
+                    let outward_normal = self.normal(point);
+                    let mut record = HitRecord::new(point, outward_normal, t);
+                    record.set_face_normal(ray, &outward_normal);
+                    record.material = self.material.clone();
+                    return Some(record);
+                }
+                t += self.epsilon / ray_length;
+            } else {
+                left_surface = true;
+                t += distance / ray_length;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+}
+
+fn abs(v: Vec3) -> Vec3 {
+    Vec3::new(v.x().abs(), v.y().abs(), v.z().abs())
+}
+
+fn max(v: Vec3, m: f64) -> Vec3 {
+    Vec3::new(v.x().max(m), v.y().max(m), v.z().max(m))
+}
+
+fn max_component(v: Vec3) -> f64 {
+    v.x().max(v.y()).max(v.z())
+}
+
+/// Sphere centered at the origin.
+pub struct SdfSphere {
+    radius: f64,
+}
+
+impl SdfSphere {
+    pub fn new(radius: f64) -> Self {
+        Self { radius }
+    }
+}
+
+impl Sdf for SdfSphere {
+    fn distance(&self, p: Point) -> f64 {
+        p.length() - self.radius
+    }
+}
+
+/// Box centered at the origin.
+pub struct SdfBox {
+    half_extents: Vec3,
+}
+
+impl SdfBox {
+    pub fn new(half_extents: Vec3) -> Self {
+        Self { half_extents }
+    }
+}
+
+impl Sdf for SdfBox {
+    fn distance(&self, p: Point) -> f64 {
+        let q = abs(p) - self.half_extents;
+        max(q, 0.0).length() + max_component(q).min(0.0)
+    }
+}
+
+/// Box centered at the origin with edges rounded by `radius`. The rounding
+/// stays inside `half_extents`.
+pub struct RoundedBox {
+    half_extents: Vec3,
+    radius: f64,
+}
+
+impl RoundedBox {
+    pub fn new(half_extents: Vec3, radius: f64) -> Self {
+        Self {
+            half_extents,
+            radius,
+        }
+    }
+}
+
+impl Sdf for RoundedBox {
+    fn distance(&self, p: Point) -> f64 {
+        let inner = self.half_extents - Vec3::new(self.radius, self.radius, self.radius);
+        let q = abs(p) - inner;
+        max(q, 0.0).length() + max_component(q).min(0.0) - self.radius
+    }
+}
+
+/// Torus around the y axis.
+pub struct Torus {
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl Torus {
+    pub fn new(major_radius: f64, minor_radius: f64) -> Self {
+        Self {
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for Torus {
+    fn distance(&self, p: Point) -> f64 {
+        let ring = (p.x().powi(2) + p.z().powi(2)).sqrt() - self.major_radius;
+        (ring.powi(2) + p.y().powi(2)).sqrt() - self.minor_radius
+    }
+}
+
+/// Segment from `a` to `b` inflated by `radius`.
+pub struct Capsule {
+    a: Point,
+    b: Point,
+    radius: f64,
+}
+
+impl Capsule {
+    pub fn new(a: Point, b: Point, radius: f64) -> Self {
+        Self { a, b, radius }
+    }
+}
+
+impl Sdf for Capsule {
+    fn distance(&self, p: Point) -> f64 {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = (dot_product(&pa, &ba) / ba.length_squared()).clamp(0.0, 1.0);
+        (pa - ba * h).length() - self.radius
+    }
+}
+
+/// Moves a field by `offset`.
+pub struct Translate {
+    sdf: Box<dyn Sdf>,
+    offset: Vec3,
+}
+
+impl Translate {
+    pub fn new(sdf: Box<dyn Sdf>, offset: Vec3) -> Self {
+        Self { sdf, offset }
+    }
+}
+
+impl Sdf for Translate {
+    fn distance(&self, p: Point) -> f64 {
+        self.sdf.distance(p - self.offset)
+    }
+}
+
+/// Union of two fields blended over a distance of about `k`.
+pub struct SmoothUnion {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+    k: f64,
+}
+
+impl SmoothUnion {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>, k: f64) -> Self {
+        Self { a, b, k }
+    }
+}
+
+impl Sdf for SmoothUnion {
+    fn distance(&self, p: Point) -> f64 {
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+        if self.k <= 0.0 {
+            return d1.min(d2);
+        }
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 * (1.0 - h) + d1 * h - self.k * h * (1.0 - h)
+    }
+}
+
+/// Removes `b` from `a`.
+pub struct Subtraction {
+    a: Box<dyn Sdf>,
+    b: Box<dyn Sdf>,
+}
+
+impl Subtraction {
+    pub fn new(a: Box<dyn Sdf>, b: Box<dyn Sdf>) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Sdf for Subtraction {
+    fn distance(&self, p: Point) -> f64 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+}
+
+/// Repeats a field infinitely with the given `period` on each axis. A
+/// period of zero leaves that axis alone. The repeated shape should fit in
+/// one cell for the distance to stay a bound.
+pub struct Repeat {
+    sdf: Box<dyn Sdf>,
+    period: Vec3,
+}
+
+impl Repeat {
+    pub fn new(sdf: Box<dyn Sdf>, period: Vec3) -> Self {
+        Self { sdf, period }
+    }
+}
+
+impl Sdf for Repeat {
+    fn distance(&self, p: Point) -> f64 {
+        let mut q = p;
+        for a in 0..3 {
+            if self.period[a] > 0.0 {
+                q[a] = p[a] - self.period[a] * (p[a] / self.period[a]).round();
+            }
+        }
+        self.sdf.distance(q)
+    }
+}
+
+/// Twists a field around the y axis by `k` radians per unit of height.
+/// The shape must lie within `radius` of the axis.
+pub struct Twist {
+    sdf: Box<dyn Sdf>,
+    k: f64,
+    radius: f64,
+    /// Largest stretch of the twist within `radius` of the axis.
+    lipschitz: f64,
+}
+
+impl Twist {
+    pub fn new(sdf: Box<dyn Sdf>, k: f64, radius: f64) -> Self {
+        // The largest singular value of the twist's Jacobian at distance r
+        // from the axis is (kr + sqrt(k²r² + 4)) / 2, growing with r.
+        let kr = (k * radius).abs();
+        Self {
+            sdf,
+            k,
+            radius,
+            lipschitz: (kr + (kr * kr + 4.0).sqrt()) / 2.0,
+        }
+    }
+
+    fn twisted_distance(&self, p: Point) -> f64 {
+        let (s, c) = (self.k * p.y()).sin_cos();
+        let q = Point::new(c * p.x() - s * p.z(), p.y(), s * p.x() + c * p.z());
+        self.sdf.distance(q) / self.lipschitz
+    }
+}
+
+impl Sdf for Twist {
+    fn distance(&self, p: Point) -> f64 {
+        // Within the cylinder holding the shape the twist stretches space
+        // by at most `lipschitz`, which keeps the field a bound.
+        let r = (p.x().powi(2) + p.z().powi(2)).sqrt();
+        if r <= self.radius {
+            return self.twisted_distance(p);
+        }
+        // Outside of it the shape is at least as far as the wall, and as
+        // the nearest point of the wall, which lies closer to all of it.
+        let scale = self.radius / r;
+        let wall = Point::new(p.x() * scale, p.y(), p.z() * scale);
+        (r - self.radius).max(self.twisted_distance(wall))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, units::vec3::random_f64, PI};
+    use std::sync::Arc;
+
+    fn object(sdf: Box<dyn Sdf>) -> SdfObject {
+        let bounds = Aabb::new(Point::new(-5.0, -5.0, -5.0), Point::new(5.0, 5.0, 5.0));
+        SdfObject::new(sdf, bounds, 1e-6, 512, Arc::new(Lambertian::default()))
+    }
+
+    #[test]
+    fn box_distance() {
+        let b = SdfBox::new(Vec3::new(1.0, 1.0, 1.0));
+        assert!((b.distance(Point::new(3.0, 0.0, 0.0)) - 2.0).abs() < 1e-12);
+        assert!((b.distance(Point::new(0.5, 0.0, 0.0)) + 0.5).abs() < 1e-12);
+        assert!((b.distance(Point::new(2.0, 2.0, 1.0)) - 2.0_f64.sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sphere_traces_torus() {
+        let torus = object(Box::new(Torus::new(2.0, 0.5)));
+        let ray = Ray::new(Point::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let record = torus.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((record.t - 2.5).abs() < 1e-5);
+        assert!((record.normal - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-4);
+
+        let through_hole = Ray::new(Point::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(&through_hole, 0.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn rays_leaving_the_surface_find_the_far_side() {
+        let sphere = object(Box::new(SdfSphere::new(1.0)));
+        let ray = Ray::new(Point::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = sphere.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((record.t - 2.0).abs() < 1e-5);
+        assert!(!record.front_face);
+    }
+
+    /// Checks that `sdf` changes no faster than the distance between random
+    /// nearby points of `[-3, 3]^3`, which with the right zero set makes it
+    /// a bound on the distance to the surface.
+    fn assert_lipschitz(sdf: &dyn Sdf) {
+        let random_point = || {
+            Point::new(
+                6.0 * random_f64() - 3.0,
+                6.0 * random_f64() - 3.0,
+                6.0 * random_f64() - 3.0,
+            )
+        };
+        for _ in 0..20_000 {
+            let p = random_point();
+            let q = p + unit_vector(random_point()) * (0.1 * random_f64());
+            let change = (sdf.distance(p) - sdf.distance(q)).abs();
+            assert!(
+                change <= (p - q).length() * (1.0 + 1e-9) + 1e-12,
+                "{p:?} {q:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn twist_stays_a_distance_bound() {
+        // Twisted enough that the old local estimate overshoots.
+        let twist = Twist::new(
+            Box::new(SdfBox::new(Vec3::new(0.5, 2.0, 0.5))),
+            2.0,
+            0.5 * 2.0_f64.sqrt(),
+        );
+        assert_lipschitz(&twist);
+        assert!(twist.distance(Point::new(0.0, 1.0, 0.0)) < 0.0);
+        assert!(twist.distance(Point::new(2.0, 1.0, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn sphere_traces_twisted_box() {
+        // At height π / 6 the square cross section is turned by 30°, and
+        // its corner-free side meets the x axis at 0.5 / cos 30°.
+        let height = PI / 6.0;
+        let twist = object(Box::new(Twist::new(
+            Box::new(SdfBox::new(Vec3::new(0.5, 2.0, 0.5))),
+            1.0,
+            0.5 * 2.0_f64.sqrt(),
+        )));
+        let ray = Ray::new(Point::new(5.0, height, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let record = twist.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((record.t - (5.0 - 0.5 / (PI / 6.0).cos())).abs() < 1e-5);
+    }
+
+    #[test]
+    fn repeat_tiles_the_field() {
+        let repeat = Repeat::new(Box::new(SdfSphere::new(0.3)), Vec3::new(1.0, 0.0, 1.0));
+        let p = Point::new(0.2, 0.4, -0.1);
+        let tiled = p + Vec3::new(3.0, 0.0, -2.0);
+        assert!((repeat.distance(p) - repeat.distance(tiled)).abs() < 1e-12);
+        assert_lipschitz(&repeat);
+
+        let spheres = object(Box::new(repeat));
+        let along_row = Ray::new(Point::new(0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = spheres.hit(&along_row, 0.0, f64::INFINITY).unwrap();
+        assert!((record.t - 0.2).abs() < 1e-5);
+        let between_rows = Ray::new(Point::new(0.0, 0.0, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert!(spheres.hit(&between_rows, 0.0, 10.0).is_none());
+        // The tiling stops at the bounds.
+        let outside = Ray::new(Point::new(5.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(spheres.hit(&outside, 0.0, 10.0).is_none());
+    }
+
+    #[test]
+    fn smooth_union_blends_near_both_shapes() {
+        let spheres = |k: f64| {
+            SmoothUnion::new(
+                Box::new(Translate::new(
+                    Box::new(SdfSphere::new(1.0)),
+                    Vec3::new(-1.5, 0.0, 0.0),
+                )),
+                Box::new(Translate::new(
+                    Box::new(SdfSphere::new(1.0)),
+                    Vec3::new(1.5, 0.0, 0.0),
+                )),
+                k,
+            )
+        };
+        let between = Point::default();
+        assert!((spheres(0.0).distance(between) - 0.5).abs() < 1e-12);
+        // Halfway between equal shapes the blend takes off a quarter of k.
+        assert!((spheres(0.4).distance(between) - 0.4).abs() < 1e-12);
+        // Away from the other shape it's the plain distance.
+        assert!((spheres(0.4).distance(Point::new(3.0, 0.0, 0.0)) - 0.5).abs() < 1e-12);
+        assert_lipschitz(&spheres(0.4));
+    }
+
+    #[test]
+    fn subtraction_hollows_out() {
+        let shell = Subtraction::new(Box::new(SdfSphere::new(1.0)), Box::new(SdfSphere::new(0.5)));
+        assert!((shell.distance(Point::default()) - 0.5).abs() < 1e-12);
+        assert!((shell.distance(Point::new(0.75, 0.0, 0.0)) + 0.25).abs() < 1e-12);
+        assert_lipschitz(&shell);
+
+        // From the hollow the ray meets the carved surface facing it.
+        let shell = object(Box::new(shell));
+        let ray = Ray::new(Point::default(), Vec3::new(1.0, 0.0, 0.0));
+        let record = shell.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!((record.t - 0.5).abs() < 1e-5);
+        assert!(record.front_face);
+        assert!((record.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-4);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::{
+    aabb::Aabb,
+    degress_to_radies,
+    environment::EnvironmentMap,
+    light::{DirectionalLight, EmissionSample, Light, LightSample, SceneDisk},
+    ray::{HitRecord, Ray},
+    spectrum::xyz_to_rgb,
+    texture::ImageTexture,
+    units::{
+        color::Color,
+        point::Point,
+        vec3::{dot_product, unit_vector, Vec3},
+    },
+    PI,
+};
+
+/// Angular diameter of the sun, in degrees.
+const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+
+/// Luminance of the sun outside of the atmosphere, in kcd/m² like the sky.
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+/// Resolution of the table the sky is importance sampled with.
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+/// Daylight sky of Preetham, Shirley and Smits 1999, in kcd/m² scaled by an
+/// intensity. Above the horizon the radiance follows the analytic model,
+/// below it a diffuse ground of the given albedo reflects the sky and the
+/// sun. Add it to the lights together with `sun`, over a black background.
+#[derive(Clone, Debug)]
+pub struct PreethamSky {
+    to_sun: Vec3,
+    /// Perez coefficients A to E for Y, x and y.
+    perez: [[f64; 5]; 3],
+    /// Luminance Y and chromaticity x, y at the zenith, over the Perez
+    /// function there, so evaluating at a direction is one product.
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    ground: Color,
+    intensity: f64,
+    table: EnvironmentMap,
+    disk: SceneDisk,
+}
+
+impl PreethamSky {
+    /// Sun `elevation` above the horizon and `azimuth` from +x towards +z in
+    /// degrees. `turbidity` goes from about 2 for a clear sky to 10 for a
+    /// hazy one.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64, ground_albedo: Color) -> Self {
+        let elevation = degress_to_radies(elevation.clamp(0.0, 90.0));
+        let azimuth = degress_to_radies(azimuth);
+        let to_sun = Vec3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let theta_s = PI / 2.0 - elevation;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let cubic =
+            |c: [f64; 4]| c[0] * theta_s.powi(3) + c[1] * theta_s.powi(2) + c[2] * theta_s + c[3];
+        let zenith_x = t * t * cubic([0.00166, -0.00375, 0.00209, 0.0])
+            + t * cubic([-0.02903, 0.06377, -0.03202, 0.00394])
+            + cubic([0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y_chroma = t * t * cubic([0.00275, -0.00610, 0.00317, 0.0])
+            + t * cubic([-0.04214, 0.08970, -0.04153, 0.00516])
+            + cubic([0.15346, -0.26756, 0.06670, 0.26688]);
+        let zenith = [zenith_y.max(0.0), zenith_x, zenith_y_chroma];
+        let zenith = [0, 1, 2].map(|i| zenith[i] / perez_function(&perez[i], 1.0, theta_s.cos()));
+
+        let mut sky = Self {
+            to_sun,
+            perez,
+            zenith,
+            sun_radiance: sun_radiance(theta_s, t),
+            ground: Color::default(),
+            intensity: 1.0,
+            table: EnvironmentMap::new(ImageTexture::new(1, 1, vec![Color::new(1.0, 1.0, 1.0)])),
+            disk: SceneDisk::default(),
+        };
+
+        // Light reaching a horizontal ground from the sky and the sun.
+        let (nu, nv) = (64, 32);
+        let mut irradiance = Color::default();
+        for j in 0..nv {
+            let theta = 0.5 * PI * (j as f64 + 0.5) / nv as f64;
+            for i in 0..nu {
+                let phi = 2.0 * PI * (i as f64 + 0.5) / nu as f64;
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                let solid_angle = theta.sin() * (0.5 * PI / nv as f64) * (2.0 * PI / nu as f64);
+                irradiance += sky.sky_radiance(&direction) * (theta.cos() * solid_angle);
+            }
+        }
+        irradiance += sky.sun_irradiance() * elevation.sin();
+        sky.ground = ground_albedo * irradiance / PI;
+
+        let pixels = (0..TABLE_HEIGHT)
+            .flat_map(|j| (0..TABLE_WIDTH).map(move |i| (i, j)))
+            .map(|(i, j)| {
+                // Same layout as `EnvironmentMap`, up at the top.
+                let theta = PI * (j as f64 + 0.5) / TABLE_HEIGHT as f64;
+                let phi = 2.0 * PI * (i as f64 + 0.5) / TABLE_WIDTH as f64;
+                let direction = Vec3::new(
+                    -theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                );
+                sky.radiance(&direction)
+            })
+            .collect();
+        sky.table = EnvironmentMap::new(ImageTexture::new(TABLE_WIDTH, TABLE_HEIGHT, pixels));
+        sky
+    }
+
+    /// Scales the radiance of the sky and its sun.
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// The sun matching the sky, a disk of the sun's size in its direction,
+    /// dimmed by the atmosphere.
+    pub fn sun(&self) -> DirectionalLight {
+        DirectionalLight::new(-self.to_sun, self.sun_irradiance() * self.intensity)
+            .with_angular_diameter(SUN_ANGULAR_DIAMETER)
+    }
+
+    /// Radiance arriving from `direction`, travelling against it, without
+    /// the sun.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let direction = unit_vector(*direction);
+        let radiance = if direction.y() > 0.0 {
+            self.sky_radiance(&direction)
+        } else {
+            self.ground
+        };
+        radiance * self.intensity
+    }
+
+    fn sky_radiance(&self, direction: &Vec3) -> Color {
+        let cos_theta = direction.y().max(1e-3);
+        let cos_gamma = dot_product(direction, &self.to_sun).clamp(-1.0, 1.0);
+        let [luminance, x, y] = [0, 1, 2]
+            .map(|i| self.zenith[i] * perez_function(&self.perez[i], cos_theta, cos_gamma));
+        if y <= 0.0 {
+            return Color::default();
+        }
+        let rgb = xyz_to_rgb(Color::new(
+            x * luminance / y,
+            luminance,
+            (1.0 - x - y) * luminance / y,
+        ));
+        Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+    }
+
+    fn sun_irradiance(&self) -> Color {
+        let sin_theta_max = degress_to_radies(SUN_ANGULAR_DIAMETER / 2.0).sin();
+        self.sun_radiance * (PI * sin_theta_max * sin_theta_max)
+    }
+}
+
+/// Perez et al.'s sky luminance distribution for a direction `θ` from the
+/// zenith and `γ` from the sun.
+fn perez_function(c: &[f64; 5], cos_theta: f64, cos_gamma: f64) -> f64 {
+    let gamma = cos_gamma.clamp(-1.0, 1.0).acos();
+    (1.0 + c[0] * (c[1] / cos_theta).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+/// Radiance of the sun seen through the atmosphere, with Rayleigh and
+/// aerosol extinction per color channel.
+fn sun_radiance(theta_s: f64, turbidity: f64) -> Color {
+    let theta_degrees = theta_s.to_degrees();
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_degrees).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    // Wavelengths in micrometers standing for the three channels.
+    let transmittance = |lambda: f64| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+        rayleigh * aerosol
+    };
+    Color::new(
+        transmittance(0.61),
+        transmittance(0.55),
+        transmittance(0.465),
+    ) * SUN_LUMINANCE
+}
+
+impl Light for PreethamSky {
+    fn sample(&self, point: &Point, u: (f64, f64)) -> Option<LightSample> {
+        self.table.sample(point, u)
+    }
+
+    fn pdf(&self, point: &Point, direction: &Vec3) -> f64 {
+        self.table.pdf(point, direction)
+    }
+
+    fn escaped(&self, r: &Ray) -> Color {
+        self.radiance(&r.direction())
+    }
+
+    fn phi(&self) -> f64 {
+        // The table holds the radiance at unit intensity.
+        self.disk
+            .phi(self.table.radiance_integral() * self.intensity)
+    }
+
+    fn preprocess(&mut self, scene_bounds: &Aabb) {
+        self.disk = SceneDisk::new(scene_bounds);
+    }
+
+    fn sample_emission(&self, u: (f64, f64), v: (f64, f64)) -> Option<EmissionSample> {
+        self.disk.sample_emission(self, u, v)
+    }
+
+    fn emission(&self, _record: &HitRecord, direction: &Vec3) -> Color {
+        self.radiance(&-*direction)
+    }
+
+    fn emission_pdf(&self, record: &HitRecord, direction: &Vec3) -> (f64, f64) {
+        self.disk.emission_pdf(self, record, direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightest_towards_the_sun_and_blue_overhead() {
+        let sky = PreethamSky::new(30.0, 90.0, 3.0, Color::new(0.3, 0.3, 0.3));
+        let near_sun = sky.radiance(&Vec3::new(0.0, 0.6, 1.0));
+        let away = sky.radiance(&Vec3::new(0.0, 0.6, -1.0));
+        assert!(near_sun.y() > away.y());
+        let zenith = sky.radiance(&Vec3::new(0.0, 1.0, 0.0));
+        assert!(zenith.z() > zenith.x());
+        let ground = sky.radiance(&Vec3::new(0.0, -1.0, 0.0));
+        assert!(ground.x() > 0.0 && ground.x() < zenith.z() * 10.0);
+    }
+
+    #[test]
+    fn sun_matches_sky_direction_and_dims_near_the_horizon() {
+        let high = PreethamSky::new(60.0, 0.0, 3.0, Color::default());
+        let low = PreethamSky::new(5.0, 0.0, 3.0, Color::default());
+        let up = Ray::new(Point::default(), high.to_sun);
+        assert!(high.sun().escaped(&up).x() > 0.0);
+        let irradiance = |sky: &PreethamSky| match sky.sun().sample(&Point::default(), (0.5, 0.5)) {
+            Some(LightSample::Direction(d)) => {
+                sky.sun().escaped(&Ray::new(Point::default(), d))
+                    / sky.sun().pdf(&Point::default(), &d)
+            }
+            _ => Color::default(),
+        };
+        let (high, low) = (irradiance(&high), irradiance(&low));
+        assert!(high.z() > low.z());
+        // Low sun is reddened.
+        assert!(low.x() / low.z() > high.x() / high.z());
+    }
+
+    #[test]
+    fn importance_sampling_finds_the_sky() {
+        let sky = PreethamSky::new(40.0, 45.0, 4.0, Color::new(0.2, 0.2, 0.2));
+        for i in 0..32 {
+            let u = ((i as f64 * 0.618034) % 1.0, (i as f64 + 0.5) / 32.0);
+            if let Some(LightSample::Direction(d)) = sky.sample(&Point::default(), u) {
+                assert!(sky.pdf(&Point::default(), &d) > 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::sync::OnceLock;
+
+use crate::units::color::Color;
+
+/// Visible range sampled for spectral paths, in nanometers.
+pub const LAMBDA_MIN: f64 = 380.0;
+pub const LAMBDA_MAX: f64 = 780.0;
+
+/// Wavelength at which the usual single index of refraction is quoted,
+/// the sodium d-line.
+pub const LAMBDA_D: f64 = 587.6;
+
+/// Maps `u` in `[0, 1)` to a wavelength, uniformly over the visible range.
+pub fn sample_wavelength(u: f64) -> f64 {
+    LAMBDA_MIN + u * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+/// Piecewise gaussian used by the CIE matching function fit.
+fn lobe(lambda: f64, mu: f64, sigma_below: f64, sigma_above: f64) -> f64 {
+    let sigma = if lambda < mu {
+        sigma_below
+    } else {
+        sigma_above
+    };
+    (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+}
+
+/// CIE 1931 color matching functions, using the multi-lobe fit of Wyman,
+/// Sloan and Shirley 2013.
+pub fn cie_xyz(lambda: f64) -> Color {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    Color::new(x, y, z)
+}
+
+/// CIE XYZ to linear sRGB (D65 white).
+pub fn xyz_to_rgb(xyz: Color) -> Color {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+/// Linear sRGB response to a single wavelength. Saturated spectral colors
+/// fall outside of the gamut, negative channels are clipped.
+fn rgb_response(lambda: f64) -> Color {
+    let rgb = xyz_to_rgb(cie_xyz(lambda));
+    Color::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0))
+}
+
+/// Response of the film to an equal energy spectrum over the visible range.
+fn white_response() -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let steps = 4000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        (0..steps)
+            .map(|i| rgb_response(LAMBDA_MIN + (i as f64 + 0.5) * step) * step)
+            .fold(Color::default(), |acc, c| acc + c)
+    })
+}
+
+/// RGB weight of a path carried by a single wavelength sampled with
+/// `sample_wavelength`. Averaging the weights over the samples gives back
+/// white, so a white light split by a prism recombines to white.
+pub fn wavelength_to_rgb(lambda: f64) -> Color {
+    let white = white_response();
+    let rgb = rgb_response(lambda) * (LAMBDA_MAX - LAMBDA_MIN);
+    Color::new(
+        rgb.x() / white.x(),
+        rgb.y() / white.y(),
+        rgb.z() / white.z(),
+    )
+}
+
+/// Wavelengths a spectral path carries: the hero wavelength, which picks
+/// the directions the path takes, and companions spread evenly from it
+/// over the visible range.
+pub const WAVELENGTH_SAMPLES: usize = 4;
+
+/// Hero and companion wavelengths of a spectral path, each with the weight
+/// its spectral throughput has gathered. Companions are dropped, with a
+/// zero weight, once the path takes a direction only the hero could have.
+///
+/// RGB paths are spectrally white. The film records a spectral path as the
+/// average response to its wavelengths, so changes to the weights are
+/// turned into an RGB factor for the path throughput by `rescale`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Wavelengths {
+    lambda: [f64; WAVELENGTH_SAMPLES],
+    weight: [f64; WAVELENGTH_SAMPLES],
+}
+
+impl Wavelengths {
+    /// Hero wavelength at `u` in `[0, 1)`, companions stratified after it.
+    pub fn sample(u: f64) -> Self {
+        let lambda = std::array::from_fn(|i| {
+            sample_wavelength((u + i as f64 / WAVELENGTH_SAMPLES as f64).fract())
+        });
+        Self {
+            lambda,
+            weight: [1.0; WAVELENGTH_SAMPLES],
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// Wavelengths still carried by the path.
+    pub fn active(&self) -> impl Iterator<Item = f64> + '_ {
+        self.lambda
+            .iter()
+            .zip(&self.weight)
+            .filter(|(_, &w)| w > 0.0)
+            .map(|(&lambda, _)| lambda)
+    }
+
+    /// Whether only the hero is left.
+    pub fn is_single(&self) -> bool {
+        self.weight[1..].iter().all(|&w| w == 0.0)
+    }
+
+    /// Average of `f` over the wavelengths still carried.
+    pub fn mean(&self, f: impl Fn(f64) -> f64) -> f64 {
+        let (sum, count) = self.active().fold((0.0, 0), |(sum, count), lambda| {
+            (sum + f(lambda), count + 1)
+        });
+        if count == 0 {
+            0.0
+        } else {
+            sum / count as f64
+        }
+    }
+
+    /// Drops the companions, for a direction only the hero follows. The
+    /// hero then stands for all of them.
+    pub fn hero_only(&self) -> Self {
+        let mut weight = [0.0; WAVELENGTH_SAMPLES];
+        weight[0] = self.weight[0] * WAVELENGTH_SAMPLES as f64;
+        Self { weight, ..*self }
+    }
+
+    /// Scales the weight of every wavelength by `f`, returning the new
+    /// wavelengths and the factor the RGB throughput of a path carrying
+    /// `from` changes by. RGB paths come from spectrally white.
+    pub fn rescale(self, from: Option<&Wavelengths>, f: impl Fn(f64) -> f64) -> (Self, Color) {
+        let mut next = self;
+        for (lambda, weight) in next.lambda.iter().zip(next.weight.iter_mut()) {
+            if *weight > 0.0 {
+                *weight *= f(*lambda);
+            }
+        }
+        let before = from.map_or(Color::new(1.0, 1.0, 1.0), Wavelengths::response);
+        let after = next.response();
+        let ratio = |a: f64, b: f64| if b > 0.0 { a / b } else { 0.0 };
+        let factor = Color::new(
+            ratio(after.x(), before.x()),
+            ratio(after.y(), before.y()),
+            ratio(after.z(), before.z()),
+        );
+        (next, factor)
+    }
+
+    /// RGB the film records for the path: the average of the responses to
+    /// each wavelength, by its weight.
+    pub fn response(&self) -> Color {
+        self.lambda
+            .iter()
+            .zip(&self.weight)
+            .fold(Color::default(), |acc, (&lambda, &weight)| {
+                acc + wavelength_to_rgb(lambda) * weight
+            })
+            / WAVELENGTH_SAMPLES as f64
+    }
+}
+
+/// Wavelength dependent index of refraction. Coefficients take the
+/// wavelength in micrometers, as they are usually tabulated.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Dispersion {
+    /// `n = a + b / λ²`
+    Cauchy { a: f64, b: f64 },
+    /// `n² = 1 + Σ b λ² / (λ² - c)`
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    pub fn bk7() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        }
+    }
+
+    pub fn fused_silica() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.6961663, 0.4079426, 0.8974794],
+            c: [0.00467914826, 0.0135120631, 97.9340025],
+        }
+    }
+
+    pub fn diamond() -> Self {
+        Dispersion::Sellmeier {
+            b: [0.3306, 4.3356, 0.0],
+            c: [0.030625, 0.011236, 0.0],
+        }
+    }
+
+    pub fn water() -> Self {
+        Dispersion::Cauchy {
+            a: 1.324,
+            b: 0.003046,
+        }
+    }
+
+    /// Index of refraction at `lambda` nanometers.
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let micrometers = lambda / 1000.0;
+        let l2 = micrometers * micrometers;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                (1.0 + b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum::<f64>()).sqrt()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_wavelengths_average_to_white() {
+        let n = 10_000;
+        let sum = (0..n)
+            .map(|i| wavelength_to_rgb(sample_wavelength((i as f64 + 0.5) / n as f64)))
+            .fold(Color::default(), |acc, c| acc + c)
+            / n as f64;
+        assert!((sum - Color::new(1.0, 1.0, 1.0)).length() < 1e-3, "{sum}");
+    }
+
+    #[test]
+    fn hero_wavelengths_average_to_white() {
+        let n = 1000;
+        let sum = (0..n)
+            .map(|i| Wavelengths::sample((i as f64 + 0.5) / n as f64).response())
+            .fold(Color::default(), |acc, c| acc + c)
+            / n as f64;
+        assert!((sum - Color::new(1.0, 1.0, 1.0)).length() < 1e-3, "{sum}");
+    }
+
+    #[test]
+    fn companions_are_stratified() {
+        let wavelengths = Wavelengths::sample(0.9);
+        let mut lambda: Vec<f64> = wavelengths.active().collect();
+        assert_eq!(lambda[0], wavelengths.hero());
+        lambda.sort_by(f64::total_cmp);
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / WAVELENGTH_SAMPLES as f64;
+        for pair in lambda.windows(2) {
+            assert!((pair[1] - pair[0] - step).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn rescaling_tracks_the_response() {
+        let wavelengths = Wavelengths::sample(0.3);
+        let (hero, factor) = wavelengths.hero_only().rescale(Some(&wavelengths), |_| 1.0);
+        assert!(hero.is_single());
+        let expected = wavelengths.hero_only().response();
+        let response = wavelengths.response();
+        assert!((factor.x() * response.x() - expected.x()).abs() < 1e-9);
+        assert!((factor.y() * response.y() - expected.y()).abs() < 1e-9);
+        assert!((factor.z() * response.z() - expected.z()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sellmeier_matches_catalog() {
+        assert!((Dispersion::bk7().ior(LAMBDA_D) - 1.5168).abs() < 1e-4);
+        assert!((Dispersion::fused_silica().ior(LAMBDA_D) - 1.4585).abs() < 1e-4);
+        assert!((Dispersion::diamond().ior(LAMBDA_D) - 2.417).abs() < 2e-3);
+        assert!((Dispersion::water().ior(LAMBDA_D) - 1.333).abs() < 1e-3);
+        // Blue bends more than red.
+        assert!(Dispersion::bk7().ior(450.0) > Dispersion::bk7().ior(650.0));
+    }
+}
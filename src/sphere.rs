@@ -1,7 +1,16 @@
 use crate::{
+    aabb::Aabb,
+    light_sampler::LightBounds,
     material::Material,
-    ray::{HitRecord, Hittable, Ray},
-    units::{point::Point, vec3::dot_product},
+    onb::Onb,
+    ray::{HitRecord, Hittable, Interval, Ray},
+    sampling::{sample_uniform_cone, sample_uniform_sphere},
+    units::{
+        color::luminance,
+        point::Point,
+        vec3::{dot_product, Vec3},
+    },
+    INFINITY, PI,
 };
 
 pub struct Sphere {
@@ -18,6 +27,25 @@ impl Sphere {
             material,
         }
     }
+
+    /// Texture coordinates of a point on the unit sphere: `u` goes around the
+    /// y axis starting at -x, `v` goes from the bottom pole to the top.
+    pub fn uv(p: &Point) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// Cosine of the half angle of the cone the sphere fills seen from
+    /// `origin`, `None` from inside.
+    fn cos_theta_max(&self, origin: &Point) -> Option<f64> {
+        let distance_squared = (self.center - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return None;
+        }
+        Some((1.0 - radius_squared / distance_squared).sqrt())
+    }
 }
 
 impl Hittable for Sphere {
@@ -48,7 +76,95 @@ impl Hittable for Sphere {
         let outward_normal = (point - self.center) / self.radius;
         let mut record = HitRecord::new(point, normal, root);
         record.set_face_normal(ray, &outward_normal);
-        record.material = self.material;
+        (record.u, record.v) = Sphere::uv(&outward_normal);
+        record.material = self.material.clone();
         Some(record)
     }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let oc = ray.origin() - self.center;
+        let a = ray.direction().length_squared();
+        let half_b = dot_product(&oc, &(ray.direction()));
+        let c = oc.length_squared() - self.radius.powi(2);
+        let discriminant = half_b.powi(2) - a * c;
+
+        if discriminant <= 0.0 {
+            return vec![];
+        }
+
+        let sqrtd = discriminant.sqrt();
+        let boundary = |t: f64| {
+            let point = ray.at(t);
+            let outward_normal = (point - self.center) / self.radius;
+            let mut record = HitRecord::new(point, outward_normal, t);
+            (record.u, record.v) = Sphere::uv(&outward_normal);
+            record.material = self.material.clone();
+            record
+        };
+        vec![Interval {
+            enter: boundary((-half_b - sqrtd) / a),
+            exit: boundary((-half_b + sqrtd) / a),
+        }]
+    }
+
+    fn pdf_value(&self, origin: &Point, direction: &Vec3) -> f64 {
+        // Directions are sampled uniformly in the cone the sphere subtends.
+        let cos_theta_max = match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return 0.0,
+        };
+        if self
+            .hit(&Ray::new(*origin, *direction), 0.001, INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point, u: (f64, f64)) -> Vec3 {
+        let to_center = self.center - *origin;
+        match self.cos_theta_max(origin) {
+            Some(cos_theta_max) => {
+                Onb::build_from_w(&to_center).local_vec(&sample_uniform_cone(u, cos_theta_max))
+            }
+            None => to_center,
+        }
+    }
+
+    fn sample_surface(&self, u: (f64, f64)) -> Option<(HitRecord, f64)> {
+        let outward_normal = sample_uniform_sphere(u);
+        let point = self.center + self.radius * outward_normal;
+        let mut record = HitRecord::new(point, outward_normal, 0.0);
+        record.front_face = true;
+        (record.u, record.v) = Sphere::uv(&outward_normal);
+        record.material = self.material.clone();
+        Some((record, 1.0 / (4.0 * PI * self.radius * self.radius)))
+    }
+
+    fn surface_pdf(&self, point: &Point) -> f64 {
+        let distance = (*point - self.center).length();
+        if (distance - self.radius).abs() > 1e-6 * self.radius.max(1.0) {
+            return 0.0;
+        }
+        1.0 / (4.0 * PI * self.radius * self.radius)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+
+    fn light_bounds(&self) -> Option<LightBounds> {
+        // Diffuse emission outwards in every direction.
+        let area = 4.0 * PI * self.radius * self.radius;
+        Some(LightBounds::new(
+            self.bounding_box()?,
+            PI * area * luminance(self.material.average_emission()),
+            Vec3::new(0.0, 0.0, 1.0),
+            -1.0,
+            0.0,
+            false,
+        ))
+    }
 }
@@ -0,0 +1,432 @@
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::units::{color::Color, point::Point};
+
+/// Color varying over a surface, looked up by texture coordinates and by
+/// the hit point. Scalar parameters read the red channel.
+pub trait Texture: Send + Sync + Debug {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color;
+
+    fn scalar(&self, u: f64, v: f64, p: &Point) -> f64 {
+        self.value(u, v, p).x()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+
+    /// Constant texture for scalar parameters.
+    pub fn scalar(value: f64) -> Self {
+        Self::new(Color::new(value, value, value))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point) -> Color {
+        self.color
+    }
+}
+
+/// 3D checker pattern alternating between two textures every `scale`
+/// units of space.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl Checker {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self { scale, even, odd }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Point) -> Color {
+        let cell = |x: f64| (x / self.scale).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+/// Bitmap looked up by texture coordinates, `v` going up the image.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    /// `pixels` are linear colors, row by row from the top of the image.
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0, "image must not be empty");
+        assert_eq!(pixels.len(), width * height, "pixels do not match size");
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Loads a `P3` or `P6` PPM image, like the ones this renderer writes,
+    /// undoing the gamma applied by `write_color`.
+    pub fn load_ppm<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_ppm(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_ppm<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents)?;
+
+        // Header tokens are whitespace separated, with `#` comments.
+        let mut position = 0;
+        let mut next_token = || -> io::Result<String> {
+            loop {
+                while position < contents.len() && contents[position].is_ascii_whitespace() {
+                    position += 1;
+                }
+                if position < contents.len() && contents[position] == b'#' {
+                    while position < contents.len() && contents[position] != b'\n' {
+                        position += 1;
+                    }
+                    continue;
+                }
+                break;
+            }
+            let start = position;
+            while position < contents.len() && !contents[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if start == position {
+                return Err(invalid("truncated PPM image"));
+            }
+            Ok(String::from_utf8_lossy(&contents[start..position]).into_owned())
+        };
+        let number = |token: String| {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid number in PPM image"))
+        };
+
+        let magic = next_token()?;
+        let width = number(next_token()?)?;
+        let height = number(next_token()?)?;
+        let max_value = number(next_token()?)?;
+        if max_value == 0 || max_value > 255 {
+            return Err(invalid("unsupported PPM maximum value"));
+        }
+        if width == 0 || height == 0 {
+            return Err(invalid("empty PPM image"));
+        }
+        let count = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(3))
+            .ok_or_else(|| invalid("PPM image is too large"))?;
+        let samples: Vec<usize> = match magic.as_str() {
+            "P3" => (0..count)
+                .map(|_| next_token().and_then(number))
+                .collect::<io::Result<_>>()?,
+            "P6" => {
+                // A single whitespace byte separates the header from the data.
+                let start = position + 1;
+                let data = start
+                    .checked_add(count)
+                    .and_then(|end| contents.get(start..end))
+                    .ok_or_else(|| invalid("truncated PPM image"))?;
+                data.iter().map(|&b| b as usize).collect()
+            }
+            _ => return Err(invalid("not a PPM image")),
+        };
+
+        let decode = |s: usize| (s as f64 / max_value as f64).powi(2);
+        let pixels = samples
+            .chunks_exact(3)
+            .map(|rgb| Color::new(decode(rgb[0]), decode(rgb[1]), decode(rgb[2])))
+            .collect();
+        Ok(Self::new(width, height, pixels))
+    }
+
+    /// Loads a Radiance `.hdr` image, flat or run length encoded.
+    pub fn load_hdr<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_hdr(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_hdr<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("#?") {
+            return Err(invalid("not a Radiance HDR image"));
+        }
+        // Header variables up to an empty line, then the resolution.
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid("truncated HDR image"));
+            }
+            let variable = line.trim();
+            if variable.is_empty() {
+                break;
+            }
+            if let Some(format) = variable.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(invalid("unsupported HDR pixel format"));
+                }
+            }
+        }
+        line.clear();
+        reader.read_line(&mut line)?;
+        let resolution: Vec<&str> = line.split_whitespace().collect();
+        let (height, width) = match resolution.as_slice() {
+            ["-Y", height, "+X", width] => (height.parse::<usize>(), width.parse::<usize>()),
+            _ => return Err(invalid("unsupported HDR image orientation")),
+        };
+        let (height, width) = match (height, width) {
+            (Ok(height), Ok(width)) => (height, width),
+            _ => return Err(invalid("invalid HDR image resolution")),
+        };
+        if width == 0 || height == 0 {
+            return Err(invalid("empty HDR image"));
+        }
+
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        // Runs pack at most 127 pixels of a component in two bytes, an image
+        // promising more pixels than that can't be backed by its data.
+        let count = width
+            .checked_mul(height)
+            .filter(|&count| count / 16 <= data.len())
+            .ok_or_else(|| invalid("HDR image is larger than its data"))?;
+        let mut position = 0;
+        let mut next = || -> io::Result<u8> {
+            let byte = *data
+                .get(position)
+                .ok_or_else(|| invalid("truncated HDR image"))?;
+            position += 1;
+            Ok(byte)
+        };
+
+        let mut pixels = Vec::with_capacity(count);
+        let mut scanline = vec![[0u8; 4]; width];
+        for _ in 0..height {
+            let header = [next()?, next()?, next()?, next()?];
+            let encoded = (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2;
+            if encoded {
+                if ((header[2] as usize) << 8 | header[3] as usize) != width {
+                    return Err(invalid("HDR scanline width mismatch"));
+                }
+                // Each of the four components is run length encoded in turn.
+                for component in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = next()? as usize;
+                        let (run, count) = if count > 128 {
+                            (true, count - 128)
+                        } else {
+                            (false, count)
+                        };
+                        if count == 0 || x + count > width {
+                            return Err(invalid("corrupt HDR scanline"));
+                        }
+                        let value = if run { next()? } else { 0 };
+                        for pixel in &mut scanline[x..x + count] {
+                            pixel[component] = if run { value } else { next()? };
+                        }
+                        x += count;
+                    }
+                }
+            } else {
+                scanline[0] = header;
+                for pixel in scanline.iter_mut().skip(1) {
+                    *pixel = [next()?, next()?, next()?, next()?];
+                }
+            }
+            pixels.extend(scanline.iter().map(|&[r, g, b, e]| {
+                if e == 0 {
+                    return Color::default();
+                }
+                let scale = 2f64.powi(e as i32 - 136);
+                Color::new(r as f64 * scale, g as f64 * scale, b as f64 * scale)
+            }));
+        }
+        Ok(Self::new(width, height, pixels))
+    }
+
+    /// Loads a Portable Float Map, in color (`PF`) or grey (`Pf`).
+    pub fn load_pfm<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_pfm(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_pfm<R: BufRead>(mut reader: R) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut header = String::new();
+        // Magic, dimensions and scale are three whitespace separated lines.
+        for _ in 0..3 {
+            if reader.read_line(&mut header)? == 0 {
+                return Err(invalid("truncated PFM image"));
+            }
+        }
+        let tokens: Vec<&str> = header.split_whitespace().collect();
+        let channels = match tokens.first() {
+            Some(&"PF") => 3,
+            Some(&"Pf") => 1,
+            _ => return Err(invalid("not a PFM image")),
+        };
+        let dimension = |index: usize| {
+            tokens
+                .get(index)
+                .and_then(|token| token.parse::<usize>().ok())
+                .filter(|&n| n > 0)
+                .ok_or_else(|| invalid("invalid PFM dimensions"))
+        };
+        let (width, height) = (dimension(1)?, dimension(2)?);
+        let scale = tokens
+            .get(3)
+            .and_then(|token| token.parse::<f64>().ok())
+            .ok_or_else(|| invalid("invalid PFM scale"))?;
+        let little_endian = scale < 0.0;
+
+        let size = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(channels * 4))
+            .ok_or_else(|| invalid("PFM image is too large"))?;
+        let mut data = vec![];
+        reader.take(size as u64).read_to_end(&mut data)?;
+        if data.len() < size {
+            return Err(invalid("truncated PFM image"));
+        }
+        let floats: Vec<f64> = data
+            .chunks_exact(4)
+            .map(|b| {
+                let bytes = [b[0], b[1], b[2], b[3]];
+                if little_endian {
+                    f32::from_le_bytes(bytes) as f64
+                } else {
+                    f32::from_be_bytes(bytes) as f64
+                }
+            })
+            .collect();
+        // Rows are stored from the bottom of the image up.
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in floats.chunks_exact(width * channels).rev() {
+            pixels.extend(row.chunks_exact(channels).map(|c| match c {
+                [r, g, b] => Color::new(*r, *g, *b),
+                [v] => Color::new(*v, *v, *v),
+                _ => unreachable!(),
+            }));
+        }
+        Ok(Self::new(width, height, pixels))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixel in column `i` and row `j`, counting from the top left.
+    pub fn pixel(&self, i: usize, j: usize) -> Color {
+        self.pixels[j * self.width + i]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point) -> Color {
+        if self.pixels.is_empty() {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.pixels[j * self.width + i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_empty_and_oversized_ppm() {
+        let huge = format!("P6 {} 2 255\n", usize::MAX);
+        for ppm in ["P3 0 2 255\n", huge.as_str(), "P6 4 4 255\n\0\0\0"] {
+            let error = ImageTexture::read_ppm(ppm.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn reads_flat_and_encoded_hdr() {
+        let mut flat = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        flat.extend([128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = ImageTexture::read_hdr(flat.as_slice()).unwrap();
+        assert_eq!(image.pixel(0, 0), Color::new(1.0, 0.5, 0.0));
+        assert_eq!(image.pixel(1, 0), Color::default());
+
+        // Eight pixels of (1, 0.5, 0) stored as runs, one component at a time.
+        let mut encoded = b"#?RADIANCE\n\n-Y 1 +X 8\n".to_vec();
+        encoded.extend([2, 2, 0, 8]);
+        encoded.extend([128 + 8, 128, 128 + 8, 64, 128 + 8, 0, 128 + 8, 129]);
+        let image = ImageTexture::read_hdr(encoded.as_slice()).unwrap();
+        assert_eq!(image.width(), 8);
+        assert_eq!(image.pixel(7, 0), Color::new(1.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_hdr() {
+        for resolution in ["-Y 0 +X 0", "-Y 4000000000 +X 4000000000"] {
+            let mut hdr = format!("#?RADIANCE\n\n{resolution}\n").into_bytes();
+            hdr.extend([128, 64, 0, 129]);
+            let error = ImageTexture::read_hdr(hdr.as_slice()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_pfm() {
+        let huge = format!("PF\n{} 2\n-1.0\n", usize::MAX);
+        for pfm in [
+            "PF\n0 4\n-1.0\n",
+            huge.as_str(),
+            "Pf\n100000 100000\n-1.0\n",
+        ] {
+            let error = ImageTexture::read_pfm(pfm.as_bytes()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn reads_pfm_bottom_up() {
+        let mut pfm = b"Pf\n1 2\n-1.0\n".to_vec();
+        pfm.extend(0.25f32.to_le_bytes());
+        pfm.extend(4.0f32.to_le_bytes());
+        let image = ImageTexture::read_pfm(pfm.as_slice()).unwrap();
+        assert_eq!(image.pixel(0, 0), Color::new(4.0, 4.0, 4.0));
+        assert_eq!(image.pixel(0, 1), Color::new(0.25, 0.25, 0.25));
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    ops::{Add, Div, Mul, Sub},
+    sync::Arc,
+};
+
+use crate::{
+    ray::HitRecord,
+    spectrum::{wavelength_to_rgb, LAMBDA_MAX, LAMBDA_MIN},
+    texture::{SolidColor, Texture},
+    units::color::Color,
+    PI,
+};
+
+/// Wavelengths the reflectance is integrated over for RGB paths.
+const SPECTRAL_SAMPLES: usize = 32;
+
+/// Thin transparent film on top of a surface, like soap, oil or an anodized
+/// oxide layer. Light reflected at its two sides interferes, coloring the
+/// reflection depending on the film thickness and the viewing angle.
+#[derive(Clone, Debug)]
+pub struct ThinFilm {
+    thickness: Arc<dyn Texture>,
+    ior: f64,
+}
+
+impl ThinFilm {
+    /// Film with index of refraction `ior` whose thickness in nanometers is
+    /// read from the scalar channel of a texture.
+    pub fn new(thickness: Arc<dyn Texture>, ior: f64) -> Self {
+        Self { thickness, ior }
+    }
+    pub fn uniform(thickness: f64, ior: f64) -> Self {
+        Self::new(Arc::new(SolidColor::scalar(thickness)), ior)
+    }
+
+    /// Reflectance of the film at `rec` between a medium of index `n_outer`,
+    /// where the light arrives from with `cos_theta` to the normal, and a
+    /// base of complex index `eta + i k` given per color channel, with the
+    /// spectrum converted to RGB.
+    pub fn reflectance(
+        &self,
+        rec: &HitRecord,
+        cos_theta: f64,
+        n_outer: f64,
+        eta: Color,
+        k: Color,
+    ) -> Color {
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / SPECTRAL_SAMPLES as f64;
+        let rgb = (0..SPECTRAL_SAMPLES)
+            .map(|i| {
+                let lambda = LAMBDA_MIN + (i as f64 + 0.5) * step;
+                wavelength_to_rgb(lambda)
+                    * self.reflectance_at(rec, cos_theta, n_outer, eta, k, lambda)
+            })
+            .fold(Color::default(), |acc, c| acc + c)
+            / SPECTRAL_SAMPLES as f64;
+        Color::new(rgb.x().min(1.0), rgb.y().min(1.0), rgb.z().min(1.0))
+    }
+
+    /// Reflectance as in `reflectance`, at the single wavelength `lambda`
+    /// nanometers carried by a spectral path.
+    pub fn reflectance_at(
+        &self,
+        rec: &HitRecord,
+        cos_theta: f64,
+        n_outer: f64,
+        eta: Color,
+        k: Color,
+        lambda: f64,
+    ) -> f64 {
+        let thickness = self.thickness.scalar(rec.u, rec.v, &rec.point).max(0.0);
+        // Conductors are only known at the three primaries; blend them with
+        // the film response to the wavelength.
+        let weights = wavelength_to_rgb(lambda);
+        let total = weights.x() + weights.y() + weights.z();
+        let blend = |c: Color| {
+            if total > 0.0 {
+                (c.x() * weights.x() + c.y() * weights.y() + c.z() * weights.z()) / total
+            } else {
+                c.y()
+            }
+        };
+        let base = Complex::new(blend(eta), blend(k));
+        airy_reflectance(cos_theta, n_outer, self.ior, base, thickness, lambda)
+    }
+}
+
+/// Reflectance of a film of index `n_film` and `thickness` nanometers
+/// between a medium of index `n_outer` and a base of complex index `n_base`,
+/// for unpolarized light of wavelength `lambda` nanometers arriving with
+/// `cos_theta` to the normal. Sums every internal reflection in closed form
+/// with the Airy formula, per polarization.
+pub fn airy_reflectance(
+    cos_theta: f64,
+    n_outer: f64,
+    n_film: f64,
+    n_base: Complex,
+    thickness: f64,
+    lambda: f64,
+) -> f64 {
+    let n1 = Complex::real(n_outer);
+    let n2 = Complex::real(n_film);
+    let cos1 = Complex::real(cos_theta.clamp(0.0, 1.0));
+    let sin2 = Complex::real(1.0 - cos_theta * cos_theta);
+    let refracted_cos = |n: Complex| {
+        let ratio = n1 / n;
+        (Complex::real(1.0) - ratio * ratio * sin2).sqrt()
+    };
+    let cos2 = refracted_cos(n2);
+    let cos3 = refracted_cos(n_base);
+
+    // Phase difference between successive reflections off the base.
+    let delta = Complex::real(4.0 * PI * thickness / lambda) * n2 * cos2;
+    let phase = (Complex::new(0.0, 1.0) * delta).exp();
+
+    let airy = |r12: Complex, r23: Complex| {
+        let r = (r12 + r23 * phase) / (Complex::real(1.0) + r12 * r23 * phase);
+        r.norm_sqr()
+    };
+    let rs = airy(
+        fresnel_s(n1, cos1, n2, cos2),
+        fresnel_s(n2, cos2, n_base, cos3),
+    );
+    let rp = airy(
+        fresnel_p(n1, cos1, n2, cos2),
+        fresnel_p(n2, cos2, n_base, cos3),
+    );
+    (0.5 * (rs + rp)).clamp(0.0, 1.0)
+}
+
+/// Amplitude reflection coefficient for light polarized perpendicular to
+/// the plane of incidence.
+fn fresnel_s(n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex) -> Complex {
+    (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t)
+}
+
+/// Amplitude reflection coefficient for light polarized parallel to the
+/// plane of incidence.
+fn fresnel_p(n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex) -> Complex {
+    (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t)
+}
+
+/// Complex number, for indices of refraction of absorbing media and wave
+/// amplitudes.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+    pub fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+    pub fn norm_sqr(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+    /// Principal square root, with a non negative real part.
+    pub fn sqrt(self) -> Self {
+        let r = self.norm_sqr().sqrt();
+        let re = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+    pub fn exp(self) -> Self {
+        let scale = self.re.exp();
+        Self::new(scale * self.im.cos(), scale * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        let denominator = rhs.norm_sqr();
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fresnel::{fresnel_conductor, fresnel_dielectric};
+
+    #[test]
+    fn vanishing_film_is_plain_fresnel() {
+        for cos_theta in [1.0, 0.7, 0.2] {
+            let r = airy_reflectance(cos_theta, 1.0, 1.33, Complex::real(1.5), 0.0, 550.0);
+            assert!((r - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-9);
+
+            let gold = Complex::new(0.143119, 3.98316);
+            let r = airy_reflectance(cos_theta, 1.0, 1.8, gold, 0.0, 550.0);
+            let expected = fresnel_conductor(
+                cos_theta,
+                Color::new(gold.re, gold.re, gold.re),
+                Color::new(gold.im, gold.im, gold.im),
+            );
+            assert!((r - expected.x()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn quarter_wave_coating_cancels_reflection() {
+        // A film of index sqrt(1.5) and a quarter wavelength thick is the
+        // classic anti reflection coating on glass.
+        let n_film = 1.5_f64.sqrt();
+        let thickness = 550.0 / (4.0 * n_film);
+        let r = airy_reflectance(1.0, 1.0, n_film, Complex::real(1.5), thickness, 550.0);
+        assert!(r < 1e-9);
+        // A half wave film has no effect at normal incidence.
+        let r = airy_reflectance(1.0, 1.0, n_film, Complex::real(1.5), 2.0 * thickness, 550.0);
+        assert!((r - fresnel_dielectric(1.0, 1.5)).abs() < 1e-9);
+    }
+}
@@ -20,6 +20,11 @@ pub fn write_color(pixel_color: Color, samples_per_pixel: i64) {
     );
 }
 
+/// Luminance of a linear Rec. 709 color.
+pub fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     if x < min {
         min
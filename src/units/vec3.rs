@@ -54,7 +54,7 @@ pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
     let neg_uv = *uv * -1.0;
     let cos_theta = dot_product(&neg_uv, n).min(1.0);
     let r_out_perp = etai_over_etat * (*uv + *n * cos_theta);
-    let r_out_parallel = *n * ((1.0 - r_out_perp.length_squared()).abs().sqrt() * -1.0);
+    let r_out_parallel = *n * -(1.0 - r_out_perp.length_squared()).abs().sqrt();
     r_out_perp + r_out_parallel
 }
 